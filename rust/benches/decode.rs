@@ -0,0 +1,68 @@
+//! Decompress + JSON parse throughput for a TRADES-sized block.
+//!
+//! Run with `cargo bench --bench decode`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use hyperliquid_grpc::common::{decompress, decompress_bytes, decompress_into};
+
+const COINS: [&str; 4] = ["BTC", "ETH", "SOL", "HYPE"];
+
+/// A block of `n` trades, shaped like the TRADES stream payload.
+fn sample_block(n: usize) -> Vec<u8> {
+    let trades: Vec<serde_json::Value> = (0..n)
+        .map(|i| {
+            serde_json::json!({
+                "coin": COINS[i % COINS.len()],
+                "side": if i % 2 == 0 { "B" } else { "A" },
+                "px": format!("{}.{}", 60_000 + i, i % 10),
+                "sz": format!("0.{:04}", i * 7 % 10_000),
+                "time": 1_714_560_000_000u64 + i as u64,
+                "hash": format!("0x{:064x}", i * 2_654_435_761),
+                "tid": 900_000_000_000u64 + i as u64,
+                "users": [format!("0x{:040x}", i), format!("0x{:040x}", i + 1)],
+            })
+        })
+        .collect();
+    let json = serde_json::to_vec(&trades).unwrap();
+    zstd::encode_all(json.as_slice(), 3).unwrap()
+}
+
+fn decode(c: &mut Criterion) {
+    let block = sample_block(500);
+    let raw_len = decompress_bytes(&block).unwrap().len();
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(raw_len as u64));
+
+    group.bench_function("decompress_string_from_str", |b| {
+        b.iter(|| {
+            let text = decompress(&block).unwrap();
+            serde_json::from_str::<serde_json::Value>(&text).unwrap()
+        })
+    });
+    group.bench_function("decompress_bytes_from_slice", |b| {
+        b.iter(|| {
+            let bytes = decompress_bytes(&block).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        })
+    });
+    group.bench_function("decompress_into_reused", |b| {
+        let mut scratch = Vec::new();
+        b.iter(|| {
+            decompress_into(&block, &mut scratch).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&scratch).unwrap()
+        })
+    });
+    group.bench_function("decompress_into_reused_from_str", |b| {
+        let mut scratch = Vec::new();
+        b.iter(|| {
+            decompress_into(&block, &mut scratch).unwrap();
+            let text = std::str::from_utf8(&scratch).unwrap();
+            serde_json::from_str::<serde_json::Value>(text).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
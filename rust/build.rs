@@ -1,5 +1,14 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("../proto/hyperliquid.proto")?;
-    tonic_build::compile_protos("../proto/orderbook.proto")?;
+    // Both protos share the `hyperliquid` package, so they must be compiled
+    // together or the second would overwrite the first's generated module.
+    // The descriptor set is embedded for --list-streams, which falls back
+    // to it when the server doesn't support reflection.
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("hyperliquid_descriptor.bin"))
+        .compile(
+            &["../proto/hyperliquid.proto", "../proto/orderbook.proto"],
+            &["../proto"],
+        )?;
     Ok(())
 }
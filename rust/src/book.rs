@@ -0,0 +1,481 @@
+//! Depth reconstruction for the BOOK_UPDATES stream.
+//!
+//! BOOK_UPDATES records are per-order deltas in the node's raw book diff
+//! format:
+//!
+//! ```json
+//! {"user": "0x..", "oid": 123, "coin": "BTC", "side": "B", "px": "97000.0",
+//!  "raw_book_diff": {"new": {"sz": "0.5"}}}
+//! ```
+//!
+//! `raw_book_diff` drives the change:
+//! - `{"new": {"sz": S}}` inserts order `oid` at `px` on `side` with size `S`
+//! - `{"update": {"origSz": A, "newSz": B}}` resizes order `oid` to `B`
+//! - `"remove"` deletes order `oid` (filled or cancelled)
+//!
+//! Orders are aggregated into price levels per coin. BOOK_UPDATES carries no
+//! snapshot, so there the book only reflects orders added since
+//! subscribing. The L4 stream's `book_diffs` use the same format and start
+//! from a full snapshot ([`DepthBook::load_snapshot`]), so there an update or
+//! removal for an order that was never seen means a diff was missed; such
+//! oids are ignored but collected for [`DepthBook::take_unknown_oids`].
+//!
+//! Sizes are summed as [`Decimal`]s so levels don't drift as orders come
+//! and go (see [`crate::price`]).
+//!
+//! [`order_changes`] reads an L4 diff the other way round, as a list of the
+//! orders it added, removed or resized, for display.
+//!
+//! [`book_checksum`] computes the CRC32 an L2 update may carry, to check
+//! the book arrived intact.
+
+use std::collections::{HashMap, HashSet};
+
+use rust_decimal::Decimal;
+use serde_json::Value;
+
+use crate::hyperliquid::{L2Level, L4BookSnapshot};
+use crate::price::{parse_px, parse_value};
+use crate::records::{field_str, split_records};
+
+/// Levels per side covered by [`book_checksum`]
+pub const CHECKSUM_DEPTH: usize = 25;
+
+/// CRC32 over the top [`CHECKSUM_DEPTH`] levels of an L2 book, in the
+/// layout `L2BookUpdate.checksum` is defined with in `orderbook.proto`.
+///
+/// That is the layout OKX documents for its order book channel checksum:
+/// levels interleaved best-first as `bid1, ask1, bid2, ask2, ...`, each
+/// rendered as `px:sz` from the strings as sent, and joined with `:`. A
+/// side that runs out of levels stops contributing. The CRC is the
+/// standard CRC-32 (IEEE), as `zlib.crc32` computes it; OKX reports it as
+/// a signed 32-bit integer, the same bits as this unsigned one.
+pub fn book_checksum(bids: &[L2Level], asks: &[L2Level]) -> u32 {
+    let mut parts = Vec::with_capacity(CHECKSUM_DEPTH * 4);
+    for i in 0..CHECKSUM_DEPTH {
+        if let Some(bid) = bids.get(i) {
+            parts.push(bid.px.as_str());
+            parts.push(bid.sz.as_str());
+        }
+        if let Some(ask) = asks.get(i) {
+            parts.push(ask.px.as_str());
+            parts.push(ask.sz.as_str());
+        }
+    }
+    crc32fast::hash(parts.join(":").as_bytes())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+impl Side {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "B" => Some(Side::Bid),
+            "A" => Some(Side::Ask),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RestingOrder {
+    side: Side,
+    px: String,
+    sz: Decimal,
+}
+
+/// Aggregated price level
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub px: String,
+    pub sz: Decimal,
+    pub n: usize,
+}
+
+#[derive(Debug, Default)]
+struct CoinBook {
+    orders: HashMap<u64, RestingOrder>,
+    /// px -> (total size, order count)
+    bids: HashMap<String, (Decimal, usize)>,
+    asks: HashMap<String, (Decimal, usize)>,
+}
+
+impl CoinBook {
+    fn levels_mut(&mut self, side: Side) -> &mut HashMap<String, (Decimal, usize)> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+
+    fn insert(&mut self, oid: u64, order: RestingOrder) {
+        // A repeated `new` for a live oid replaces it
+        self.remove(oid);
+        let level = self
+            .levels_mut(order.side)
+            .entry(order.px.clone())
+            .or_default();
+        level.0 += order.sz;
+        level.1 += 1;
+        self.orders.insert(oid, order);
+    }
+
+    /// Returns false if `oid` isn't in the book.
+    fn resize(&mut self, oid: u64, new_sz: Decimal) -> bool {
+        let Some(order) = self.orders.get_mut(&oid) else {
+            return false;
+        };
+        let delta = new_sz - order.sz;
+        order.sz = new_sz;
+        let (side, px) = (order.side, order.px.clone());
+        if let Some(level) = self.levels_mut(side).get_mut(&px) {
+            level.0 += delta;
+        }
+        true
+    }
+
+    /// Returns false if `oid` isn't in the book.
+    fn remove(&mut self, oid: u64) -> bool {
+        let Some(order) = self.orders.remove(&oid) else {
+            return false;
+        };
+        let levels = self.levels_mut(order.side);
+        if let Some(level) = levels.get_mut(&order.px) {
+            level.0 -= order.sz;
+            level.1 -= 1;
+            if level.1 == 0 {
+                levels.remove(&order.px);
+            }
+        }
+        true
+    }
+}
+
+/// Per-coin depth maintained from BOOK_UPDATES deltas.
+#[derive(Debug, Default)]
+pub struct DepthBook {
+    coins: HashMap<String, CoinBook>,
+    /// Oids of updates and removals that matched no resting order
+    unknown_oids: Vec<u64>,
+}
+
+impl DepthBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply every delta in a decoded BOOK_UPDATES payload, returning the
+    /// coins whose books changed (sorted).
+    pub fn apply_payload(&mut self, payload: &Value) -> Vec<String> {
+        let mut touched = HashSet::new();
+        for record in split_records(payload) {
+            if let Some(coin) = self.apply(record) {
+                touched.insert(coin);
+            }
+        }
+        let mut touched: Vec<String> = touched.into_iter().collect();
+        touched.sort();
+        touched
+    }
+
+    /// Apply one delta. Returns the coin it applied to, or `None` if the
+    /// record isn't a recognizable book diff.
+    pub fn apply(&mut self, record: &Value) -> Option<String> {
+        let coin = record.get("coin")?.as_str()?;
+        let oid = record.get("oid")?.as_u64()?;
+        let diff = record.get("raw_book_diff")?;
+        let book = self.coins.entry(coin.to_string()).or_default();
+
+        let known = match diff {
+            Value::String(s) if s == "remove" => book.remove(oid),
+            Value::Object(obj) => {
+                if let Some(new) = obj.get("new") {
+                    let side = Side::parse(record.get("side")?.as_str()?)?;
+                    let px = record.get("px")?.as_str()?.to_string();
+                    let sz = parse_value(new.get("sz")?)?;
+                    book.insert(oid, RestingOrder { side, px, sz });
+                    true
+                } else if let Some(update) = obj.get("update") {
+                    book.resize(oid, parse_value(update.get("newSz")?)?)
+                } else {
+                    return None;
+                }
+            }
+            _ => return None,
+        };
+        if !known {
+            self.unknown_oids.push(oid);
+        }
+
+        Some(coin.to_string())
+    }
+
+    /// Replace `snapshot.coin`'s book with the orders in an L4 snapshot.
+    /// Orders with an unparseable side or size are skipped.
+    pub fn load_snapshot(&mut self, snapshot: &L4BookSnapshot) {
+        let mut book = CoinBook::default();
+        for order in snapshot.bids.iter().chain(&snapshot.asks) {
+            let (Some(side), Ok(sz)) = (Side::parse(&order.side), parse_px(&order.sz)) else {
+                continue;
+            };
+            book.insert(
+                order.oid,
+                RestingOrder {
+                    side,
+                    px: order.limit_px.clone(),
+                    sz,
+                },
+            );
+        }
+        self.coins.insert(snapshot.coin.clone(), book);
+    }
+
+    /// Oids of updates and removals applied since the last call that
+    /// referenced an order not in the book.
+    pub fn take_unknown_oids(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.unknown_oids)
+    }
+
+    /// Number of resting orders tracked for `coin`.
+    pub fn order_count(&self, coin: &str) -> usize {
+        self.coins.get(coin).map_or(0, |book| book.orders.len())
+    }
+
+    /// Best `n` levels on `side` for `coin`, best first.
+    pub fn top_levels(&self, coin: &str, side: Side, n: usize) -> Vec<Level> {
+        let Some(book) = self.coins.get(coin) else {
+            return Vec::new();
+        };
+        let levels = match side {
+            Side::Bid => &book.bids,
+            Side::Ask => &book.asks,
+        };
+
+        let mut levels: Vec<(Decimal, Level)> = levels
+            .iter()
+            .filter_map(|(px, &(sz, n))| {
+                let key = parse_px(px).ok()?;
+                Some((
+                    key,
+                    Level {
+                        px: px.clone(),
+                        sz: sz.normalize(),
+                        n,
+                    },
+                ))
+            })
+            .collect();
+        levels.sort_by(|a, b| match side {
+            Side::Bid => b.0.cmp(&a.0),
+            Side::Ask => a.0.cmp(&b.0),
+        });
+        levels.into_iter().take(n).map(|(_, level)| level).collect()
+    }
+}
+
+/// How an L4 diff changed an order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderChangeKind {
+    /// Placed and resting (`+`)
+    New,
+    /// Filled or cancelled (`-`)
+    Removed,
+    /// Resized in place, e.g. partially filled (`~`)
+    Modified,
+}
+
+impl OrderChangeKind {
+    pub fn symbol(self) -> char {
+        match self {
+            OrderChangeKind::New => '+',
+            OrderChangeKind::Removed => '-',
+            OrderChangeKind::Modified => '~',
+        }
+    }
+}
+
+/// One order an L4 diff touched, with its fields as sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderChange {
+    pub kind: OrderChangeKind,
+    pub oid: u64,
+    pub side: String,
+    pub px: String,
+    /// Size after the change (what was left, for a removal)
+    pub sz: String,
+    /// The order status, e.g. `open`, `filled`, `canceled`; `update` for a
+    /// resize
+    pub status: String,
+    /// The order's owner, when the diff names one
+    pub user: Option<String>,
+}
+
+/// The orders an L4 diff (`{"order_statuses": [..], "book_diffs": [..]}`)
+/// added, removed or resized, in that order.
+///
+/// Each order status is classified by its `status`: `open` is new, `filled`
+/// and any cancellation (`canceled`, `marginCanceled`, ...) are removals.
+/// Other statuses (rejections, triggers) never touched the book and are
+/// left out. Book diffs that resize an order not already listed are
+/// modifications.
+pub fn order_changes(diff: &Value) -> Vec<OrderChange> {
+    let text = |value: Option<&Value>| {
+        value
+            .and_then(crate::records::value_str)
+            .unwrap_or_default()
+    };
+    let mut changes: Vec<OrderChange> = Vec::new();
+
+    for status in diff["order_statuses"].as_array().into_iter().flatten() {
+        let order = &status["order"];
+        let (Some(name), Some(oid)) = (status["status"].as_str(), order["oid"].as_u64()) else {
+            continue;
+        };
+        let kind = match name {
+            "open" => OrderChangeKind::New,
+            "filled" => OrderChangeKind::Removed,
+            name if name.to_ascii_lowercase().ends_with("canceled") => OrderChangeKind::Removed,
+            _ => continue,
+        };
+        changes.push(OrderChange {
+            kind,
+            oid,
+            side: text(order.get("side")),
+            px: text(order.get("limitPx")),
+            sz: text(order.get("sz")),
+            status: name.to_string(),
+            user: field_str(status, "user"),
+        });
+    }
+
+    for record in split_records(&diff["book_diffs"]) {
+        let (Some(oid), Some(new_sz)) = (
+            record["oid"].as_u64(),
+            record["raw_book_diff"]["update"].get("newSz"),
+        ) else {
+            continue;
+        };
+        if changes.iter().any(|change| change.oid == oid) {
+            continue;
+        }
+        changes.push(OrderChange {
+            kind: OrderChangeKind::Modified,
+            oid,
+            side: text(record.get("side")),
+            px: text(record.get("px")),
+            sz: text(Some(new_sz)),
+            status: "update".to_string(),
+            user: field_str(record, "user"),
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<L2Level> {
+        levels
+            .iter()
+            .map(|&(px, sz)| L2Level {
+                px: px.to_string(),
+                sz: sz.to_string(),
+                n: 1,
+            })
+            .collect()
+    }
+
+    // Expected values are zlib.crc32 of the joined string in each comment
+
+    #[test]
+    fn checksum_interleaves_bids_and_asks() {
+        // "3366.1:7:3366.8:9:3366:6:3368:8"
+        let bids = levels(&[("3366.1", "7"), ("3366", "6")]);
+        let asks = levels(&[("3366.8", "9"), ("3368", "8")]);
+        assert_eq!(book_checksum(&bids, &asks), 2413953002);
+    }
+
+    #[test]
+    fn checksum_skips_a_side_that_runs_out() {
+        // "10:1:11:2:12:3"
+        let bids = levels(&[("10", "1")]);
+        let asks = levels(&[("11", "2"), ("12", "3")]);
+        assert_eq!(book_checksum(&bids, &asks), 2737570776);
+    }
+
+    #[test]
+    fn checksum_covers_only_the_top_levels() {
+        // "100:1:101:1:99:1:102:1:...", 25 levels a side
+        let px: Vec<_> = (0..30)
+            .map(|i| ((100 - i).to_string(), (101 + i).to_string()))
+            .collect();
+        let bids: Vec<_> = px.iter().map(|(bid, _)| (bid.as_str(), "1")).collect();
+        let asks: Vec<_> = px.iter().map(|(_, ask)| (ask.as_str(), "1")).collect();
+        assert_eq!(book_checksum(&levels(&bids), &levels(&asks)), 3112440833);
+    }
+
+    #[test]
+    fn order_changes_classify_each_order() {
+        let diff = serde_json::json!({
+            "order_statuses": [
+                { "status": "open", "user": "0xa",
+                  "order": { "oid": 1, "side": "B", "limitPx": "97000", "sz": "0.5" } },
+                { "status": "filled",
+                  "order": { "oid": 2, "side": "A", "limitPx": "97001", "sz": "0.0" } },
+                { "status": "canceled",
+                  "order": { "oid": 3, "side": "A", "limitPx": "97002", "sz": "1.0" } },
+                { "status": "marginCanceled",
+                  "order": { "oid": 4, "side": "B", "limitPx": "96999", "sz": "2.0" } },
+                // Never rested, so never touched the book
+                { "status": "perpMarginRejected",
+                  "order": { "oid": 5, "side": "B", "limitPx": "96000", "sz": "1.0" } },
+            ],
+            "book_diffs": [
+                // Already listed as new
+                { "oid": 1, "side": "B", "px": "97000",
+                  "raw_book_diff": { "new": { "sz": "0.5" } } },
+                { "oid": 6, "side": "A", "px": "97003", "user": "0xb",
+                  "raw_book_diff": { "update": { "origSz": "3.0", "newSz": "2.5" } } },
+                { "oid": 2, "side": "A", "px": "97001", "raw_book_diff": "remove" },
+            ],
+        });
+
+        let changes = order_changes(&diff);
+        let summary: Vec<(u64, char, &str, &str, &str)> = changes
+            .iter()
+            .map(|c| {
+                (
+                    c.oid,
+                    c.kind.symbol(),
+                    c.side.as_str(),
+                    c.px.as_str(),
+                    c.sz.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, '+', "B", "97000", "0.5"),
+                (2, '-', "A", "97001", "0.0"),
+                (3, '-', "A", "97002", "1.0"),
+                (4, '-', "B", "96999", "2.0"),
+                (6, '~', "A", "97003", "2.5"),
+            ]
+        );
+        assert_eq!(changes[0].user.as_deref(), Some("0xa"));
+        assert_eq!(changes[1].status, "filled");
+        assert_eq!(changes[4].status, "update");
+        assert_eq!(changes[4].user.as_deref(), Some("0xb"));
+    }
+
+    #[test]
+    fn order_changes_of_an_empty_diff() {
+        assert!(order_changes(&serde_json::json!({})).is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+//! A bounded queue between the raw stream's read loop and its outputs.
+//!
+//! Without it, a slow sink (a remote Postgres, a congested WebSocket
+//! client) stalls the read loop directly, and the only symptom is the
+//! server's flow control. With it, the reader runs ahead by up to the
+//! buffer's capacity, and a full buffer is reported as it happens, which
+//! points at the sinks rather than the network. When full, the reader
+//! either waits for room ([`OnFull::Block`]) or discards the oldest queued
+//! message ([`OnFull::Drop`]), counting each one.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// Minimum time between "buffer full" warnings
+const FULL_WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What [`Sender::send`] does when the buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OnFull {
+    /// Wait for the outputs to make room, slowing the read loop down
+    #[default]
+    Block,
+    /// Discard the oldest queued message to make room
+    Drop,
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    capacity: usize,
+    /// Signalled when an item is queued or the sender is gone
+    readable: Notify,
+    /// Signalled when an item is taken or the receiver is gone
+    writable: Notify,
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+/// A buffer of `capacity` items (at least 1).
+pub fn channel<T>(capacity: usize, on_full: OnFull) -> (Sender<T>, Receiver<T>) {
+    let capacity = capacity.max(1);
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            items: VecDeque::with_capacity(capacity),
+            closed: false,
+        }),
+        capacity,
+        readable: Notify::new(),
+        writable: Notify::new(),
+    });
+    let sender = Sender {
+        shared: shared.clone(),
+        on_full,
+        peak: 0,
+        full_count: 0,
+        dropped: 0,
+        last_warned: None,
+    };
+    (sender, Receiver { shared })
+}
+
+/// The read loop's end of the buffer. Dropping it lets the receiver drain
+/// what is queued and then end.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    on_full: OnFull,
+    /// Most items queued at once
+    peak: usize,
+    /// Sends that found the buffer full
+    full_count: u64,
+    dropped: u64,
+    last_warned: Option<Instant>,
+}
+
+/// The receiver is gone, so nothing more can be delivered.
+#[derive(Debug)]
+pub struct Closed;
+
+impl<T> Sender<T> {
+    /// Queue `item`, waiting for room or discarding the oldest item when
+    /// the buffer is full (see [`OnFull`]).
+    pub async fn send(&mut self, item: T) -> Result<(), Closed> {
+        let shared = self.shared.clone();
+        let mut item = Some(item);
+        let mut waited = false;
+        loop {
+            {
+                let mut queue = shared.queue.lock().unwrap();
+                if queue.closed {
+                    return Err(Closed);
+                }
+                let full = queue.items.len() >= shared.capacity;
+                if full && !waited {
+                    self.full();
+                }
+                if full && self.on_full == OnFull::Drop {
+                    queue.items.pop_front();
+                    self.dropped += 1;
+                }
+                if !full || self.on_full == OnFull::Drop {
+                    queue.items.extend(item.take());
+                    self.peak = self.peak.max(queue.items.len());
+                    drop(queue);
+                    shared.readable.notify_one();
+                    return Ok(());
+                }
+            }
+            waited = true;
+            shared.writable.notified().await;
+        }
+    }
+
+    fn full(&mut self) {
+        self.full_count += 1;
+        if self
+            .last_warned
+            .is_none_or(|at| at.elapsed() >= FULL_WARN_INTERVAL)
+        {
+            self.last_warned = Some(Instant::now());
+            warn!(
+                "Output buffer full ({} messages): the outputs are not keeping up with the stream{}",
+                self.shared.capacity,
+                match self.on_full {
+                    OnFull::Block => "; reading is paused until they catch up",
+                    OnFull::Drop => "; dropping the oldest messages",
+                }
+            );
+        }
+    }
+
+    /// Log how full the buffer got and what was dropped.
+    pub fn report(&self) {
+        info!(
+            "Output buffer: peak {} of {} message(s), full {} time(s), {} message(s) dropped",
+            self.peak, self.shared.capacity, self.full_count, self.dropped
+        );
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.readable.notify_one();
+    }
+}
+
+/// The outputs' end of the buffer.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// The next item, or `None` once the sender is gone and the buffer is
+    /// drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut queue = self.shared.queue.lock().unwrap();
+                if let Some(item) = queue.items.pop_front() {
+                    drop(queue);
+                    self.shared.writable.notify_one();
+                    return Some(item);
+                }
+                if queue.closed {
+                    return None;
+                }
+            }
+            self.shared.readable.notified().await;
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.writable.notify_one();
+    }
+}
@@ -0,0 +1,284 @@
+//! OHLCV candles aggregated from the TRADES stream.
+//!
+//! Trades are bucketed per coin by their own `time` (falling back to the
+//! message timestamp) into fixed intervals aligned to the unix epoch. Trades
+//! can arrive slightly out of order, so a bucket stays open until a trade
+//! at least `grace` past its end has been seen; later trades for it are
+//! counted as late and dropped. Each closed bar is printed as one NDJSON
+//! line, and bars still open when the stream stops are printed on flush
+//! with `"complete": false`.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::price::parse_value;
+use crate::records::split_records;
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+/// Default time a bucket stays open past its end for late trades
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(2);
+
+/// Parse a candle interval: a whole number of seconds, minutes, hours or
+/// days (`1s`, `15s`, `1m`, `5m`, `1h`, `1d`).
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let unit_at = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in {:?} (use s, m, h or d)", value))?;
+    let (count, unit) = value.split_at(unit_at);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("invalid interval {:?}", value))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        _ => return Err(format!("unknown unit {:?} (use s, m, h or d)", unit)),
+    };
+    if count == 0 {
+        return Err("interval must be at least 1".to_string());
+    }
+    Ok(Duration::from_secs(count * unit_secs))
+}
+
+/// `interval` in the largest unit that divides it, e.g. `5m`.
+fn interval_label(interval: Duration) -> String {
+    let secs = interval.as_secs();
+    match secs {
+        s if s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s % 3_600 == 0 => format!("{}h", s / 3_600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// One coin's bar for one bucket.
+#[derive(Debug, Clone, PartialEq)]
+struct Bar {
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+    trades: u64,
+    /// Times of the trades that set `open` and `close`
+    first_time: u64,
+    last_time: u64,
+}
+
+impl Bar {
+    fn new(time: u64, px: Decimal, sz: Decimal) -> Self {
+        Self {
+            open: px,
+            high: px,
+            low: px,
+            close: px,
+            volume: sz,
+            trades: 1,
+            first_time: time,
+            last_time: time,
+        }
+    }
+
+    /// Add a trade. Open and close follow trade time, not arrival order.
+    fn add(&mut self, time: u64, px: Decimal, sz: Decimal) {
+        self.high = self.high.max(px);
+        self.low = self.low.min(px);
+        self.volume += sz;
+        self.trades += 1;
+        if time < self.first_time {
+            self.first_time = time;
+            self.open = px;
+        }
+        if time >= self.last_time {
+            self.last_time = time;
+            self.close = px;
+        }
+    }
+}
+
+/// Aggregates trades into bars, printing each as it closes.
+pub struct CandleSink {
+    interval_ms: u64,
+    grace_ms: u64,
+    label: String,
+    /// Open bars by (bucket start, coin)
+    open: BTreeMap<(u64, String), Bar>,
+    /// Latest trade time seen. Buckets ending `grace_ms` or more before it
+    /// are closed
+    watermark: u64,
+    late: u64,
+}
+
+impl CandleSink {
+    pub fn new(interval: Duration, grace: Duration) -> Self {
+        Self {
+            interval_ms: (interval.as_millis() as u64).max(1),
+            grace_ms: grace.as_millis() as u64,
+            label: interval_label(interval),
+            open: BTreeMap::new(),
+            watermark: 0,
+            late: 0,
+        }
+    }
+
+    /// Add one trade, returning the bars it closed, oldest first.
+    pub fn add_trade(&mut self, coin: &str, time: u64, px: Decimal, sz: Decimal) -> Vec<Value> {
+        let start = time - time % self.interval_ms;
+        if start + self.interval_ms <= self.watermark.saturating_sub(self.grace_ms) {
+            self.late += 1;
+            debug!("Dropping late {} trade at {} for a closed bar", coin, time);
+            return Vec::new();
+        }
+        match self.open.get_mut(&(start, coin.to_string())) {
+            Some(bar) => bar.add(time, px, sz),
+            None => {
+                self.open
+                    .insert((start, coin.to_string()), Bar::new(time, px, sz));
+            }
+        }
+
+        self.watermark = self.watermark.max(time);
+        let close_before = self.watermark.saturating_sub(self.grace_ms);
+        let mut closed = Vec::new();
+        while let Some(entry) = self.open.first_entry() {
+            let bucket_start = entry.key().0;
+            if bucket_start + self.interval_ms > close_before {
+                break;
+            }
+            let ((start, coin), bar) = entry.remove_entry();
+            closed.push(self.bar_json(start, &coin, &bar, true));
+        }
+        closed
+    }
+
+    /// Every bar still open, oldest first, marked incomplete.
+    pub fn drain(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.open)
+            .into_iter()
+            .map(|((start, coin), bar)| self.bar_json(start, &coin, &bar, false))
+            .collect()
+    }
+
+    fn bar_json(&self, start: u64, coin: &str, bar: &Bar, complete: bool) -> Value {
+        let text = |value: Decimal| value.normalize().to_string();
+        json!({
+            "coin": coin,
+            "interval": self.label,
+            "start": start,
+            "end": start + self.interval_ms,
+            "open": text(bar.open),
+            "high": text(bar.high),
+            "low": text(bar.low),
+            "close": text(bar.close),
+            "volume": text(bar.volume),
+            "trades": bar.trades,
+            "complete": complete,
+        })
+    }
+}
+
+#[async_trait]
+impl RecordSink for CandleSink {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        for trade in split_records(record.payload) {
+            let (Some(coin), Some(px), Some(sz)) = (
+                trade.get("coin").and_then(Value::as_str),
+                trade.get("px").and_then(parse_value),
+                trade.get("sz").and_then(parse_value),
+            ) else {
+                continue;
+            };
+            let time = trade
+                .get("time")
+                .and_then(Value::as_u64)
+                .unwrap_or(record.timestamp);
+            for bar in self.add_trade(coin, time, px, sz) {
+                println!("{}", bar);
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        for bar in self.drain() {
+            println!("{}", bar);
+        }
+        if self.late > 0 {
+            info!(
+                "Dropped {} trade(s) that arrived after their {} bar closed",
+                self.late, self.label
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(sink: &mut CandleSink, time: u64, px: i64, sz: &str) -> Vec<Value> {
+        let sz = sz.parse().unwrap();
+        sink.add_trade("BTC", time, Decimal::from(px), sz)
+    }
+
+    #[test]
+    fn trades_across_two_buckets_make_two_bars() {
+        let mut sink = CandleSink::new(Duration::from_secs(60), DEFAULT_GRACE);
+
+        assert!(add(&mut sink, 1_000, 100, "1").is_empty());
+        assert!(add(&mut sink, 30_000, 105, "2").is_empty());
+        // Out of order but before the last trade: neither open nor close
+        assert!(add(&mut sink, 20_000, 95, "0.5").is_empty());
+        assert!(add(&mut sink, 59_000, 101, "1").is_empty());
+        // The next bucket starts, but the first stays open for the grace
+        assert!(add(&mut sink, 61_000, 102, "1").is_empty());
+        // Late within the grace, and the latest trade of its bucket
+        assert!(add(&mut sink, 59_500, 100, "1").is_empty());
+        assert!(add(&mut sink, 61_500, 99, "1").is_empty());
+
+        let closed = add(&mut sink, 62_500, 103, "1");
+        assert_eq!(
+            closed,
+            vec![json!({
+                "coin": "BTC", "interval": "1m", "start": 0, "end": 60_000,
+                "open": "100", "high": "105", "low": "95", "close": "100",
+                "volume": "5.5", "trades": 5, "complete": true,
+            })]
+        );
+
+        // Past the grace: the first bar is closed, so this is dropped
+        assert!(add(&mut sink, 59_900, 200, "1").is_empty());
+        assert_eq!(sink.late, 1);
+
+        assert_eq!(
+            sink.drain(),
+            vec![json!({
+                "coin": "BTC", "interval": "1m", "start": 60_000, "end": 120_000,
+                "open": "102", "high": "103", "low": "99", "close": "103",
+                "volume": "3", "trades": 3, "complete": false,
+            })]
+        );
+        assert!(sink.drain().is_empty());
+    }
+
+    #[test]
+    fn intervals_parse_and_label() {
+        assert_eq!(parse_interval("15s"), Ok(Duration::from_secs(15)));
+        assert_eq!(parse_interval("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_interval("1d"), Ok(Duration::from_secs(86_400)));
+        assert!(parse_interval("5").is_err());
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("2w").is_err());
+        assert_eq!(interval_label(Duration::from_secs(7_200)), "2h");
+        assert_eq!(interval_label(Duration::from_secs(90)), "90s");
+    }
+}
@@ -0,0 +1,235 @@
+//! Persist the raw stream's position so a restarted collector resumes where
+//! it stopped.
+//!
+//! The state file is a small JSON object: the stream type, the last block
+//! whose messages were fully handed to every sink, and the hashes of that
+//! block's records (see [`ReplayDedup`](crate::dedup::ReplayDedup)). On
+//! restart the stream subscribes from that block again and the hashes drop
+//! the records already written, as they do across a reconnect. A crash can
+//! still repeat whatever was written after the last save.
+//!
+//! Writes go to a temporary file that is then renamed over the state file,
+//! so a crash mid-write leaves the previous state intact.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::common::write_atomic;
+use crate::hyperliquid::StreamType;
+use crate::Result;
+
+/// How often the state file is rewritten while streaming
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The position recorded in a state file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub stream_type: String,
+    pub block_number: u64,
+    pub record_hashes: HashSet<u64>,
+}
+
+/// Read the state file at `path`, or `None` if it doesn't exist yet.
+pub fn load(path: &Path) -> Result<Option<State>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("cannot read state file {}: {}", path.display(), e).into()),
+    };
+    let value: Value = serde_json::from_str(&text)
+        .map_err(|e| format!("invalid state file {}: {}", path.display(), e))?;
+    let block_number = value
+        .get("block_number")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| format!("state file {} has no block_number", path.display()))?;
+    Ok(Some(State {
+        stream_type: value
+            .get("stream_type")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        block_number,
+        record_hashes: value
+            .get("record_hashes")
+            .and_then(Value::as_array)
+            .map(|hashes| hashes.iter().filter_map(Value::as_u64).collect())
+            .unwrap_or_default(),
+    }))
+}
+
+/// The block to subscribe from on startup: the state file's block, unless
+/// `start_block` overrides it or there's no state file yet. A state file
+/// written for another stream type is an error.
+pub fn resume_from(
+    path: &Path,
+    stream_type: StreamType,
+    start_block: Option<u64>,
+) -> Result<Option<u64>> {
+    let Some(state) = load(path)? else {
+        return Ok(start_block);
+    };
+    if state.stream_type != stream_type.as_str_name() {
+        return Err(format!(
+            "state file {} is for the {} stream, not {}",
+            path.display(),
+            state.stream_type,
+            stream_type.as_str_name()
+        )
+        .into());
+    }
+    if start_block.is_some() {
+        warn!(
+            "--start-block given, ignoring block {} from the state file",
+            state.block_number
+        );
+        return Ok(start_block);
+    }
+    info!(
+        "Resuming from block {} (state file {})",
+        state.block_number,
+        path.display()
+    );
+    Ok(Some(state.block_number))
+}
+
+/// Write `state` to `path` atomically: a sibling temporary file is written,
+/// synced and renamed over `path`.
+pub fn save(path: &Path, state: &State) -> Result<()> {
+    let mut hashes: Vec<u64> = state.record_hashes.iter().copied().collect();
+    hashes.sort_unstable();
+    let body = json!({
+        "stream_type": state.stream_type,
+        "block_number": state.block_number,
+        "record_hashes": hashes,
+    });
+
+    write_atomic(path, body.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// Saves the stream's position to a state file every [`SAVE_INTERVAL`] and
+/// when the stream stops.
+pub struct Checkpoint {
+    path: PathBuf,
+    stream_type: StreamType,
+    /// Latest position, not yet saved if `dirty`
+    latest: Option<(u64, HashSet<u64>)>,
+    dirty: bool,
+    last_save: Instant,
+}
+
+impl Checkpoint {
+    pub fn new(path: PathBuf, stream_type: StreamType) -> Self {
+        Self {
+            path,
+            stream_type,
+            latest: None,
+            dirty: false,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// Note that every message up to `block_number` has been handled, with
+    /// `hashes` the records of that block seen so far. Saves if
+    /// [`SAVE_INTERVAL`] has passed; a failed save is logged and retried
+    /// next time rather than stopping the stream.
+    pub fn record(&mut self, block_number: u64, hashes: &HashSet<u64>) {
+        match &mut self.latest {
+            Some((block, seen)) if *block == block_number => seen.clone_from(hashes),
+            latest => *latest = Some((block_number, hashes.clone())),
+        }
+        self.dirty = true;
+        if self.last_save.elapsed() >= SAVE_INTERVAL {
+            if let Err(e) = self.save() {
+                warn!("Failed to save state file {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    /// Save the latest position now, if it changed since the last save.
+    pub fn save(&mut self) -> Result<()> {
+        self.last_save = Instant::now();
+        let Some((block_number, hashes)) = &self.latest else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        save(
+            &self.path,
+            &State {
+                stream_type: self.stream_type.as_str_name().to_string(),
+                block_number: *block_number,
+                record_hashes: hashes.clone(),
+            },
+        )?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_at(dir: &Path, block_number: u64) -> PathBuf {
+        let path = dir.join("state.json");
+        let mut checkpoint = Checkpoint::new(path.clone(), StreamType::Trades);
+        checkpoint.record(block_number, &HashSet::from([7, 11]));
+        checkpoint.save().unwrap();
+        path
+    }
+
+    #[test]
+    fn a_restart_resumes_from_the_saved_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = saved_at(dir.path(), 105);
+
+        let state = load(&path).unwrap().unwrap();
+        assert_eq!(state.block_number, 105);
+        assert_eq!(state.record_hashes, HashSet::from([7, 11]));
+        assert_eq!(
+            resume_from(&path, StreamType::Trades, None).unwrap(),
+            Some(105)
+        );
+    }
+
+    #[test]
+    fn start_block_overrides_the_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = saved_at(dir.path(), 105);
+
+        assert_eq!(
+            resume_from(&path, StreamType::Trades, Some(7)).unwrap(),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn a_missing_state_file_keeps_start_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        assert_eq!(resume_from(&path, StreamType::Trades, None).unwrap(), None);
+        assert_eq!(
+            resume_from(&path, StreamType::Trades, Some(7)).unwrap(),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn a_state_file_for_another_stream_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = saved_at(dir.path(), 105);
+
+        let err = resume_from(&path, StreamType::Orders, None).unwrap_err();
+        assert!(
+            err.to_string().contains("is for the TRADES stream"),
+            "{err}"
+        );
+    }
+}
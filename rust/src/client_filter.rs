@@ -0,0 +1,336 @@
+//! Client-side record filters for conditions the server can't express.
+//!
+//! Server-side filters only match top-level field values, so "trades larger
+//! than X" or "writer actions whose `action.type` is Y" has to be checked
+//! here, after decompression. These reduce what is printed and sunk, but the
+//! full filtered stream is still received.
+
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::records::{field_str, retain_records, value_str};
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientFilter {
+    /// Keep records whose `sz` is at least this
+    pub min_size: Option<f64>,
+    /// Keep records whose `side` is this (`B` or `A`)
+    pub side: Option<String>,
+    /// Keep records whose `user`, or one of whose `users`, is this address.
+    /// Compared case-insensitively
+    pub user: Option<String>,
+    /// Keep records matching every one of these
+    pub paths: Vec<PathFilter>,
+}
+
+impl ClientFilter {
+    pub fn is_empty(&self) -> bool {
+        self.min_size.is_none()
+            && self.side.is_none()
+            && self.user.is_none()
+            && self.paths.is_empty()
+    }
+
+    /// Whether `record` passes every filter. A record missing a filtered
+    /// field (or with an unparseable `sz`) doesn't.
+    pub fn matches(&self, record: &Value) -> bool {
+        if let Some(min_size) = self.min_size {
+            let size = field_str(record, "sz").and_then(|sz| sz.parse::<f64>().ok());
+            if !size.is_some_and(|size| size >= min_size) {
+                return false;
+            }
+        }
+        if let Some(side) = &self.side {
+            if field_str(record, "side").as_deref() != Some(side.as_str()) {
+                return false;
+            }
+        }
+        if let Some(user) = &self.user {
+            if !has_user(record, user) {
+                return false;
+            }
+        }
+        self.paths.iter().all(|path| path.matches(record))
+    }
+
+    /// Drop the records of `payload` that don't match, returning how many
+    /// are left.
+    pub fn apply(&self, payload: &mut Value) -> usize {
+        retain_records(payload, |record| self.matches(record))
+    }
+}
+
+/// Whether `record`'s `user`, or any entry of its `users` array (a trade
+/// lists both sides there), is `user`, ignoring case.
+pub fn has_user(record: &Value, user: &str) -> bool {
+    let is_user = |value: &Value| value.as_str().is_some_and(|v| v.eq_ignore_ascii_case(user));
+    record.get("user").is_some_and(is_user)
+        || record
+            .get("users")
+            .and_then(Value::as_array)
+            .is_some_and(|users| users.iter().any(is_user))
+}
+
+/// A user address as the streams write it: trimmed, lowercase and
+/// `0x`-prefixed. Doesn't check that it is one; see [`is_hex_address`].
+pub fn normalize_address(address: &str) -> String {
+    let address = address.trim().to_ascii_lowercase();
+    match address.strip_prefix("0x") {
+        Some(_) => address,
+        None => format!("0x{}", address),
+    }
+}
+
+/// Whether `user` is a `0x`-prefixed, 20-byte hex address.
+pub fn is_hex_address(user: &str) -> bool {
+    user.len() == 42 && user.starts_with("0x") && user[2..].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// One step of a [`JsonPath`].
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// A simple JSON path: `$` followed by `.key`, `['key']` or `[index]`
+/// steps, e.g. `$.action.type` or `$.action.orders[0].c`. The leading `$.`
+/// may be left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+impl JsonPath {
+    /// The value at this path in `record`, if there is one.
+    pub fn get<'a>(&self, record: &'a Value) -> Option<&'a Value> {
+        self.steps
+            .iter()
+            .try_fold(record, |value, step| match step {
+                Step::Key(key) => value.get(key),
+                Step::Index(index) => value.get(index),
+            })
+    }
+
+    /// The key of the last step, unless it's an index.
+    pub fn last_key(&self) -> Option<&str> {
+        match self.steps.last() {
+            Some(Step::Key(key)) => Some(key),
+            _ => None,
+        }
+    }
+}
+
+/// `<path>=<value>`: keep records whose value at a [`JsonPath`] equals
+/// `value`, compared as text (strings unquoted, numbers and bools as JSON).
+/// A record without the path doesn't match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathFilter {
+    path: JsonPath,
+    value: String,
+}
+
+impl PathFilter {
+    pub fn matches(&self, record: &Value) -> bool {
+        self.path
+            .get(record)
+            .and_then(value_str)
+            .is_some_and(|found| found == self.value)
+    }
+}
+
+impl FromStr for PathFilter {
+    type Err = String;
+
+    fn from_str(expr: &str) -> Result<Self, String> {
+        let (path, value) = expr.split_once('=').ok_or("expected <path>=<value>")?;
+        Ok(Self {
+            path: path.parse()?,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, String> {
+        let path = path.trim();
+
+        // `a.b` and `[0]` are short for `$.a.b` and `$[0]`
+        let normalized = match path.strip_prefix('$') {
+            Some(rest) => rest.to_string(),
+            None if path.starts_with('[') => path.to_string(),
+            None => format!(".{}", path),
+        };
+        let mut rest = normalized.as_str();
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                steps.push(Step::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| "unclosed [".to_string())?;
+                let inner = &after[..end];
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|key| key.strip_suffix('\''))
+                    .or_else(|| {
+                        inner
+                            .strip_prefix('"')
+                            .and_then(|key| key.strip_suffix('"'))
+                    });
+                steps.push(match quoted {
+                    Some(key) => Step::Key(key.to_string()),
+                    None => Step::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| "expected [index] or ['key']".to_string())?,
+                    ),
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err("expected . or [ after a step".to_string());
+            }
+        }
+
+        if steps.is_empty() || steps.iter().any(|step| *step == Step::Key(String::new())) {
+            return Err("empty path or key".to_string());
+        }
+        Ok(Self { steps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn trades() -> Value {
+        json!([
+            {"coin": "BTC", "side": "B", "px": "60000", "sz": "0.5"},
+            {"coin": "BTC", "side": "A", "px": "60001", "sz": "2"},
+            {"coin": "ETH", "side": "B", "px": "3000", "sz": "10"},
+            {"coin": "ETH", "side": "A", "px": "3001", "sz": "not a size"},
+            {"coin": "SOL", "side": "B", "px": "150"},
+        ])
+    }
+
+    fn coins(payload: &Value) -> Vec<&str> {
+        payload
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|trade| trade["coin"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn min_size_keeps_trades_at_or_above_it() {
+        let filter = ClientFilter {
+            min_size: Some(2.0),
+            ..Default::default()
+        };
+        let mut payload = trades();
+        assert_eq!(filter.apply(&mut payload), 2);
+        assert_eq!(coins(&payload), ["BTC", "ETH"]);
+        assert_eq!(payload[0]["sz"], "2");
+    }
+
+    #[test]
+    fn side_keeps_only_that_side() {
+        let filter = ClientFilter {
+            side: Some("A".to_string()),
+            ..Default::default()
+        };
+        let mut payload = trades();
+        assert_eq!(filter.apply(&mut payload), 2);
+        assert!(payload
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|trade| trade["side"] == "A"));
+    }
+
+    #[test]
+    fn min_size_and_side_must_both_match() {
+        let filter = ClientFilter {
+            min_size: Some(1.0),
+            side: Some("B".to_string()),
+            ..Default::default()
+        };
+        let mut payload = trades();
+        assert_eq!(filter.apply(&mut payload), 1);
+        assert_eq!(coins(&payload), ["ETH"]);
+    }
+
+    fn action(kind: &str, grouping: &str) -> Value {
+        json!({
+            "user": "0x1",
+            "action": {
+                "type": kind,
+                "grouping": grouping,
+                "orders": [{"a": 0, "b": true, "p": "100", "c": "0xabc"}],
+            },
+        })
+    }
+
+    fn path_filter(expr: &str) -> PathFilter {
+        expr.parse().unwrap()
+    }
+
+    #[test]
+    fn path_filter_matches_nested_keys() {
+        let record = action("order", "na");
+        assert!(path_filter("$.action.type=order").matches(&record));
+        assert!(path_filter("action.type=order").matches(&record));
+        assert!(path_filter("$['action']['grouping']=na").matches(&record));
+        assert!(!path_filter("$.action.type=cancel").matches(&record));
+    }
+
+    #[test]
+    fn path_filter_indexes_into_arrays() {
+        let record = action("order", "na");
+        assert!(path_filter("$.action.orders[0].c=0xabc").matches(&record));
+        // Numbers and bools compare as their JSON text
+        assert!(path_filter("$.action.orders[0].a=0").matches(&record));
+        assert!(path_filter("$.action.orders[0].b=true").matches(&record));
+        assert!(!path_filter("$.action.orders[1].c=0xabc").matches(&record));
+    }
+
+    #[test]
+    fn path_filter_misses_when_the_path_is_absent() {
+        let record = action("order", "na");
+        assert!(!path_filter("$.action.missing=order").matches(&record));
+        // Stepping into a string or by index into an object finds nothing
+        assert!(!path_filter("$.action.type.inner=order").matches(&record));
+        assert!(!path_filter("$.action[0]=order").matches(&record));
+    }
+
+    #[test]
+    fn path_filters_apply_to_every_record() {
+        let filter = ClientFilter {
+            paths: vec![
+                path_filter("$.action.type=order"),
+                path_filter("$.action.grouping=na"),
+            ],
+            ..Default::default()
+        };
+        let mut payload = json!([
+            action("order", "na"),
+            action("cancel", "na"),
+            action("order", "normalTpsl"),
+        ]);
+        assert_eq!(filter.apply(&mut payload), 1);
+        assert_eq!(payload[0], action("order", "na"));
+    }
+
+    #[test]
+    fn malformed_paths_are_rejected() {
+        for expr in ["no-equals", "$.=x", "$.a[=x", "$.a[x]=y", "$.a..b=x"] {
+            assert!(expr.parse::<PathFilter>().is_err(), "{}", expr);
+        }
+    }
+}
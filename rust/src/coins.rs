@@ -0,0 +1,136 @@
+//! Which coins are trading right now.
+//!
+//! [`sample_coins`] subscribes to TRADES unfiltered for a short window and
+//! counts trades per `coin`, as a quick inventory of active markets before
+//! setting up filters. A quiet market may not trade within the window, so
+//! the list is a sample, not every listed coin.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{info, warn};
+
+use crate::common::{
+    authed_request, build_endpoint, decompress_with, streaming_client, validate_token,
+};
+use crate::error::CliError;
+use crate::health::describe_connect_error;
+use crate::hyperliquid::{self, StreamSubscribe, StreamType, SubscribeRequest};
+use crate::records::{field_str, split_records};
+use crate::source::Received;
+use crate::stream::StreamOptions;
+use crate::Result;
+
+pub const DEFAULT_SAMPLE: Duration = Duration::from_secs(10);
+
+/// Trades seen per coin.
+#[derive(Debug, Default)]
+pub struct CoinCounts {
+    trades: HashMap<String, u64>,
+}
+
+impl CoinCounts {
+    /// Count every trade in a decoded TRADES payload. Records without a
+    /// `coin` are skipped.
+    pub fn add_payload(&mut self, payload: &Value) {
+        for trade in split_records(payload) {
+            if let Some(coin) = field_str(trade, "coin") {
+                *self.trades.entry(coin).or_default() += 1;
+            }
+        }
+    }
+
+    /// `(coin, trades)`, most active first, ties by name.
+    pub fn sorted(&self) -> Vec<(&str, u64)> {
+        let mut coins: Vec<_> = self
+            .trades
+            .iter()
+            .map(|(coin, &count)| (coin.as_str(), count))
+            .collect();
+        coins.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        coins
+    }
+
+    pub fn total(&self) -> u64 {
+        self.trades.values().sum()
+    }
+
+    /// `[{"coin": "BTC", "trades": 12}, ...]`, in [`sorted`](Self::sorted)
+    /// order.
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.sorted()
+                .into_iter()
+                .map(|(coin, trades)| json!({ "coin": coin, "trades": trades }))
+                .collect(),
+        )
+    }
+}
+
+/// Read live TRADES for `window` and count trades per coin. The window
+/// starts once the subscription is accepted, so connecting doesn't eat
+/// into it. One connection, no reconnects; a stream that closes early
+/// returns what it saw.
+pub async fn sample_coins(options: &StreamOptions, window: Duration) -> Result<CoinCounts> {
+    validate_token(&options.token, options.channel.auth)?;
+    let channel = build_endpoint(&options.endpoint, &options.channel)?
+        .connect()
+        .await
+        .map_err(|e| CliError::ConnectFailed(describe_connect_error(&e)))?;
+    let mut client = streaming_client(channel, &options.channel);
+
+    // Held until the window ends; dropping it would end the subscription
+    let (tx, rx) = mpsc::channel(1);
+    tx.send(SubscribeRequest {
+        request: Some(hyperliquid::subscribe_request::Request::Subscribe(
+            StreamSubscribe {
+                stream_type: StreamType::Trades as i32,
+                start_block: 0,
+                filters: Default::default(),
+                filter_name: String::new(),
+            },
+        )),
+    })
+    .await?;
+    let request = authed_request(
+        ReceiverStream::new(rx),
+        &options.token,
+        options.channel.auth,
+    )?;
+    let mut stream = client.stream_data(request).await?.into_inner();
+    info!(
+        "Sampling trades for {}s from {}",
+        window.as_secs_f64(),
+        options.endpoint
+    );
+
+    let deadline = Instant::now() + window;
+    let mut counts = CoinCounts::default();
+    let mut scratch = Vec::new();
+    loop {
+        let message = tokio::select! {
+            message = stream.message() => message?,
+            _ = tokio::time::sleep_until(deadline) => break,
+        };
+        let Some(update) = message else {
+            warn!("Stream closed before the sample window ended");
+            break;
+        };
+        let Received::Data(data) = Received::from_update(update) else {
+            continue;
+        };
+        decompress_with(data.data.as_bytes(), &mut scratch, None)?;
+        match serde_json::from_slice(&scratch) {
+            Ok(payload) => counts.add_payload(&payload),
+            Err(e) => warn!(
+                "Block {} is not valid JSON, skipped: {}",
+                data.block_number, e
+            ),
+        }
+    }
+    Ok(counts)
+}
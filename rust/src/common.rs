@@ -0,0 +1,689 @@
+//! Channel, auth and payload helpers shared by the streaming examples.
+
+use std::fmt;
+use std::future::Future;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::io::DuplexStream;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Uri;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::Request;
+use zstd::dict::DecoderDictionary;
+
+use crate::error::CliError;
+use crate::hyperliquid::order_book_streaming_client::OrderBookStreamingClient;
+use crate::hyperliquid::streaming_client::StreamingClient;
+use crate::Result;
+
+/// Zstd frame magic number
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Largest message accepted from the server by default, in MiB: tonic's
+/// own default. A busy block or a large L4 snapshot can exceed it
+pub const DEFAULT_MAX_RECV_MB: usize = 4;
+
+/// Largest request sent. Subscriptions and pings are a few KB even with
+/// long filter lists, so this only guards against a runaway request
+pub const MAX_SEND_BYTES: usize = 1024 * 1024;
+
+/// How requests authenticate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum AuthScheme {
+    /// QuickNode's `x-token: <token>` header
+    #[default]
+    Token,
+    /// `authorization: Bearer <token>`, e.g. for a JWT
+    Bearer,
+    /// A TLS client certificate; no token is sent
+    Mtls,
+}
+
+/// Transport settings for [`build_endpoint`] / [`build_channel`].
+///
+/// The default trusts the system roots, uses the endpoint's own host name
+/// for SNI and certificate verification, and leaves keepalive, Nagle and
+/// the connect timeout at tonic's defaults (no HTTP/2 pings, `TCP_NODELAY`
+/// on, no timeout).
+#[derive(Debug, Clone, Default)]
+pub struct ChannelOptions {
+    /// Extra PEM CA certificate to trust, e.g. for a corporate proxy or a
+    /// staging endpoint with a self-signed certificate.
+    pub ca_cert: Option<PathBuf>,
+    /// Override the domain name used for SNI and certificate verification.
+    pub tls_domain: Option<String>,
+    /// How [`authed_request`] authenticates, checked by [`validate_token`].
+    pub auth: AuthScheme,
+    /// PEM client certificate and key presented during the TLS handshake,
+    /// for [`AuthScheme::Mtls`].
+    pub client_identity: Option<(PathBuf, PathBuf)>,
+    /// Send HTTP/2 pings at this interval. Unlike the application-level
+    /// ping, a missing reply closes the connection from the transport, so
+    /// a dead peer is noticed even while a stream sits in a long read.
+    pub http2_keepalive: Option<Duration>,
+    /// How long to wait for a keepalive ping's reply.
+    pub http2_keepalive_timeout: Option<Duration>,
+    /// Set `TCP_NODELAY` on the socket; `None` keeps tonic's default (on).
+    pub tcp_nodelay: Option<bool>,
+    /// Fail a TCP connect that takes longer than this.
+    pub connect_timeout: Option<Duration>,
+    /// Largest message accepted from the server, in MiB; `None` for
+    /// [`DEFAULT_MAX_RECV_MB`].
+    pub max_recv_mb: Option<usize>,
+    /// Open every connection through this instead of TCP and TLS.
+    pub connector: Option<Connector>,
+}
+
+/// Opens the transport of a connection in place of TCP and TLS, e.g. an
+/// in-memory pipe to a server in the same process, for tests. The endpoint
+/// URL is then only used for the HTTP/2 authority.
+#[derive(Clone)]
+pub struct Connector(
+    Arc<dyn Fn() -> BoxFuture<'static, std::io::Result<DuplexStream>> + Send + Sync>,
+);
+
+impl Connector {
+    pub fn new<F, Fut>(open: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<DuplexStream>> + Send + 'static,
+    {
+        Self(Arc::new(move || Box::pin(open())))
+    }
+}
+
+impl fmt::Debug for Connector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Connector")
+    }
+}
+
+impl ChannelOptions {
+    /// Largest message accepted from the server, in bytes.
+    pub fn max_recv_bytes(&self) -> usize {
+        self.max_recv_mb.unwrap_or(DEFAULT_MAX_RECV_MB) * 1024 * 1024
+    }
+
+    fn tls_config(&self) -> Result<ClientTlsConfig> {
+        let mut tls = ClientTlsConfig::new();
+
+        if let Some(path) = &self.ca_cert {
+            let pem = std::fs::read(path)
+                .map_err(|e| format!("failed to read CA certificate {}: {}", path.display(), e))?;
+            if !String::from_utf8_lossy(&pem).contains("-----BEGIN CERTIFICATE-----") {
+                return Err(format!(
+                    "CA certificate {} does not contain a PEM certificate",
+                    path.display()
+                )
+                .into());
+            }
+            tls = tls.ca_certificate(Certificate::from_pem(pem));
+        }
+
+        if let Some(domain) = &self.tls_domain {
+            tls = tls.domain_name(domain.clone());
+        }
+
+        if let Some((cert, key)) = &self.client_identity {
+            let read = |path: &PathBuf, what: &str| {
+                std::fs::read(path).map_err(|e| {
+                    format!("failed to read client {} {}: {}", what, path.display(), e)
+                })
+            };
+            tls = tls.identity(Identity::from_pem(
+                read(cert, "certificate")?,
+                read(key, "key")?,
+            ));
+        }
+
+        Ok(tls)
+    }
+}
+
+/// Build a TLS endpoint without connecting.
+///
+/// Errors here (bad URL, unreadable CA certificate) are configuration
+/// problems that retrying won't fix, which is why this is separate from
+/// [`build_channel`].
+pub fn build_endpoint(endpoint: &str, options: &ChannelOptions) -> Result<Endpoint> {
+    let mut endpoint =
+        Channel::from_shared(endpoint.to_string())?.tls_config(options.tls_config()?)?;
+    if let Some(interval) = options.http2_keepalive {
+        endpoint = endpoint.http2_keep_alive_interval(interval);
+    }
+    if let Some(timeout) = options.http2_keepalive_timeout {
+        endpoint = endpoint.keep_alive_timeout(timeout);
+    }
+    if let Some(nodelay) = options.tcp_nodelay {
+        endpoint = endpoint.tcp_nodelay(nodelay);
+    }
+    if let Some(timeout) = options.connect_timeout {
+        endpoint = endpoint.connect_timeout(timeout);
+    }
+    Ok(endpoint)
+}
+
+/// A `Streaming` client on `channel` with the message size limits of
+/// `options`.
+pub fn streaming_client(channel: Channel, options: &ChannelOptions) -> StreamingClient<Channel> {
+    StreamingClient::new(channel)
+        .max_decoding_message_size(options.max_recv_bytes())
+        .max_encoding_message_size(MAX_SEND_BYTES)
+}
+
+/// An `OrderBookStreaming` client on `channel` with the message size limits
+/// of `options`.
+pub fn order_book_client(
+    channel: Channel,
+    options: &ChannelOptions,
+) -> OrderBookStreamingClient<Channel> {
+    OrderBookStreamingClient::new(channel)
+        .max_decoding_message_size(options.max_recv_bytes())
+        .max_encoding_message_size(MAX_SEND_BYTES)
+}
+
+/// Connect `endpoint`, through `options.connector` if one is set.
+pub async fn connect(
+    endpoint: &Endpoint,
+    options: &ChannelOptions,
+) -> Result<Channel, tonic::transport::Error> {
+    match &options.connector {
+        Some(Connector(open)) => {
+            let open = open.clone();
+            endpoint
+                .connect_with_connector(tower::service_fn(move |_: Uri| open()))
+                .await
+        }
+        None => endpoint.connect().await,
+    }
+}
+
+/// Connect a TLS channel to `endpoint`.
+pub async fn build_channel(endpoint: &str, options: &ChannelOptions) -> Result<Channel> {
+    Ok(connect(&build_endpoint(endpoint, options)?, options).await?)
+}
+
+/// `--ca-cert`, `--tls-domain` and transport flags for the examples that
+/// connect over gRPC.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ChannelArgs {
+    /// PEM CA certificate to trust in addition to the system roots
+    #[arg(long, value_name = "PATH")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Override the TLS domain name used for SNI and certificate verification
+    #[arg(long)]
+    pub tls_domain: Option<String>,
+
+    /// How to authenticate: the x-token header, an `authorization: Bearer`
+    /// header (both carrying --token), or a TLS client certificate
+    /// (--client-cert and --client-key, no token)
+    #[arg(long, value_enum, default_value = "token")]
+    pub auth_scheme: AuthScheme,
+
+    /// PEM client certificate for --auth-scheme mtls
+    #[arg(long, value_name = "PATH", requires = "client_key")]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM private key for --client-cert
+    #[arg(long, value_name = "PATH", requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+
+    /// Send HTTP/2 keepalive pings every N seconds, so the transport drops
+    /// a dead connection even when the application ping can't get through
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub http2_keepalive_secs: Option<u64>,
+
+    /// Drop the connection when a keepalive ping gets no reply within N
+    /// seconds (tonic's default is 20)
+    #[arg(long, value_name = "N", requires = "http2_keepalive_secs", value_parser = clap::value_parser!(u64).range(1..))]
+    pub http2_keepalive_timeout_secs: Option<u64>,
+
+    /// Set TCP_NODELAY (already on by default); `--tcp-nodelay false`
+    /// turns it off
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub tcp_nodelay: Option<bool>,
+
+    /// Fail a TCP connect that takes longer than N seconds. Counts as a
+    /// failed connection, so it's retried like one
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Accept messages from the server up to N MiB (default 4). Raise it if
+    /// the stream fails with a message-too-large error, e.g. on busy blocks
+    /// or large L4 snapshots
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=4096))]
+    pub max_recv_mb: Option<usize>,
+}
+
+impl ChannelArgs {
+    /// The options these flags describe. A client certificate is required
+    /// for, and only accepted with, `--auth-scheme mtls`.
+    pub fn options(self) -> Result<ChannelOptions, CliError> {
+        let client_identity = self.client_cert.zip(self.client_key);
+        match (self.auth_scheme, &client_identity) {
+            (AuthScheme::Mtls, None) => {
+                return Err(CliError::BadArgs(
+                    "--auth-scheme mtls needs --client-cert and --client-key".to_string(),
+                ))
+            }
+            (AuthScheme::Token | AuthScheme::Bearer, Some(_)) => {
+                return Err(CliError::BadArgs(
+                    "--client-cert is only used with --auth-scheme mtls".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        Ok(ChannelOptions {
+            ca_cert: self.ca_cert,
+            tls_domain: self.tls_domain,
+            auth: self.auth_scheme,
+            client_identity,
+            http2_keepalive: self.http2_keepalive_secs.map(Duration::from_secs),
+            http2_keepalive_timeout: self.http2_keepalive_timeout_secs.map(Duration::from_secs),
+            tcp_nodelay: self.tcp_nodelay,
+            connect_timeout: self.connect_timeout_secs.map(Duration::from_secs),
+            max_recv_mb: self.max_recv_mb,
+            connector: None,
+        })
+    }
+}
+
+/// The token the examples ship with, before it's replaced with a real one
+pub const PLACEHOLDER_TOKEN: &str = "your-auth-token";
+
+/// Reject a token that can't be right before connecting with it: empty,
+/// still the placeholder, or not valid as a header value. The server would
+/// only reject these with a less helpful status. [`AuthScheme::Mtls`] sends
+/// no token, so any is accepted.
+pub fn validate_token(token: &str, auth: AuthScheme) -> Result<(), CliError> {
+    if auth == AuthScheme::Mtls {
+        return Ok(());
+    }
+    if token.trim().is_empty() {
+        return Err(CliError::BadArgs(
+            "auth token is empty; pass --token or --token-file, or use --auth-scheme mtls"
+                .to_string(),
+        ));
+    }
+    if token == PLACEHOLDER_TOKEN {
+        return Err(CliError::BadArgs(format!(
+            "auth token is still the placeholder {:?}; set AUTH_TOKEN to your QuickNode token",
+            PLACEHOLDER_TOKEN
+        )));
+    }
+    if let Some((position, c)) = invalid_token_char(token) {
+        let kind = if c.is_ascii_whitespace() {
+            "whitespace"
+        } else if c.is_ascii_control() {
+            "control character"
+        } else {
+            "non-ASCII character"
+        };
+        return Err(CliError::BadArgs(format!(
+            "auth token has {} {:?} at position {} of {}; a token must be printable ASCII, with no whitespace at either end",
+            kind,
+            c,
+            position,
+            token.chars().count()
+        )));
+    }
+    if token.parse::<MetadataValue<_>>().is_err() {
+        return Err(CliError::BadArgs(
+            "auth token contains characters not allowed in a header".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The first character of `token` that can't go in a header, with its
+/// 1-based position: a control or non-ASCII character, or whitespace at
+/// either end. Spaces and tabs inside a token are allowed.
+fn invalid_token_char(token: &str) -> Option<(usize, char)> {
+    let last = token.chars().count().saturating_sub(1);
+    token
+        .chars()
+        .enumerate()
+        .find(|&(i, c)| {
+            let inner_blank = (c == ' ' || c == '\t') && i != 0 && i != last;
+            !c.is_ascii_graphic() && !inner_blank
+        })
+        .map(|(i, c)| (i + 1, c))
+}
+
+/// Read a token from `path`, for `--token-file`, so it stays out of
+/// process arguments and shell history. Trailing whitespace is trimmed:
+/// most editors and `echo` end the file with a newline.
+pub fn read_token_file(path: &Path) -> Result<String, CliError> {
+    let bytes = std::fs::read(path).map_err(|e| {
+        CliError::BadArgs(format!("can't read token file {}: {}", path.display(), e))
+    })?;
+    let token = String::from_utf8(bytes).map_err(|e| {
+        let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+        CliError::BadArgs(format!(
+            "token file {} has an invalid byte 0x{:02x} at position {}; a token must be printable ASCII",
+            path.display(),
+            e.as_bytes()[valid.len()],
+            String::from_utf8_lossy(valid).chars().count() + 1
+        ))
+    })?;
+    Ok(token.trim_end().to_string())
+}
+
+/// Wrap `msg` in a request carrying `token` as `auth` says: the QuickNode
+/// `x-token` header, an `authorization: Bearer` header, or nothing for
+/// mTLS, where the client certificate authenticates the connection.
+pub fn authed_request<T>(msg: T, token: &str, auth: AuthScheme) -> Result<Request<T>> {
+    validate_token(token, auth)?;
+    let mut request = Request::new(msg);
+    match auth {
+        AuthScheme::Token => {
+            let token: MetadataValue<_> = token.parse()?;
+            request.metadata_mut().insert("x-token", token);
+        }
+        AuthScheme::Bearer => {
+            let value: MetadataValue<_> = format!("Bearer {}", token).parse()?;
+            request.metadata_mut().insert("authorization", value);
+        }
+        AuthScheme::Mtls => {}
+    }
+    Ok(request)
+}
+
+/// Write `contents` to `path` atomically: a sibling temporary file is
+/// written, synced and renamed over `path`, so readers (and a restart after
+/// a crash) see the old file or the new one, never part of one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Whether `data` starts with a zstd frame.
+pub fn is_zstd(data: &[u8]) -> bool {
+    data.len() >= 4 && data[0..4] == ZSTD_MAGIC
+}
+
+/// Inflate a stream payload if it is zstd, returning the raw bytes. Every
+/// concatenated frame is decoded, not just the first; anything else is
+/// returned unchanged.
+pub fn decompress_bytes(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    decompress_into(data, &mut out)?;
+    Ok(out)
+}
+
+/// A zstd dictionary that payloads may be compressed against, loaded once
+/// and reused for every message (`--zstd-dict`).
+pub struct ZstdDict {
+    path: PathBuf,
+    /// `None` for a raw-content dictionary, which carries no id
+    id: Option<u32>,
+    dict: DecoderDictionary<'static>,
+}
+
+impl ZstdDict {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("cannot read zstd dictionary {}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            id: zstd::zstd_safe::get_dict_id_from_dict(&bytes).map(u32::from),
+            dict: DecoderDictionary::copy(&bytes),
+        })
+    }
+
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+}
+
+impl std::fmt::Debug for ZstdDict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZstdDict")
+            .field("path", &self.path)
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+/// [`decompress_bytes`] into a caller-owned buffer, which is cleared first.
+/// Reusing one buffer across messages avoids an allocation per payload.
+pub fn decompress_into(data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    decompress_with(data, out, None)
+}
+
+/// How the raw stream decodes payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadEncoding {
+    /// Decompress payloads that start with a zstd frame, pass the rest
+    /// through
+    #[default]
+    Auto,
+    /// Every payload is plain JSON text: used as received, with no frame
+    /// check, copy or UTF-8 pass. A compressed one comes out as garbage
+    Plain,
+    /// Every payload must be zstd-compressed; a plain one is an error, which
+    /// points at a misconfigured endpoint
+    Zstd,
+}
+
+/// [`decompress_into`], decoding frames that name a dictionary id with
+/// `dict`. Every frame of a concatenated payload is checked, since a later
+/// one may name a dictionary the first doesn't. Payloads without any take
+/// the same path as without a dictionary.
+pub fn decompress_with(data: &[u8], out: &mut Vec<u8>, dict: Option<&ZstdDict>) -> Result<()> {
+    out.clear();
+    if is_zstd(data) {
+        // Frames compressed against a dictionary can't be decoded without it
+        if let Some(dict_id) = frame_dict_id(data)? {
+            let Some(dict) = dict else {
+                return Err(format!(
+                    "payload is zstd-compressed with dictionary {}; pass that dictionary with --zstd-dict <path>",
+                    dict_id
+                )
+                .into());
+            };
+            if dict.id.is_some_and(|id| id != dict_id) {
+                return Err(format!(
+                    "payload is zstd-compressed with dictionary {}, but --zstd-dict {} is dictionary {}",
+                    dict_id,
+                    dict.path.display(),
+                    dict.id.unwrap_or_default()
+                )
+                .into());
+            }
+            let mut decoder = zstd::stream::read::Decoder::with_prepared_dictionary(
+                Cursor::new(data),
+                &dict.dict,
+            )?;
+            decoder
+                .read_to_end(out)
+                .map_err(|e| format!("zstd decode with dictionary {} failed: {}", dict_id, e))?;
+            return Ok(());
+        }
+
+        // The input is already in memory, so skip the decoder's own buffer
+        let mut decoder = zstd::stream::read::Decoder::with_buffer(Cursor::new(data))?;
+        decoder
+            .read_to_end(out)
+            .map_err(|e| format!("zstd decode failed: {}", e))?;
+        return Ok(());
+    }
+
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+/// The dictionary id the frames of `data` name, if any does. Frames that
+/// name none (or are skippable) don't count; two different ids are an
+/// error, since only one dictionary can be given.
+fn frame_dict_id(data: &[u8]) -> Result<Option<u32>> {
+    let mut found: Option<u32> = None;
+    let mut rest = data;
+    while !rest.is_empty() {
+        let len = zstd::zstd_safe::find_frame_compressed_size(rest).map_err(|code| {
+            format!(
+                "malformed zstd frame at byte {}: {}",
+                data.len() - rest.len(),
+                zstd::zstd_safe::get_error_name(code)
+            )
+        })?;
+        if let Some(id) = zstd::zstd_safe::get_dict_id_from_frame(&rest[..len]) {
+            match found {
+                Some(first) if first != id.get() => {
+                    return Err(format!(
+                        "payload has frames compressed with dictionaries {} and {}; only one --zstd-dict can be given",
+                        first, id
+                    )
+                    .into())
+                }
+                _ => found = Some(id.get()),
+            }
+        }
+        rest = &rest[len..];
+    }
+    Ok(found)
+}
+
+/// [`decompress_bytes`] decoded to text. Compressed payloads must be valid
+/// UTF-8; uncompressed ones are decoded lossily.
+pub fn decompress(data: &[u8]) -> Result<String> {
+    if is_zstd(data) {
+        return Ok(String::from_utf8(decompress_bytes(data)?)?);
+    }
+
+    Ok(String::from_utf8_lossy(data).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trained dictionary, which unlike raw content carries an id.
+    fn trained_dict() -> Vec<u8> {
+        let samples: Vec<Vec<u8>> = (0..1000)
+            .map(|i| {
+                format!(
+                    r#"{{"coin":"C{}","side":"{}","px":"{}.{}","sz":"0.{}","tid":{}}}"#,
+                    i % 17,
+                    if i % 2 == 0 { "B" } else { "A" },
+                    97000 + i % 91,
+                    i % 10,
+                    i % 7,
+                    i
+                )
+                .into_bytes()
+            })
+            .collect();
+        zstd::dict::from_samples(&samples, 4096).unwrap()
+    }
+
+    fn with_dict(dict: &[u8], data: &[u8]) -> Vec<u8> {
+        zstd::bulk::Compressor::with_dictionary(3, dict)
+            .unwrap()
+            .compress(data)
+            .unwrap()
+    }
+
+    fn loaded(dict: &[u8]) -> ZstdDict {
+        ZstdDict {
+            path: PathBuf::from("test.dict"),
+            id: zstd::zstd_safe::get_dict_id_from_dict(dict).map(u32::from),
+            dict: DecoderDictionary::copy(dict),
+        }
+    }
+
+    #[test]
+    fn concatenated_frames_decode_in_full() {
+        let mut data = zstd::encode_all(&b"[{\"tid\":1},"[..], 3).unwrap();
+        data.extend(zstd::encode_all(&b"{\"tid\":2}]"[..], 3).unwrap());
+        assert_eq!(decompress(&data).unwrap(), "[{\"tid\":1},{\"tid\":2}]");
+    }
+
+    #[test]
+    fn dictionary_in_a_later_frame_is_detected() {
+        let dict = trained_dict();
+        let id = zstd::zstd_safe::get_dict_id_from_dict(&dict).unwrap();
+        let mut data = zstd::encode_all(&b"[{\"coin\":\"C1\"},"[..], 3).unwrap();
+        data.extend(with_dict(&dict, b"{\"coin\":\"C2\"}]"));
+
+        let mut out = Vec::new();
+        let err = decompress_with(&data, &mut out, None).unwrap_err();
+        assert!(
+            err.to_string().contains(&format!("dictionary {}", id)),
+            "{}",
+            err
+        );
+
+        decompress_with(&data, &mut out, Some(&loaded(&dict))).unwrap();
+        assert_eq!(out, b"[{\"coin\":\"C1\"},{\"coin\":\"C2\"}]");
+    }
+
+    fn bad_args(result: Result<impl std::fmt::Debug, CliError>) -> String {
+        match result {
+            Err(CliError::BadArgs(message)) => message,
+            other => panic!("expected BadArgs, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_token_file_loses_its_trailing_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        std::fs::write(&path, "abc123\r\n").unwrap();
+
+        let token = read_token_file(&path).unwrap();
+        assert_eq!(token, "abc123");
+        assert!(validate_token(&token, AuthScheme::Token).is_ok());
+    }
+
+    #[test]
+    fn a_token_file_with_an_invalid_byte_names_its_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        std::fs::write(&path, b"abc\xff123\n").unwrap();
+
+        let message = bad_args(read_token_file(&path));
+        assert!(
+            message.contains("invalid byte 0xff at position 4"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn a_token_with_a_newline_or_control_character_is_rejected() {
+        let message = bad_args(validate_token("abc123\n", AuthScheme::Token));
+        assert!(
+            message.contains("whitespace '\\n' at position 7 of 7"),
+            "{}",
+            message
+        );
+
+        let message = bad_args(validate_token("ab\u{7}c", AuthScheme::Bearer));
+        assert!(message.contains("control character"), "{}", message);
+        let message = bad_args(validate_token("tökén", AuthScheme::Token));
+        assert!(
+            message.contains("non-ASCII character 'ö' at position 2"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn inner_spaces_and_mtls_without_a_token_are_accepted() {
+        assert!(validate_token("abc 123", AuthScheme::Token).is_ok());
+        assert!(validate_token("", AuthScheme::Mtls).is_ok());
+        bad_args(validate_token(" ", AuthScheme::Token));
+        bad_args(validate_token(PLACEHOLDER_TOKEN, AuthScheme::Token));
+    }
+}
@@ -0,0 +1,161 @@
+//! Output files compressed as they are written.
+//!
+//! Long NDJSON and CSV exports grow quickly, and the payloads compress well
+//! (often 5-10x). [`OutputFile`] writes through a zstd or gzip encoder
+//! picked with `--compress-output`, or from the file's extension (`.zst`,
+//! `.gz`) when the flag isn't given. A compressed file is only complete
+//! once [`OutputFile::finish`] has written the end of the zstd frame or the
+//! gzip trailer; a run that dies before that leaves a truncated file that
+//! decompresses up to where it stopped, with an error at the end.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputCompression {
+    Zstd,
+    Gzip,
+}
+
+impl OutputCompression {
+    /// The compression a file name asks for: `.zst` or `.zstd` for zstd,
+    /// `.gz` for gzip.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "zst" | "zstd" => Some(Self::Zstd),
+            "gz" => Some(Self::Gzip),
+            _ => None,
+        }
+    }
+}
+
+/// A file written plain or through an encoder.
+pub enum OutputFile {
+    Plain(BufWriter<File>),
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl OutputFile {
+    /// Create `path`, compressed with `compression`, or as its extension
+    /// asks when that is `None`.
+    pub fn create(path: &Path, compression: Option<OutputCompression>) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(
+            match compression.or_else(|| OutputCompression::from_path(path)) {
+                None => Self::Plain(file),
+                Some(OutputCompression::Zstd) => Self::Zstd(zstd::stream::write::Encoder::new(
+                    file,
+                    zstd::DEFAULT_COMPRESSION_LEVEL,
+                )?),
+                Some(OutputCompression::Gzip) => {
+                    Self::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+                }
+            },
+        )
+    }
+
+    /// Write the end of the compressed stream and flush everything to the
+    /// file. Call once, after the last write.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Zstd(encoder) => {
+                encoder.do_finish()?;
+                encoder.get_mut().flush()
+            }
+            Self::Gzip(encoder) => {
+                encoder.try_finish()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    const LINES: &str =
+        "{\"coin\":\"BTC\",\"px\":\"97000.5\"}\n{\"coin\":\"ETH\",\"px\":\"3000.25\"}\n";
+
+    /// Write `LINES` to `name` and return the file's bytes.
+    fn written(dir: &Path, name: &str, compression: Option<OutputCompression>) -> Vec<u8> {
+        let path = dir.join(name);
+        let mut file = OutputFile::create(&path, compression).unwrap();
+        for line in LINES.split_inclusive('\n') {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.finish().unwrap();
+        drop(file);
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn zstd_output_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = written(dir.path(), "out.ndjson", Some(OutputCompression::Zstd));
+
+        let decoded = zstd::decode_all(bytes.as_slice()).unwrap();
+        assert_eq!(String::from_utf8(decoded).unwrap(), LINES);
+    }
+
+    #[test]
+    fn gzip_output_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = written(dir.path(), "out.ndjson", Some(OutputCompression::Gzip));
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, LINES);
+    }
+
+    #[test]
+    fn the_extension_picks_the_encoder() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let zstd = written(dir.path(), "out.ndjson.zst", None);
+        assert_eq!(zstd::decode_all(zstd.as_slice()).unwrap(), LINES.as_bytes());
+        let gzip = written(dir.path(), "out.csv.GZ", None);
+        assert_eq!(&gzip[..2], [0x1f, 0x8b]);
+        assert_eq!(written(dir.path(), "out.ndjson", None), LINES.as_bytes());
+    }
+
+    #[test]
+    fn an_unfinished_zstd_file_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.zst");
+        let mut file = OutputFile::create(&path, None).unwrap();
+        file.write_all(LINES.as_bytes()).unwrap();
+        file.flush().unwrap();
+        // Dropped without finish(), as when a run dies
+        drop(file);
+
+        assert!(zstd::decode_all(std::fs::read(path).unwrap().as_slice()).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Suppression of records replayed across a reconnect.
+//!
+//! A reconnect resubscribes from the last block received, so the server may
+//! deliver that boundary block again. [`ReplayDedup`] remembers a hash of
+//! every record in the most recent block; after a reconnect, records in that
+//! same block whose hash was already seen are dropped. The window closes as
+//! soon as a later block arrives, so at most one block of hashes is kept.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::records::retain_records;
+
+#[derive(Default)]
+pub struct ReplayDedup {
+    /// Block currently being received and the hashes of its records
+    current: Option<(u64, HashSet<u64>)>,
+    /// Boundary block from before the last reconnect
+    overlap: Option<(u64, HashSet<u64>)>,
+    suppressed: u64,
+}
+
+impl ReplayDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call when the stream is about to resubscribe: the block in progress
+    /// becomes the overlap window.
+    pub fn reconnected(&mut self) {
+        if let Some(current) = self.current.take() {
+            self.overlap = Some(current);
+        }
+    }
+
+    /// Drop records of `payload` already delivered before a reconnect.
+    /// Returns false if nothing is left, in which case the message should
+    /// be skipped entirely.
+    pub fn retain_new(&mut self, block_number: u64, payload: &mut Value) -> bool {
+        let (seen, replayed, suppressed) = self.window(block_number);
+        let kept = retain_records(payload, |record| {
+            let hash = xxh3_64(record.to_string().as_bytes());
+            seen.insert(hash);
+            let duplicate = replayed.is_some_and(|hashes| hashes.contains(&hash));
+            if duplicate {
+                *suppressed += 1;
+            }
+            !duplicate
+        });
+        kept > 0
+    }
+
+    /// Like [`retain_new`](Self::retain_new) for a payload that isn't
+    /// parsed, with the whole message hashed as one record. Returns false
+    /// if it was already delivered before a reconnect.
+    pub fn retain_message(&mut self, block_number: u64, text: &[u8]) -> bool {
+        let (seen, replayed, suppressed) = self.window(block_number);
+        let hash = xxh3_64(text);
+        seen.insert(hash);
+        let duplicate = replayed.is_some_and(|hashes| hashes.contains(&hash));
+        if duplicate {
+            *suppressed += 1;
+        }
+        !duplicate
+    }
+
+    /// Move the window to `block_number`: the hashes seen for it so far, the
+    /// hashes delivered for it before a reconnect (if it is the boundary
+    /// block), and the suppressed count.
+    fn window(
+        &mut self,
+        block_number: u64,
+    ) -> (&mut HashSet<u64>, Option<&HashSet<u64>>, &mut u64) {
+        if matches!(&self.overlap, Some((block, _)) if block_number > *block) {
+            self.overlap = None;
+        }
+        if !matches!(&self.current, Some((block, _)) if *block == block_number) {
+            self.current = Some((block_number, HashSet::new()));
+        }
+
+        let Self {
+            current,
+            overlap,
+            suppressed,
+        } = self;
+        let (_, seen) = current.as_mut().expect("current block was just set");
+        let replayed = match overlap {
+            Some((block, hashes)) if *block == block_number => Some(&*hashes),
+            _ => None,
+        };
+        (seen, replayed, suppressed)
+    }
+
+    /// Treat `hashes` as records of `block_number` already delivered, e.g.
+    /// by an earlier run that is being resumed.
+    pub fn resume(&mut self, block_number: u64, hashes: HashSet<u64>) {
+        self.overlap = Some((block_number, hashes));
+    }
+
+    /// The block being received and the hashes of its records so far.
+    pub fn current(&self) -> Option<(u64, &HashSet<u64>)> {
+        self.current
+            .as_ref()
+            .map(|(block, hashes)| (*block, hashes))
+    }
+
+    /// Records dropped as replays so far.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn replayed_boundary_block_drops_only_seen_records() {
+        let mut dedup = ReplayDedup::new();
+        let mut before = json!([{ "tid": 1 }, { "tid": 2 }]);
+        assert!(dedup.retain_new(100, &mut before));
+        dedup.reconnected();
+
+        // The boundary block comes again, with a record missed before
+        let mut replayed = json!([{ "tid": 1 }, { "tid": 2 }, { "tid": 3 }]);
+        assert!(dedup.retain_new(100, &mut replayed));
+        assert_eq!(replayed, json!([{ "tid": 3 }]));
+        assert_eq!(dedup.suppressed(), 2);
+
+        // Nothing new at all: the message is skipped
+        let mut again = json!([{ "tid": 2 }]);
+        assert!(!dedup.retain_new(100, &mut again));
+
+        // A later block closes the window, even for identical records
+        let mut next = json!([{ "tid": 1 }]);
+        assert!(dedup.retain_new(101, &mut next));
+        assert_eq!(next, json!([{ "tid": 1 }]));
+        assert_eq!(dedup.suppressed(), 3);
+    }
+}
@@ -0,0 +1,159 @@
+//! Several endpoints for one stream, rotated on reconnect.
+//!
+//! Each reconnect moves to the endpoint with the fewest consecutive
+//! failures, taking the next one in order among equals. With every endpoint
+//! failing alike that's plain round robin; once one is delivering data,
+//! dead ones are only retried after it fails too. Every endpoint carries its
+//! own token.
+
+use tonic::transport::{Channel, Endpoint};
+use tracing::info;
+
+use crate::common::{build_endpoint, connect, validate_token, AuthScheme, ChannelOptions};
+use crate::error::CliError;
+use crate::Result;
+
+/// Pair each `--endpoint` with a `--token`: one token is shared by every
+/// endpoint, otherwise there must be one per endpoint, in the same order.
+pub fn pair_tokens(
+    endpoints: Vec<String>,
+    tokens: Vec<String>,
+) -> Result<Vec<(String, String)>, CliError> {
+    if endpoints.is_empty() {
+        return Err(CliError::BadArgs(
+            "--endpoint is required for this command".to_string(),
+        ));
+    }
+    match tokens.len() {
+        // Only --auth-scheme mtls goes without; validate_token catches the rest
+        0 => Ok(endpoints
+            .into_iter()
+            .map(|endpoint| (endpoint, String::new()))
+            .collect()),
+        1 => {
+            let token = &tokens[0];
+            Ok(endpoints
+                .into_iter()
+                .map(|endpoint| (endpoint, token.clone()))
+                .collect())
+        }
+        n if n == endpoints.len() => Ok(endpoints.into_iter().zip(tokens).collect()),
+        n => Err(CliError::BadArgs(format!(
+            "{} --token values for {} --endpoint values; give one token, or one per endpoint in the same order",
+            n,
+            endpoints.len()
+        ))),
+    }
+}
+
+struct Entry {
+    url: String,
+    token: String,
+    endpoint: Endpoint,
+    /// Connections that delivered data
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u64,
+}
+
+/// The endpoints a stream may use and how each has fared.
+pub struct EndpointPool {
+    entries: Vec<Entry>,
+    current: usize,
+    channel: ChannelOptions,
+}
+
+impl EndpointPool {
+    /// Build every endpoint up front, so a bad URL, CA certificate or token
+    /// fails immediately rather than on the first rotation.
+    pub fn new(targets: &[(String, String)], channel: &ChannelOptions) -> Result<Self> {
+        if targets.is_empty() {
+            return Err(CliError::BadArgs("no endpoint given".to_string()).into());
+        }
+        let mut entries = Vec::with_capacity(targets.len());
+        for (url, token) in targets {
+            validate_token(token, channel.auth)?;
+            entries.push(Entry {
+                url: url.clone(),
+                token: token.clone(),
+                endpoint: build_endpoint(url, channel)?,
+                successes: 0,
+                failures: 0,
+                consecutive_failures: 0,
+            });
+        }
+        Ok(Self {
+            entries,
+            current: 0,
+            channel: channel.clone(),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.entries[self.current].url
+    }
+
+    pub fn token(&self) -> &str {
+        &self.entries[self.current].token
+    }
+
+    /// How requests authenticate, the same for every endpoint
+    pub fn auth(&self) -> AuthScheme {
+        self.channel.auth
+    }
+
+    /// Transport settings shared by every endpoint
+    pub fn channel(&self) -> &ChannelOptions {
+        &self.channel
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.entries[self.current].endpoint
+    }
+
+    /// Connect to the current endpoint.
+    pub async fn connect(&self) -> Result<Channel, tonic::transport::Error> {
+        connect(self.endpoint(), &self.channel).await
+    }
+
+    /// Record that the current endpoint delivered data.
+    pub fn succeeded(&mut self) {
+        let entry = &mut self.entries[self.current];
+        entry.successes += 1;
+        entry.consecutive_failures = 0;
+    }
+
+    /// Record that the current connection failed and move to the healthiest
+    /// endpoint, the next in order among equals.
+    pub fn failed(&mut self) {
+        let entry = &mut self.entries[self.current];
+        entry.failures += 1;
+        entry.consecutive_failures += 1;
+        if self.entries.len() == 1 {
+            return;
+        }
+
+        let n = self.entries.len();
+        let next = (1..=n)
+            .map(|offset| (self.current + offset) % n)
+            .min_by_key(|&i| self.entries[i].consecutive_failures)
+            .unwrap_or(self.current);
+        if next != self.current {
+            self.current = next;
+            info!("Switching to endpoint {}", self.url());
+        }
+    }
+
+    /// Log each endpoint's counts, when there is more than one.
+    pub fn report(&self) {
+        if self.entries.len() < 2 {
+            return;
+        }
+        for entry in &self.entries {
+            info!(
+                "Endpoint {}: {} connection(s) delivered data, {} ended in failure",
+                entry.url, entry.successes, entry.failures
+            );
+        }
+    }
+}
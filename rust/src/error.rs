@@ -0,0 +1,147 @@
+//! Classified errors and exit codes for the example binaries.
+//!
+//! Library functions return the crate-wide boxed [`Error`](crate::Error);
+//! binaries classify whatever reaches `main` with [`CliError::from_error`]
+//! and exit with a code per kind, so scripts can branch on the failure:
+//!
+//! | kind             | exit code |
+//! |------------------|-----------|
+//! | `other`          | 1         |
+//! | `bad_args`       | 2         |
+//! | `connect_failed` | 3         |
+//! | `auth_rejected`  | 4         |
+//! | `stream_ended`   | 5         |
+//! | `s3_error`       | 6         |
+//! | `outage`         | 7         |
+
+use std::fmt;
+
+use tonic::Code;
+
+#[derive(Debug)]
+pub enum CliError {
+    /// Could not connect, or gave up reconnecting
+    ConnectFailed(String),
+    /// The server rejected the token
+    AuthRejected(String),
+    /// The server terminated the stream with an error
+    StreamEnded(String),
+    /// An S3 request failed
+    S3Error(String),
+    /// Reconnecting went on longer than `--max-outage-secs`
+    Outage(String),
+    /// Invalid or conflicting arguments
+    BadArgs(String),
+    /// Anything else
+    Other(String),
+}
+
+impl CliError {
+    /// Classify an error returned from the library.
+    pub fn from_error(error: crate::Error) -> Self {
+        let error = match error.downcast::<CliError>() {
+            Ok(cli) => return *cli,
+            Err(error) => error,
+        };
+        if let Some(status) = error.downcast_ref::<tonic::Status>() {
+            return Self::from_status(status);
+        }
+        if error.is::<tonic::transport::Error>() {
+            return Self::ConnectFailed(error.to_string());
+        }
+        if error.is::<aws_sdk_s3::Error>() {
+            return Self::S3Error(error.to_string());
+        }
+        Self::Other(error.to_string())
+    }
+
+    /// Classify a gRPC status. Auth rejections get a message pointing at the
+    /// token rather than the bare status, and oversized messages one
+    /// pointing at `--max-recv-mb`.
+    pub fn from_status(status: &tonic::Status) -> Self {
+        let message = format!("{:?}: {}", status.code(), status.message());
+        if is_message_too_large(status) {
+            return Self::StreamEnded(format!(
+                "a message was larger than the receive limit; raise it with --max-recv-mb ({})",
+                message
+            ));
+        }
+        match status.code() {
+            Code::Unauthenticated | Code::PermissionDenied => Self::AuthRejected(format!(
+                "authentication failed — check your QuickNode token ({})",
+                message
+            )),
+            Code::Unavailable => Self::ConnectFailed(message),
+            _ => Self::StreamEnded(message),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::ConnectFailed(_) => "connect_failed",
+            Self::AuthRejected(_) => "auth_rejected",
+            Self::StreamEnded(_) => "stream_ended",
+            Self::S3Error(_) => "s3_error",
+            Self::Outage(_) => "outage",
+            Self::BadArgs(_) => "bad_args",
+            Self::Other(_) => "other",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Other(_) => 1,
+            Self::BadArgs(_) => 2,
+            Self::ConnectFailed(_) => 3,
+            Self::AuthRejected(_) => 4,
+            Self::StreamEnded(_) => 5,
+            Self::S3Error(_) => 6,
+            Self::Outage(_) => 7,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Self::ConnectFailed(m)
+            | Self::AuthRejected(m)
+            | Self::StreamEnded(m)
+            | Self::S3Error(m)
+            | Self::Outage(m)
+            | Self::BadArgs(m)
+            | Self::Other(m) => m,
+        }
+    }
+
+    /// Print the error to stderr, as a `{"error":...,"kind":...}` line when
+    /// `json` is set, and exit with its code.
+    pub fn exit(self, json: bool) -> ! {
+        if json {
+            eprintln!(
+                "{}",
+                serde_json::json!({ "error": self.message(), "kind": self.kind() })
+            );
+        } else {
+            eprintln!("Error: {}", self);
+        }
+        std::process::exit(self.exit_code())
+    }
+}
+
+/// Whether `status` reports a message over a size limit: tonic's own
+/// receive limit (`OUT_OF_RANGE`), or the server's (`RESOURCE_EXHAUSTED`).
+/// Retrying would only fetch the same message again.
+pub fn is_message_too_large(status: &tonic::Status) -> bool {
+    let message = status.message().to_ascii_lowercase();
+    matches!(status.code(), Code::OutOfRange | Code::ResourceExhausted)
+        && ["too large", "larger than max"]
+            .iter()
+            .any(|hint| message.contains(hint))
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for CliError {}
@@ -0,0 +1,146 @@
+//! CSV export for spreadsheet-friendly analysis.
+//!
+//! Missing fields are written as empty cells rather than failing the row, so
+//! a schema change upstream degrades the output instead of stopping it.
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::compressed::{OutputCompression, OutputFile};
+use crate::hyperliquid::L2BookUpdate;
+use crate::price::mid_and_spread_bps;
+use crate::records::{field_str, split_records};
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+/// Error for a write after `flush` finished the file
+const FINISHED: &str = "CSV file already finished";
+
+/// Writes one row per trade: `block_number,timestamp,coin,px,sz,side,hash`.
+pub struct TradeCsvWriter {
+    /// Taken by `flush` to finish the file
+    writer: Option<csv::Writer<OutputFile>>,
+}
+
+impl TradeCsvWriter {
+    /// Create `path`, compressed as [`OutputFile::create`] decides.
+    pub fn create(path: &Path, compression: Option<OutputCompression>) -> Result<Self> {
+        let mut writer = csv::Writer::from_writer(OutputFile::create(path, compression)?);
+        writer.write_record([
+            "block_number",
+            "timestamp",
+            "coin",
+            "px",
+            "sz",
+            "side",
+            "hash",
+        ])?;
+        Ok(Self {
+            writer: Some(writer),
+        })
+    }
+
+    /// Write every trade in a decoded TRADES payload.
+    pub fn write_payload(
+        &mut self,
+        block_number: u64,
+        timestamp: u64,
+        payload: &Value,
+    ) -> Result<()> {
+        let writer = self.writer.as_mut().ok_or(FINISHED)?;
+        for trade in split_records(payload) {
+            let field = |name| field_str(trade, name).unwrap_or_default();
+            writer.write_record([
+                block_number.to_string(),
+                timestamp.to_string(),
+                field("coin"),
+                field("px"),
+                field("sz"),
+                field("side"),
+                field("hash"),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Write out every row and finish the file. Call once, at the end.
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok(file.finish()?)
+    }
+}
+
+#[async_trait]
+impl RecordSink for TradeCsvWriter {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        self.write_payload(record.block_number, record.timestamp, record.payload)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        TradeCsvWriter::flush(self)
+    }
+}
+
+/// Writes one top-of-book row per L2 update:
+/// `block,time,coin,best_bid,best_ask,mid,spread_bps`.
+pub struct L2CsvWriter {
+    /// Taken by `flush` to finish the file
+    writer: Option<csv::Writer<OutputFile>>,
+}
+
+impl L2CsvWriter {
+    /// Create `path`, compressed as [`OutputFile::create`] decides.
+    pub fn create(path: &Path, compression: Option<OutputCompression>) -> Result<Self> {
+        let mut writer = csv::Writer::from_writer(OutputFile::create(path, compression)?);
+        writer.write_record([
+            "block",
+            "time",
+            "coin",
+            "best_bid",
+            "best_ask",
+            "mid",
+            "spread_bps",
+        ])?;
+        Ok(Self {
+            writer: Some(writer),
+        })
+    }
+
+    /// Write the top of book for `update`. Mid and spread are left empty
+    /// when either side is empty or unparseable.
+    pub fn write_update(&mut self, update: &L2BookUpdate) -> Result<()> {
+        let best_bid = update.bids.first().map(|l| l.px.as_str()).unwrap_or("");
+        let best_ask = update.asks.first().map(|l| l.px.as_str()).unwrap_or("");
+
+        let (mid, spread_bps) = match mid_and_spread_bps(best_bid, best_ask) {
+            Some((mid, spread_bps)) => (mid.to_string(), format!("{:.2}", spread_bps)),
+            None => (String::new(), String::new()),
+        };
+
+        let writer = self.writer.as_mut().ok_or(FINISHED)?;
+        writer.write_record([
+            update.block_number.to_string(),
+            update.time.to_string(),
+            update.coin.clone(),
+            best_bid.to_string(),
+            best_ask.to_string(),
+            mid,
+            spread_bps,
+        ])?;
+        Ok(())
+    }
+
+    /// Write out every row and finish the file. Call once, at the end.
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok(file.finish()?)
+    }
+}
@@ -0,0 +1,130 @@
+//! Detect blocks the BLOCKS stream skipped.
+//!
+//! Every block produces a BLOCKS message, so a `block_number` more than one
+//! past the previous one means blocks were never delivered. The other stream
+//! types only send blocks that have matching records, so jumps there are
+//! normal and aren't checked. Each gap is logged with the missing range as
+//! it's seen and counted; with [`OnGap::Backfill`] the raw stream fetches
+//! the missing blocks from the S3 archive ([`crate::s3`]) and writes them out
+//! before the block that revealed the gap.
+
+use std::ops::RangeInclusive;
+
+use clap::ValueEnum;
+use tracing::warn;
+
+use crate::hyperliquid::StreamType;
+
+/// What the raw stream does about a gap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OnGap {
+    /// Log the missing range and carry on
+    #[default]
+    Warn,
+    /// Also fetch the missing blocks from S3 and write them out first
+    Backfill,
+}
+
+/// Whether `stream_type` delivers a message for every block.
+pub fn expects_every_block(stream_type: StreamType) -> bool {
+    stream_type == StreamType::Blocks
+}
+
+/// Tracks the last block seen and the gaps found so far.
+#[derive(Debug, Default)]
+pub struct GapDetector {
+    last: Option<u64>,
+    gaps: u64,
+    missing: u64,
+    backfilled: u64,
+}
+
+impl GapDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect `block` to come first: a later first block is a gap.
+    pub fn starting_at(block: u64) -> Self {
+        Self {
+            last: block.checked_sub(1),
+            ..Self::default()
+        }
+    }
+
+    /// Note a data message for `block_number`, returning the blocks skipped
+    /// since the previous one. A repeated or older block (the boundary block
+    /// resent after a reconnect) is not a gap.
+    pub fn observe(&mut self, block_number: u64) -> Option<RangeInclusive<u64>> {
+        let last = self.last;
+        self.last = Some(last.map_or(block_number, |last| last.max(block_number)));
+        let missing = last
+            .filter(|&last| block_number > last + 1)
+            .map(|last| last + 1..=block_number - 1)?;
+        self.gaps += 1;
+        self.missing += block_number - missing.start();
+        warn!(
+            "Gap in the stream: block(s) {}-{} missing ({} block(s))",
+            missing.start(),
+            missing.end(),
+            block_number - missing.start()
+        );
+        Some(missing)
+    }
+
+    /// Note that `count` missing blocks were fetched and written out.
+    pub fn backfilled(&mut self, count: u64) {
+        self.backfilled += count;
+    }
+
+    /// Gaps seen so far
+    pub fn gaps(&self) -> u64 {
+        self.gaps
+    }
+
+    /// Log the gaps found, if any.
+    pub fn report(&self) {
+        if self.gaps > 0 {
+            warn!(
+                "{} gap(s) in the stream, {} block(s) missing, {} backfilled from S3",
+                self.gaps, self.missing, self.backfilled
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_skipped_block_range_is_reported() {
+        let mut gaps = GapDetector::new();
+
+        assert_eq!(gaps.observe(100), None);
+        assert_eq!(gaps.observe(101), None);
+        assert_eq!(gaps.observe(104), Some(102..=103));
+        assert_eq!(gaps.gaps(), 1);
+        assert_eq!(gaps.missing, 2);
+    }
+
+    #[test]
+    fn repeated_and_older_blocks_are_not_gaps() {
+        let mut gaps = GapDetector::new();
+
+        assert_eq!(gaps.observe(100), None);
+        assert_eq!(gaps.observe(100), None);
+        assert_eq!(gaps.observe(99), None);
+        assert_eq!(gaps.observe(101), None);
+        assert_eq!(gaps.gaps(), 0);
+    }
+
+    #[test]
+    fn a_late_first_block_is_a_gap_from_the_start() {
+        let mut gaps = GapDetector::starting_at(100);
+
+        assert_eq!(gaps.observe(103), Some(100..=102));
+        assert_eq!(gaps.gaps(), 1);
+        assert_eq!(GapDetector::starting_at(0).observe(0), None);
+    }
+}
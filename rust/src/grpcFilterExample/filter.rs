@@ -0,0 +1,71 @@
+//! The filtering example (`filter_example`, and `hyperliquid filter`):
+//! stream only the trades for a handful of coins.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use hyperliquid_grpc::common::ChannelArgs;
+use hyperliquid_grpc::error::CliError;
+use hyperliquid_grpc::hyperliquid::StreamType;
+use hyperliquid_grpc::reconnect::BreakerArgs;
+use hyperliquid_grpc::stream::{read_coins_file, stream_data, StreamOptions};
+use hyperliquid_grpc::Result;
+use tracing::info;
+
+#[derive(clap::Args)]
+pub struct FilterArgs {
+    /// Stream the coins listed in this file (one per line, # comments
+    /// allowed) instead of ETH and BTC
+    #[arg(long, value_name = "PATH")]
+    coins_file: Option<PathBuf>,
+
+    /// Name sent with the filter (`filter_name`), and again on every
+    /// reconnect. The server keeps it only for the session
+    #[arg(long, value_name = "NAME", default_value = "eth-btc-trades")]
+    filter_name: String,
+
+    /// Print throughput instead of the trades themselves
+    #[arg(long)]
+    count_only: bool,
+
+    /// Reconnect after N seconds without a message
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    read_timeout_secs: Option<u64>,
+
+    #[command(flatten)]
+    breaker: BreakerArgs,
+
+    #[command(flatten)]
+    channel: ChannelArgs,
+}
+
+/// Run with `endpoints` as `(endpoint, token)` pairs; reconnects rotate
+/// through them.
+pub async fn run(args: FilterArgs, endpoints: &[(String, String)]) -> Result<()> {
+    // Subscribe to TRADES with filters
+    let mut filters = HashMap::new();
+    // Filter for specific coins only: ETH and BTC, or the coins listed in
+    // --coins-file
+    let coins = match &args.coins_file {
+        Some(path) => read_coins_file(path).map_err(|e| CliError::BadArgs(e.to_string()))?,
+        None => vec!["ETH".to_string(), "BTC".to_string()],
+    };
+    filters.insert("coin".to_string(), coins.clone());
+
+    // The named filter and coin filter are resent on every reconnect, which
+    // resumes from the last block seen
+    let mut options = StreamOptions::with_endpoints(endpoints)?;
+    options.channel = args.channel.options()?;
+    options.filter_name = args.filter_name;
+    options.count_only = args.count_only;
+    if let Some(secs) = args.read_timeout_secs {
+        options.read_timeout = Duration::from_secs(secs);
+    }
+    options.breaker = args.breaker.breaker();
+    options.max_outage = args.breaker.max_outage();
+
+    info!("Streaming TRADES filtered by coin: {}", coins.join(", "));
+
+    stream_data(StreamType::Trades, filters, &options).await
+}
@@ -0,0 +1,143 @@
+//! One-shot connectivity check for the raw data stream.
+//!
+//! Connects, subscribes and sends a ping straight away, then waits for the
+//! first message of any kind: a `Pong` proves the endpoint and token work
+//! even on a filtered stream that is currently quiet. Failures are
+//! classified as [`CliError`]s so the exit code says what went wrong.
+
+use std::collections::HashMap;
+use std::error::Error as _;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::common::{authed_request, build_endpoint, streaming_client, validate_token};
+use crate::error::CliError;
+use crate::hyperliquid::{self, FilterValues, Ping, StreamSubscribe, StreamType, SubscribeRequest};
+use crate::source::Received;
+use crate::stream::StreamOptions;
+use crate::Result;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first message received by [`healthcheck`].
+#[derive(Debug, Clone, Copy)]
+pub enum FirstMessage {
+    Pong,
+    Data { block_number: u64 },
+}
+
+/// Subscribe to `stream_type` and wait up to `timeout` for the first
+/// message, returning it with the time it took (connect included).
+pub async fn healthcheck(
+    stream_type: StreamType,
+    filters: HashMap<String, Vec<String>>,
+    options: &StreamOptions,
+    timeout: Duration,
+) -> Result<(FirstMessage, Duration)> {
+    validate_token(&options.token, options.channel.auth)?;
+    let endpoint = build_endpoint(&options.endpoint, &options.channel)?;
+    let started = Instant::now();
+
+    let check = async {
+        let channel = endpoint
+            .connect()
+            .await
+            .map_err(|e| CliError::ConnectFailed(describe_connect_error(&e)))?;
+        let mut client = streaming_client(channel, &options.channel);
+
+        let (tx, rx) = mpsc::channel(2);
+        tx.send(SubscribeRequest {
+            request: Some(hyperliquid::subscribe_request::Request::Subscribe(
+                StreamSubscribe {
+                    stream_type: stream_type as i32,
+                    start_block: 0,
+                    filters: filters
+                        .into_iter()
+                        .map(|(field, values)| (field, FilterValues { values }))
+                        .collect(),
+                    filter_name: options.filter_name.clone(),
+                },
+            )),
+        })
+        .await?;
+        tx.send(SubscribeRequest {
+            request: Some(hyperliquid::subscribe_request::Request::Ping(Ping {
+                timestamp: chrono::Utc::now().timestamp_millis(),
+            })),
+        })
+        .await?;
+        let request = authed_request(
+            ReceiverStream::new(rx),
+            &options.token,
+            options.channel.auth,
+        )?;
+
+        let mut stream = client.stream_data(request).await?.into_inner();
+        loop {
+            match stream.message().await? {
+                Some(update) => match Received::from_update(update) {
+                    Received::Pong(_) => return Ok::<_, crate::Error>(FirstMessage::Pong),
+                    Received::Data(data) => {
+                        return Ok(FirstMessage::Data {
+                            block_number: data.block_number,
+                        })
+                    }
+                    Received::Unknown => {}
+                },
+                None => {
+                    return Err(CliError::StreamEnded(
+                        "stream closed before the first message".to_string(),
+                    )
+                    .into())
+                }
+            }
+        }
+    };
+
+    match tokio::time::timeout(timeout, check).await {
+        Ok(result) => Ok((result?, started.elapsed())),
+        Err(_) => Err(CliError::StreamEnded(format!(
+            "no Pong or data within {}s",
+            timeout.as_secs_f64()
+        ))
+        .into()),
+    }
+}
+
+/// Name the likely cause of a failed connect (DNS, TLS, refused) from the
+/// transport error's source chain, which is otherwise just
+/// "transport error". Each layer repeats the one below, so only the root
+/// cause is kept.
+pub(crate) fn describe_connect_error(error: &tonic::transport::Error) -> String {
+    let mut chain = Vec::new();
+    let mut source = error.source();
+    while let Some(e) = source {
+        chain.push(e.to_string().to_lowercase());
+        source = e.source();
+    }
+    let has = |needle: &str| chain.iter().any(|e| e.contains(needle));
+
+    let cause = if has("dns") {
+        "DNS lookup failed"
+    } else if has("certificate") || has("tls") || has("handshake") {
+        "TLS handshake failed"
+    } else if has("refused") {
+        "connection refused"
+    } else {
+        "connect failed"
+    };
+    match error.source().map(root_cause) {
+        Some(root) => format!("{}: {}", cause, root),
+        None => format!("{}: {}", cause, error),
+    }
+}
+
+fn root_cause(mut error: &(dyn std::error::Error + 'static)) -> String {
+    while let Some(source) = error.source() {
+        error = source;
+    }
+    error.to_string()
+}
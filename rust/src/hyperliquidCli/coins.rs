@@ -0,0 +1,57 @@
+//! `hyperliquid coins`: sample the unfiltered TRADES stream for a few
+//! seconds and list the coins that traded, most active first, to pick
+//! symbols before setting up filters.
+
+use std::time::Duration;
+
+use hyperliquid_grpc::coins::{sample_coins, DEFAULT_SAMPLE};
+use hyperliquid_grpc::common::ChannelArgs;
+use hyperliquid_grpc::stream::StreamOptions;
+use hyperliquid_grpc::Result;
+
+#[derive(clap::Args)]
+pub struct CoinsArgs {
+    /// How long to sample trades for
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_SAMPLE.as_secs(),
+          value_parser = clap::value_parser!(u64).range(1..))]
+    sample_secs: u64,
+
+    /// Print a JSON array of {"coin","trades"} instead of a table
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    channel: ChannelArgs,
+}
+
+/// Run with `endpoints` as `(endpoint, token)` pairs; only the first is
+/// used.
+pub async fn run(args: CoinsArgs, endpoints: &[(String, String)]) -> Result<()> {
+    let mut options = StreamOptions::with_endpoints(endpoints)?;
+    options.channel = args.channel.options()?;
+
+    let window = Duration::from_secs(args.sample_secs);
+    let counts = sample_coins(&options, window).await?;
+    if args.json {
+        println!("{}", counts.to_json());
+        return Ok(());
+    }
+
+    let coins = counts.sorted();
+    if coins.is_empty() {
+        println!("No trades in {}s", args.sample_secs);
+        return Ok(());
+    }
+    let width = coins.iter().map(|(coin, _)| coin.len()).max().unwrap_or(0);
+    println!("{:<width$}  TRADES", "COIN", width = width.max(4));
+    for (coin, trades) in &coins {
+        println!("{:<width$}  {}", coin, trades, width = width.max(4));
+    }
+    println!(
+        "{} coin(s), {} trade(s) in {}s",
+        coins.len(),
+        counts.total(),
+        args.sample_secs
+    );
+    Ok(())
+}
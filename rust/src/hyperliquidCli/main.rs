@@ -0,0 +1,116 @@
+//! Every example behind one binary: `hyperliquid stream`, `filter`,
+//! `orderbook` and `backfill` take the same flags as `main`,
+//! `filter_example`, `orderbookStreamExample` and `s3_blocks_backfill`,
+//! with the endpoint, token and logging options given once up front.
+//! `verify` compares gRPC blocks with their S3 copies and `coins` lists the
+//! coins trading right now; neither has a standalone binary.
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use hyperliquid_grpc::common::read_token_file;
+use hyperliquid_grpc::endpoints::pair_tokens;
+use hyperliquid_grpc::error::CliError;
+use hyperliquid_grpc::logging::{self, LogLevel};
+use hyperliquid_grpc::Result;
+
+#[path = "../replicaCmdsOnS3Example/backfill.rs"]
+pub mod backfill;
+mod coins;
+#[path = "../grpcFilterExample/filter.rs"]
+mod filter;
+#[path = "../orderbookStreamExample/orderbook.rs"]
+mod orderbook;
+#[path = "../grpcRawDataExample/stream.rs"]
+mod stream;
+mod verify;
+
+#[derive(Parser)]
+#[command(name = "hyperliquid")]
+#[command(about = "Hyperliquid gRPC streaming and S3 backfill")]
+struct Cli {
+    /// gRPC endpoint, e.g. https://your-endpoint.hype-mainnet.quiknode.pro:10000.
+    /// Repeat to fail over: each reconnect moves to the next endpoint,
+    /// preferring ones that haven't been failing
+    #[arg(long, global = true, value_name = "URL")]
+    endpoint: Vec<String>,
+
+    /// QuickNode auth token. Give it once to use it for every endpoint, or
+    /// repeat it once per --endpoint, in the same order
+    #[arg(long, global = true)]
+    token: Vec<String>,
+
+    /// Read the auth token from this file instead, keeping it out of the
+    /// process list and shell history. Trailing whitespace is trimmed.
+    /// Repeats like --token
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "token")]
+    token_file: Vec<PathBuf>,
+
+    /// Diagnostics level; data always goes to stdout
+    #[arg(long, global = true, value_enum, default_value = "info")]
+    log_level: LogLevel,
+
+    /// On failure, print a {"error":...,"kind":...} JSON line to stderr
+    /// instead of text. The exit code reflects the kind either way
+    #[arg(long, global = true)]
+    json_errors: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Stream raw data for one or more stream types (the `main` example)
+    Stream(Box<stream::StreamArgs>),
+    /// Stream trades for a few coins under a named filter (`filter_example`)
+    Filter(filter::FilterArgs),
+    /// Stream L2 or L4 orderbooks (`orderbookStreamExample`)
+    Orderbook(orderbook::OrderbookArgs),
+    /// Read historical blocks from the node-data S3 bucket
+    /// (`s3_blocks_backfill`)
+    Backfill(backfill::BackfillArgs),
+    /// Compare a range of blocks from the gRPC BLOCKS stream with their
+    /// copies in the S3 archive
+    Verify(verify::VerifyArgs),
+    /// List the coins trading right now, most active first, from a short
+    /// sample of the TRADES stream
+    Coins(coins::CoinsArgs),
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let color = !matches!(&cli.command, Command::Orderbook(args) if args.no_color);
+    logging::init_with_color(cli.log_level, color);
+
+    let json_errors = cli.json_errors;
+    if let Err(e) = run(cli).await {
+        CliError::from_error(e).exit(json_errors);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let Cli {
+        endpoint,
+        mut token,
+        token_file,
+        command,
+        ..
+    } = cli;
+    if !token_file.is_empty() {
+        token = token_file
+            .iter()
+            .map(|path| read_token_file(path))
+            .collect::<Result<_, _>>()?;
+    }
+
+    match command {
+        Command::Stream(args) => stream::run(*args, &pair_tokens(endpoint, token)?).await,
+        Command::Filter(args) => filter::run(args, &pair_tokens(endpoint, token)?).await,
+        Command::Orderbook(args) => orderbook::run(args, &pair_tokens(endpoint, token)?).await,
+        Command::Backfill(args) => Ok(backfill::run(args).await?),
+        Command::Verify(args) => verify::run(args, &pair_tokens(endpoint, token)?).await,
+        Command::Coins(args) => coins::run(args, &pair_tokens(endpoint, token)?).await,
+    }
+}
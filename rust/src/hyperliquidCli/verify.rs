@@ -0,0 +1,151 @@
+//! `hyperliquid verify`: compare a range of blocks received over the gRPC
+//! BLOCKS stream with the same blocks in the S3 archive, to catch delivery
+//! bugs. The range has to be both still retained by the endpoint and
+//! already archived; finding a block in S3 means reading its multi-GB file
+//! from the start.
+
+use std::collections::BTreeMap;
+
+use hyperliquid_grpc::common::ChannelArgs;
+use hyperliquid_grpc::error::CliError;
+use hyperliquid_grpc::s3::{backfill_range, Bucket, MAINNET_BUCKET};
+use hyperliquid_grpc::stream::StreamOptions;
+use hyperliquid_grpc::verify::{compare_blocks, fetch_grpc_blocks};
+use hyperliquid_grpc::Result;
+use tracing::info;
+
+#[derive(clap::Args)]
+pub struct VerifyArgs {
+    /// First block to compare
+    #[arg(long, value_name = "N")]
+    start_block: u64,
+
+    /// Last block to compare, inclusive (default: --start-block)
+    #[arg(long, value_name = "N")]
+    end_block: Option<u64>,
+
+    /// Bucket holding the archive
+    #[arg(long, value_name = "NAME", default_value = MAINNET_BUCKET)]
+    bucket: String,
+
+    /// Whether --bucket is requester-pays; false for mirrors that reject
+    /// the requester-pays header
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    requester_pays: bool,
+
+    /// Differences to print for the first mismatched block
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    max_diffs: usize,
+
+    /// Report S3 download progress and ETA on stderr
+    #[arg(long)]
+    progress: bool,
+
+    #[command(flatten)]
+    channel: ChannelArgs,
+}
+
+/// Run with `endpoints` as `(endpoint, token)` pairs; only the first is
+/// used.
+pub async fn run(args: VerifyArgs, endpoints: &[(String, String)]) -> Result<()> {
+    let start = args.start_block;
+    let end = args.end_block.unwrap_or(start);
+    if end < start {
+        return Err(CliError::BadArgs(format!(
+            "--end-block {} is before --start-block {}",
+            end, start
+        ))
+        .into());
+    }
+    let mut options = StreamOptions::with_endpoints(endpoints)?;
+    options.channel = args.channel.options()?;
+
+    // gRPC first: the endpoint only retains recent blocks
+    let grpc = fetch_grpc_blocks(&options, start, end).await?;
+    info!("Received {} block(s) over gRPC", grpc.len());
+
+    let bucket = Bucket {
+        name: args.bucket,
+        requester_pays: args.requester_pays,
+    };
+    info!("Fetching blocks {} to {} from {}", start, end, bucket);
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let client = aws_sdk_s3::Client::new(&config);
+    let mut s3 = BTreeMap::new();
+    backfill_range(&client, &bucket, start, end, args.progress, None, |block| {
+        s3.insert(block.block_number, block.data);
+    })
+    .await
+    .map_err(|e| CliError::S3Error(format!("error fetching blocks {} to {}: {}", start, end, e)))?;
+    info!("Read {} block(s) from S3", s3.len());
+
+    let report = compare_blocks(&grpc, &s3, args.max_diffs);
+    println!(
+        "Compared {} block(s): {} mismatch(es)",
+        report.compared,
+        report.mismatches.len()
+    );
+    if !report.grpc_only.is_empty() {
+        println!(
+            "Only over gRPC ({}): {}",
+            report.grpc_only.len(),
+            block_list(&report.grpc_only)
+        );
+    }
+    if !report.s3_only.is_empty() {
+        println!(
+            "Only in S3 ({}): {}",
+            report.s3_only.len(),
+            block_list(&report.s3_only)
+        );
+    }
+    if let Some(first) = report.mismatches.first() {
+        println!("\nFirst mismatch, block {}:", first.block_number);
+        for difference in &first.differences {
+            println!("  {}", difference);
+        }
+        if report.mismatches.len() > 1 {
+            println!(
+                "Other mismatched blocks: {}",
+                block_list(
+                    &report.mismatches[1..]
+                        .iter()
+                        .map(|m| m.block_number)
+                        .collect::<Vec<_>>()
+                )
+            );
+        }
+    }
+
+    if report.compared == 0 {
+        return Err(CliError::Other(format!(
+            "no block in {} to {} was found in both",
+            start, end
+        ))
+        .into());
+    }
+    if !report.mismatches.is_empty() {
+        return Err(CliError::Other(format!(
+            "{} of {} block(s) differ",
+            report.mismatches.len(),
+            report.compared
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Block numbers for a summary line, the first few of a long list.
+fn block_list(blocks: &[u64]) -> String {
+    const SHOWN: usize = 10;
+    let mut list = blocks
+        .iter()
+        .take(SHOWN)
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if blocks.len() > SHOWN {
+        list.push_str(&format!(", ... ({} more)", blocks.len() - SHOWN));
+    }
+    list
+}
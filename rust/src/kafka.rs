@@ -0,0 +1,136 @@
+//! Kafka sink for decoded stream messages (behind the `kafka` feature).
+//!
+//! Each message is published as compact JSON, keyed by its `coin` when it
+//! has one and by block number otherwise. At most `MAX_IN_FLIGHT` messages
+//! await delivery at a time, so a slow broker applies backpressure to the
+//! stream instead of buffering without bound.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::util::Timeout;
+use tracing::{info, warn};
+
+use crate::records::{field_str, split_records};
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+const MAX_IN_FLIGHT: usize = 1000;
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+    in_flight: FuturesUnordered<DeliveryFuture>,
+    failed: u64,
+}
+
+impl KafkaSink {
+    pub fn new(brokers: &str, topic: &str) -> Result<Self> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "30000")
+            .create()?;
+        info!("Publishing to Kafka topic {} on {}", topic, brokers);
+
+        Ok(Self {
+            producer,
+            topic: topic.to_string(),
+            in_flight: FuturesUnordered::new(),
+            failed: 0,
+        })
+    }
+
+    /// Queue `payload` for delivery, first waiting for an earlier delivery
+    /// to complete if the in-flight limit is reached.
+    pub async fn publish(&mut self, key: &str, payload: &str) -> Result<()> {
+        let Self {
+            producer,
+            topic,
+            in_flight,
+            failed,
+        } = self;
+
+        while in_flight.len() >= MAX_IN_FLIGHT {
+            complete_one(in_flight, failed).await;
+        }
+
+        let mut record = FutureRecord::to(topic).key(key).payload(payload);
+        loop {
+            match producer.send_result(record) {
+                Ok(delivery) => {
+                    in_flight.push(delivery);
+                    return Ok(());
+                }
+                // librdkafka's local queue is full: wait for a delivery and retry
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+                    record = returned;
+                    if in_flight.is_empty() {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    } else {
+                        complete_one(in_flight, failed).await;
+                    }
+                }
+                Err((e, _)) => return Err(Box::new(e)),
+            }
+        }
+    }
+
+    /// Wait for outstanding deliveries, giving up after a timeout.
+    pub async fn flush(&mut self) -> Result<()> {
+        let drained = tokio::time::timeout(FLUSH_TIMEOUT, async {
+            while !self.in_flight.is_empty() {
+                complete_one(&mut self.in_flight, &mut self.failed).await;
+            }
+        })
+        .await;
+        if drained.is_err() {
+            warn!(
+                "Kafka flush timed out with {} message(s) undelivered",
+                self.in_flight.len()
+            );
+        }
+        self.producer.flush(Timeout::After(FLUSH_TIMEOUT))?;
+        if self.failed > 0 {
+            warn!("Kafka: {} message(s) failed delivery", self.failed);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RecordSink for KafkaSink {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        // Key by coin when the message has one, else by block
+        let key = split_records(record.payload)
+            .first()
+            .and_then(|item| field_str(item, "coin"))
+            .unwrap_or_else(|| record.block_number.to_string());
+        self.publish(&key, &record.payload.to_string()).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        KafkaSink::flush(self).await
+    }
+}
+
+/// Wait for one delivery to finish, counting it if it failed.
+async fn complete_one(in_flight: &mut FuturesUnordered<DeliveryFuture>, failed: &mut u64) {
+    match in_flight.next().await {
+        Some(Ok(Ok(_))) | None => {}
+        Some(Ok(Err((e, _)))) => {
+            *failed += 1;
+            warn!("Kafka delivery failed: {}", e);
+        }
+        Some(Err(_)) => {
+            *failed += 1;
+            warn!("Kafka delivery cancelled");
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Shared plumbing for the Hyperliquid gRPC examples: generated protobuf
+//! types plus the channel, auth and decompression helpers every streaming
+//! example needs.
+
+pub mod book;
+pub mod buffer;
+pub mod candles;
+pub mod checkpoint;
+pub mod client_filter;
+pub mod coins;
+pub mod common;
+pub mod compressed;
+pub mod dedup;
+pub mod endpoints;
+pub mod error;
+pub mod export;
+pub mod gaps;
+pub mod health;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod logging;
+pub mod multiplex;
+#[cfg(feature = "parquet")]
+pub mod parquet_export;
+#[cfg(feature = "postgres")]
+pub mod postgres_sink;
+pub mod price;
+pub mod projection;
+pub mod recent;
+pub mod reconnect;
+pub mod records;
+pub mod reflection;
+pub mod replay;
+pub mod report;
+pub mod s3;
+pub mod schema;
+pub mod since;
+pub mod sink;
+pub mod source;
+pub mod split;
+pub mod stats;
+pub mod stream;
+pub mod twap;
+pub mod typed;
+#[cfg(unix)]
+pub mod uds;
+pub mod verify;
+pub mod ws;
+
+pub mod hyperliquid {
+    tonic::include_proto!("hyperliquid");
+
+    /// Encoded descriptors of the compiled-in protos
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("hyperliquid_descriptor");
+}
+
+/// Error type used across the examples. `Send + Sync` so streams can run
+/// inside spawned tasks.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,51 @@
+//! Diagnostics via `tracing`, written to stderr so stdout carries only
+//! stream data. ANSI colors are only used when stderr is a terminal.
+
+use std::io::IsTerminal;
+
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Install the stderr subscriber. Call once, at the start of `main`.
+pub fn init(level: LogLevel) {
+    init_with_color(level, true);
+}
+
+/// [`init`], with colors turned off entirely when `color` is false (e.g. for
+/// a `--no-color` flag).
+pub fn init_with_color(level: LogLevel, color: bool) {
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .with_ansi(color && color_allowed(&std::io::stderr()))
+        .init();
+}
+
+/// Whether decoration suits `stream`: it is a terminal and `NO_COLOR` isn't
+/// set.
+pub fn color_allowed(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
@@ -0,0 +1,566 @@
+//! Mock Hyperliquid gRPC server
+//! ============================
+//!
+//! Serves the `Streaming` and `OrderBookStreaming` services on a local port
+//! with made-up data, so the clients in this repo can be run without a
+//! QuickNode endpoint. Each connection is cut after a fixed number of
+//! messages with `DATA_LOSS` (what the real server sends when it
+//! reinitializes), so the reconnect path runs every few seconds.
+//!
+//! Every subscription is logged with its start block, which is how a
+//! reconnect resuming from the last block seen can be checked; pings are
+//! answered with pongs, and `--skip-every` drops blocks from the BLOCKS
+//! stream to trigger gap warnings. Several subscriptions on one connection
+//! are all served, unless `--single-subscription` rejects the extra ones
+//! like servers that only allow one.
+//!
+//! USAGE:
+//! ------
+//!   cargo run --bin mock_server -- --port 50051
+//!   cargo run --bin hyperliquid -- --endpoint http://127.0.0.1:50051 --token test stream -s TRADES --sample 20
+//!   cargo run --bin hyperliquid -- --endpoint http://127.0.0.1:50051 --token test orderbook --coin BTC --compact
+//!
+//! The token is only required to be present; any value is accepted.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use futures::Stream;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+use hyperliquid_grpc::book::book_checksum;
+use hyperliquid_grpc::hyperliquid::{
+    self, l4_book_update,
+    order_book_streaming_server::{OrderBookStreaming, OrderBookStreamingServer},
+    streaming_server::{Streaming, StreamingServer},
+    L2BookRequest, L2BookUpdate, L2Level, L4BookDiff, L4BookRequest, L4BookSnapshot, L4BookUpdate,
+    L4Order, PingRequest, PingResponse, Pong, StreamResponse, StreamSubscribe, StreamType,
+    SubscribeRequest, SubscribeUpdate, FILE_DESCRIPTOR_SET,
+};
+use hyperliquid_grpc::logging::{self, LogLevel};
+
+/// First block served to a subscription from the live tip
+const FIRST_BLOCK: u64 = 1_000;
+/// Price the mock books are centred on
+const MID_PX: u64 = 97_000;
+/// Block time of block 0; each block is 100 ms after the previous one, so a
+/// block resent after a reconnect has the same payload
+const GENESIS_MS: i64 = 1_700_000_000_000;
+
+#[derive(Parser, Clone)]
+#[command(name = "mock_server")]
+#[command(about = "Local mock of the Hyperliquid gRPC services")]
+struct Args {
+    /// Port to listen on (127.0.0.1)
+    #[arg(long, default_value_t = 50051)]
+    port: u16,
+
+    /// Milliseconds between blocks
+    #[arg(long, value_name = "N", default_value_t = 200)]
+    interval_ms: u64,
+
+    /// End each connection with DATA_LOSS after this many messages
+    /// (0 = never)
+    #[arg(long, value_name = "N", default_value_t = 25)]
+    fail_after: u64,
+
+    /// On the BLOCKS stream, leave out every Nth block
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(2..))]
+    skip_every: Option<u64>,
+
+    /// Pad every data payload with N bytes of trailing whitespace, e.g. to
+    /// exercise --max-recv-mb
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pad_bytes: usize,
+
+    /// Don't serve gRPC server reflection, like endpoints that don't
+    /// support it
+    #[arg(long)]
+    no_reflection: bool,
+
+    /// End a connection with INVALID_ARGUMENT when it subscribes a second
+    /// time, like servers that allow one subscription per connection
+    #[arg(long)]
+    single_subscription: bool,
+
+    /// Diagnostics level
+    #[arg(long, value_enum, default_value = "info")]
+    log_level: LogLevel,
+}
+
+type UpdateStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// State shared by every connection.
+struct Mock {
+    args: Args,
+    /// Highest block served so far; a subscription from the live tip starts
+    /// after it
+    tip: AtomicU64,
+    connections: AtomicU64,
+}
+
+impl Mock {
+    /// Number a new connection, or `None` if it didn't send a token
+    fn connection(&self, request_token: Option<&str>) -> Option<u64> {
+        if request_token.is_none_or(str::is_empty) {
+            return None;
+        }
+        Some(self.connections.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    /// Where a subscription from `start_block` begins: the block itself, or
+    /// just past the tip for 0
+    fn first_block(&self, start_block: u64) -> u64 {
+        match start_block {
+            0 => self.tip.load(Ordering::Relaxed).max(FIRST_BLOCK - 1) + 1,
+            block => block,
+        }
+    }
+
+    fn served(&self, block: u64) {
+        self.tip.fetch_max(block, Ordering::Relaxed);
+    }
+
+    fn interval(&self) -> tokio::time::Interval {
+        tokio::time::interval(Duration::from_millis(self.args.interval_ms.max(1)))
+    }
+
+    /// Whether a connection that has sent `sent` messages should now fail
+    fn should_fail(&self, sent: u64) -> bool {
+        self.args.fail_after > 0 && sent >= self.args.fail_after
+    }
+}
+
+/// The `x-token` header, or the token of an `authorization: Bearer` one
+fn token<T>(request: &Request<T>) -> Option<&str> {
+    let metadata = request.metadata();
+    let header = |name| metadata.get(name).and_then(|value| value.to_str().ok());
+    header("x-token").or_else(|| header("authorization")?.strip_prefix("Bearer "))
+}
+
+fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+/// The payload of one block's message for `stream_type`
+fn payload(stream_type: StreamType, block: u64) -> String {
+    let time = chrono::DateTime::from_timestamp_millis(GENESIS_MS + block as i64 * 100)
+        .unwrap_or_default();
+    let side = if block.is_multiple_of(2) { "B" } else { "A" };
+    let px = format!("{}.{}", MID_PX + block % 7, block % 10);
+    match stream_type {
+        StreamType::Blocks => json!({
+            "abci_block": {
+                "time": time.naive_utc().format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+                "round": block,
+            },
+        }),
+        StreamType::Trades => json!([{
+            "coin": "BTC",
+            "side": side,
+            "px": px,
+            "sz": "0.01",
+            "time": time.timestamp_millis(),
+            "hash": format!("0x{:064x}", block),
+            "tid": block * 10,
+            "user": format!("0x{:040x}", block % 3),
+        }]),
+        // Three TWAPs at a time, each stepping through ten states; executed
+        // size moves every other step, and the last step finishes it
+        StreamType::Twap => {
+            let step = (block / 3) % 10 + 1;
+            let executed = if step == 10 { 10 } else { step / 2 * 2 };
+            let twap_id = (block / 30) * 3 + block % 3;
+            json!([{
+                "time": time.timestamp_millis(),
+                "twap_id": twap_id,
+                "state": {
+                    "coin": "BTC",
+                    "user": format!("0x{:040x}", block % 3),
+                    "side": if twap_id.is_multiple_of(2) { "B" } else { "A" },
+                    "sz": "10.0",
+                    "executedSz": format!("{}.0", executed),
+                    "executedNtl": format!("{}", executed * MID_PX),
+                    "minutes": 30,
+                    "reduceOnly": false,
+                    "randomize": false,
+                    "timestamp": GENESIS_MS,
+                },
+                "status": if step == 10 { "finished" } else { "activated" },
+            }])
+        }
+        StreamType::Orders => json!([{
+            "time": time.timestamp_millis(),
+            "user": format!("0x{:040x}", block % 3),
+            "status": "open",
+            "order": {
+                "coin": "BTC",
+                "side": side,
+                "limitPx": px,
+                "sz": "0.01",
+                "origSz": "0.01",
+                "oid": block,
+                "timestamp": time.timestamp_millis(),
+            },
+        }]),
+        StreamType::BookUpdates => json!([{
+            "coin": "BTC",
+            "side": side,
+            "px": px,
+            "user": format!("0x{:040x}", block % 3),
+            "oid": block,
+            "raw_book_diff": { "new": { "sz": "0.01" } },
+        }]),
+        StreamType::Events => json!({
+            "events": [[
+                format!("0x{:040x}", block % 3),
+                { "type": "deposit", "usdc": "100.0", "time": time.timestamp_millis() },
+            ]],
+        }),
+        StreamType::WriterActions => json!([{
+            "user": format!("0x{:040x}", block % 3),
+            "nonce": block,
+            "action": { "type": "systemSpotSendAction", "token": "USDC", "wei": "100" },
+        }]),
+        _ => json!([{
+            "coin": "BTC",
+            "side": side,
+            "px": px,
+            "sz": "0.01",
+            "user": format!("0x{:040x}", block % 3),
+            "block": block,
+        }]),
+    }
+    .to_string()
+}
+
+/// Send `subscribe`'s blocks on `tx` until the client goes away, or end
+/// the connection with DATA_LOSS after `--fail-after` of them.
+fn serve_subscription(
+    mock: Arc<Mock>,
+    id: u64,
+    subscribe: StreamSubscribe,
+    tx: mpsc::Sender<Result<SubscribeUpdate, Status>>,
+) {
+    let stream_type = StreamType::try_from(subscribe.stream_type).unwrap_or_default();
+    let first = mock.first_block(subscribe.start_block);
+    info!(
+        "Connection {}: subscribe {} from start_block {} (serving from {}), filters {:?}, filter_name {:?}",
+        id,
+        stream_type.as_str_name(),
+        subscribe.start_block,
+        first,
+        subscribe.filters.keys().collect::<Vec<_>>(),
+        subscribe.filter_name
+    );
+
+    tokio::spawn(async move {
+        let mut interval = mock.interval();
+        let mut block = first;
+        let mut sent = 0;
+        loop {
+            interval.tick().await;
+            if mock.should_fail(sent) {
+                info!("Connection {}: DATA_LOSS after {} message(s)", id, sent);
+                let _ = tx
+                    .send(Err(Status::data_loss("mock server reinitialized")))
+                    .await;
+                break;
+            }
+            let skipped = stream_type == StreamType::Blocks
+                && mock
+                    .args
+                    .skip_every
+                    .is_some_and(|n| block.is_multiple_of(n));
+            if !skipped {
+                let data = SubscribeUpdate {
+                    update: Some(hyperliquid::subscribe_update::Update::Data(
+                        StreamResponse {
+                            block_number: block,
+                            timestamp: now_ms(),
+                            data: payload(stream_type, block) + &" ".repeat(mock.args.pad_bytes),
+                        },
+                    )),
+                };
+                if tx.send(Ok(data)).await.is_err() {
+                    info!("Connection {}: client went away", id);
+                    break;
+                }
+                sent += 1;
+            }
+            mock.served(block);
+            block += 1;
+        }
+    });
+}
+
+#[derive(Clone)]
+struct StreamingService(Arc<Mock>);
+
+#[tonic::async_trait]
+impl Streaming for StreamingService {
+    type StreamDataStream = UpdateStream<SubscribeUpdate>;
+
+    async fn stream_data(
+        &self,
+        request: Request<tonic::Streaming<SubscribeRequest>>,
+    ) -> Result<Response<Self::StreamDataStream>, Status> {
+        let id = self
+            .0
+            .connection(token(&request))
+            .ok_or_else(|| Status::unauthenticated("missing x-token or bearer token"))?;
+        let mut requests = request.into_inner();
+        let subscribe = loop {
+            match requests.message().await? {
+                Some(SubscribeRequest {
+                    request: Some(hyperliquid::subscribe_request::Request::Subscribe(subscribe)),
+                }) => break subscribe,
+                Some(_) => continue,
+                None => return Err(Status::invalid_argument("no subscription sent")),
+            }
+        };
+        let (tx, rx) = mpsc::channel(16);
+        serve_subscription(self.0.clone(), id, subscribe, tx.clone());
+
+        // Answer pings, and serve further subscriptions, for as long as the
+        // client keeps the request stream open
+        let mock = self.0.clone();
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = requests.message().await {
+                match request.request {
+                    Some(hyperliquid::subscribe_request::Request::Ping(ping)) => {
+                        info!("Connection {}: ping {}", id, ping.timestamp);
+                        let pong = SubscribeUpdate {
+                            update: Some(hyperliquid::subscribe_update::Update::Pong(Pong {
+                                timestamp: ping.timestamp,
+                            })),
+                        };
+                        if tx.send(Ok(pong)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(hyperliquid::subscribe_request::Request::Subscribe(_))
+                        if mock.args.single_subscription =>
+                    {
+                        info!("Connection {}: rejecting a second subscription", id);
+                        let _ = tx
+                            .send(Err(Status::invalid_argument(
+                                "only one subscription per connection",
+                            )))
+                            .await;
+                        break;
+                    }
+                    Some(hyperliquid::subscribe_request::Request::Subscribe(subscribe)) => {
+                        serve_subscription(mock.clone(), id, subscribe, tx.clone());
+                    }
+                    None => {}
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PingResponse>, Status> {
+        Ok(Response::new(PingResponse {
+            count: request.into_inner().count,
+        }))
+    }
+}
+
+/// `n` L2 levels stepping away from the mid by `step`
+fn levels(n: u32, mid: u64, step: i64) -> Vec<L2Level> {
+    (1..=n as i64)
+        .map(|i| L2Level {
+            px: (mid as i64 + step * i).to_string(),
+            sz: format!("{}.{}", i, i % 10),
+            n: i as u32,
+        })
+        .collect()
+}
+
+fn l4_order(coin: &str, side: &str, px: u64, oid: u64) -> L4Order {
+    L4Order {
+        user: format!("0x{:040x}", oid % 3),
+        coin: coin.to_string(),
+        side: side.to_string(),
+        limit_px: px.to_string(),
+        sz: "0.5".to_string(),
+        oid,
+        timestamp: now_ms(),
+        trigger_condition: "N/A".to_string(),
+        is_trigger: false,
+        trigger_px: "0.0".to_string(),
+        is_position_tpsl: false,
+        reduce_only: false,
+        order_type: "Limit".to_string(),
+        tif: Some("Gtc".to_string()),
+        cloid: None,
+    }
+}
+
+#[derive(Clone)]
+struct OrderBookService(Arc<Mock>);
+
+#[tonic::async_trait]
+impl OrderBookStreaming for OrderBookService {
+    type StreamL2BookStream = UpdateStream<L2BookUpdate>;
+    type StreamL4BookStream = UpdateStream<L4BookUpdate>;
+
+    async fn stream_l2_book(
+        &self,
+        request: Request<L2BookRequest>,
+    ) -> Result<Response<Self::StreamL2BookStream>, Status> {
+        let id = self
+            .0
+            .connection(token(&request))
+            .ok_or_else(|| Status::unauthenticated("missing x-token or bearer token"))?;
+        let request = request.into_inner();
+        let n_levels = match request.n_levels {
+            0 => 20,
+            n => n.min(100),
+        };
+        info!(
+            "Connection {}: L2 {} ({} levels)",
+            id, request.coin, n_levels
+        );
+
+        let (tx, rx) = mpsc::channel(16);
+        let mock = self.0.clone();
+        tokio::spawn(async move {
+            let mut interval = mock.interval();
+            let mut block = mock.first_block(0);
+            let mut sent = 0;
+            loop {
+                interval.tick().await;
+                if mock.should_fail(sent) {
+                    info!("Connection {}: DATA_LOSS after {} message(s)", id, sent);
+                    let _ = tx
+                        .send(Err(Status::data_loss("mock server reinitialized")))
+                        .await;
+                    break;
+                }
+                let mid = MID_PX + block % 5;
+                let (bids, asks) = (levels(n_levels, mid, -1), levels(n_levels, mid, 1));
+                let update = L2BookUpdate {
+                    coin: request.coin.clone(),
+                    time: now_ms(),
+                    block_number: block,
+                    checksum: Some(book_checksum(&bids, &asks)),
+                    bids,
+                    asks,
+                };
+                if tx.send(Ok(update)).await.is_err() {
+                    break;
+                }
+                sent += 1;
+                mock.served(block);
+                block += 1;
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn stream_l4_book(
+        &self,
+        request: Request<L4BookRequest>,
+    ) -> Result<Response<Self::StreamL4BookStream>, Status> {
+        let id = self
+            .0
+            .connection(token(&request))
+            .ok_or_else(|| Status::unauthenticated("missing x-token or bearer token"))?;
+        let coin = request.into_inner().coin;
+        info!("Connection {}: L4 {}", id, coin);
+
+        let (tx, rx) = mpsc::channel(16);
+        let mock = self.0.clone();
+        tokio::spawn(async move {
+            let mut interval = mock.interval();
+            let mut block = mock.first_block(0);
+            let snapshot = L4BookUpdate {
+                update: Some(l4_book_update::Update::Snapshot(L4BookSnapshot {
+                    coin: coin.clone(),
+                    time: now_ms(),
+                    height: block,
+                    bids: (1..=3)
+                        .map(|i| l4_order(&coin, "B", MID_PX - i, i))
+                        .collect(),
+                    asks: (1..=3)
+                        .map(|i| l4_order(&coin, "A", MID_PX + i, 10 + i))
+                        .collect(),
+                })),
+            };
+            if tx.send(Ok(snapshot)).await.is_err() {
+                return;
+            }
+            let mut sent = 1;
+            loop {
+                interval.tick().await;
+                if mock.should_fail(sent) {
+                    info!("Connection {}: DATA_LOSS after {} message(s)", id, sent);
+                    let _ = tx
+                        .send(Err(Status::data_loss("mock server reinitialized")))
+                        .await;
+                    break;
+                }
+                block += 1;
+                let diff = L4BookUpdate {
+                    update: Some(l4_book_update::Update::Diff(L4BookDiff {
+                        time: now_ms(),
+                        height: block,
+                        data: json!({"order_statuses": [], "book_diffs": []}).to_string(),
+                    })),
+                };
+                if tx.send(Ok(diff)).await.is_err() {
+                    break;
+                }
+                sent += 1;
+                mock.served(block);
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    logging::init(args.log_level);
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], args.port));
+    let reflection = match args.no_reflection {
+        true => None,
+        false => Some(
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+                .build()?,
+        ),
+    };
+    let mock = Arc::new(Mock {
+        args,
+        tip: AtomicU64::new(0),
+        connections: AtomicU64::new(0),
+    });
+    info!("Mock server listening on http://{}", addr);
+
+    Server::builder()
+        .add_service(StreamingServer::new(StreamingService(mock.clone())))
+        .add_service(OrderBookStreamingServer::new(OrderBookService(mock)))
+        .add_optional_service(reflection)
+        .serve_with_shutdown(addr, async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
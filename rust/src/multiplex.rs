@@ -0,0 +1,663 @@
+//! Several stream types over one `StreamData` connection.
+//!
+//! The request side of `StreamData` is a stream, so one connection can
+//! carry a `StreamSubscribe` per type, with a single keep-alive and a single
+//! reconnect loop. Responses don't name the subscription they answer, so
+//! each is attributed by the shape of its records (see [`payload_types`]).
+//!
+//! Not every server accepts more than one subscription per connection.
+//! [`stream_multiplexed_until`] falls back to one connection per type when
+//! the server rejects the extra subscriptions, when an unfiltered type
+//! sends nothing while the others stream, or when a payload can't be
+//! attributed to exactly one type. Each type then carries on from the last
+//! block it reached, with the outputs it already had open.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::future::join_all;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Code, Status};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::common::{authed_request, decompress_with, streaming_client, PayloadEncoding, ZstdDict};
+use crate::endpoints::EndpointPool;
+use crate::gaps::{expects_every_block, GapDetector};
+use crate::hyperliquid::{
+    self, FilterValues, StreamResponse, StreamSubscribe, StreamType, SubscribeRequest,
+};
+use crate::reconnect::{RetryBudget, BASE_DELAY_SECS, MAX_RETRIES};
+use crate::records::split_records;
+use crate::report::{redact, ExitReason, ReconnectEvent};
+use crate::source::{GrpcSource, MessageSource};
+use crate::stream::{
+    disconnected, handle_message, run_connections, start_at_tip, until_ctrl_c, Attempt,
+    CancellationToken, Output, PingTask, Pipeline, Progress, StreamOptions, StreamSummary,
+};
+use crate::Result;
+
+/// How long an unfiltered type may stay silent after the first message of
+/// any other type before the server is taken to serve only one
+/// subscription per connection
+const SILENT_SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Stream every type in `stream_types` until Ctrl-C, over one connection
+/// where the server allows it. See [`stream_multiplexed_until`].
+pub async fn stream_multiplexed(
+    stream_types: &[StreamType],
+    filters: HashMap<String, Vec<String>>,
+    options: &StreamOptions,
+) -> Result<()> {
+    until_ctrl_c(|cancel| stream_multiplexed_until(stream_types, filters, options, cancel))
+        .await
+        .map(|_| ())
+}
+
+/// Subscribe to every type in `stream_types` on one connection, sending
+/// each type's records to its own outputs, until `cancel` is cancelled or
+/// every type is done. Falls back to one connection per type as described
+/// in the [module docs](self). Returns a summary per type, in order; a type
+/// that fails doesn't stop the others, and the first failure is returned
+/// once they have all stopped.
+pub async fn stream_multiplexed_until(
+    stream_types: &[StreamType],
+    filters: HashMap<String, Vec<String>>,
+    options: &StreamOptions,
+    cancel: CancellationToken,
+) -> Result<Vec<StreamSummary>> {
+    let mut pool = EndpointPool::new(&options.endpoints(), &options.channel)?;
+    let mut outputs = Vec::with_capacity(stream_types.len());
+    for &stream_type in stream_types {
+        outputs.push(Pipeline::new(
+            Output::new(stream_type, &filters, options).await?,
+            options,
+        ));
+    }
+    let mut demux = Demux::new(stream_types, options)?;
+
+    // Block heights are the chain's, so one tip serves every subscription
+    let mut options = options.clone();
+    let tip = start_at_tip(&mut options, &cancel).await?;
+    let options = &options;
+    let mut lanes: Vec<_> = stream_types
+        .iter()
+        .zip(outputs)
+        .map(|(&stream_type, output)| {
+            let mut progress = Progress::new(
+                expects_every_block(stream_type)
+                    .then(|| tip.map_or_else(GapDetector::new, GapDetector::starting_at)),
+            );
+            if options.tail {
+                progress.tail_from = tip;
+            }
+            Lane {
+                stream_type,
+                progress,
+                output,
+                seen: false,
+                done: false,
+            }
+        })
+        .collect();
+
+    if !filters.is_empty() {
+        info!("Filters applied: {:?}", filters);
+    }
+    let probe = filters.is_empty();
+    let filters: HashMap<String, FilterValues> = filters
+        .into_iter()
+        .map(|(field, values)| (field, FilterValues { values }))
+        .collect();
+    let names = stream_types
+        .iter()
+        .map(|stream_type| stream_type.as_str_name())
+        .collect::<Vec<_>>()
+        .join(",");
+    match tip {
+        Some(block) => info!(
+            "Streaming {} live from block {} over one connection...",
+            names, block
+        ),
+        None => info!("Streaming {} over one connection...", names),
+    }
+
+    let mut budget = RetryBudget::new(options.breaker).with_max_outage(options.max_outage);
+    loop {
+        if cancel.is_cancelled() {
+            break;
+        }
+        if budget.attempt() > 1 {
+            info!(
+                "Reconnecting (attempt {}/{})...",
+                budget.attempt(),
+                MAX_RETRIES
+            );
+        }
+
+        let span = info_span!(
+            "connection",
+            stream = names.as_str(),
+            retry = budget.attempt() - 1,
+            endpoint = pool.url()
+        );
+        let shared = stream_shared(
+            &pool, &mut lanes, &mut demux, &filters, options, probe, &cancel,
+        )
+        .instrument(span)
+        .await;
+
+        let (reason, code, received, base_delay_secs) = match shared {
+            Ok(Shared::Ended(Attempt::Done)) => break,
+            Ok(Shared::Ended(Attempt::StartUnavailable { block, reason })) => {
+                let reason = format!("start block {} is not available ({})", block, reason);
+                return fall_back(lanes, &filters, options, &reason, &cancel).await;
+            }
+            Ok(Shared::Unsupported(reason)) => {
+                return fall_back(lanes, &filters, options, &reason, &cancel).await;
+            }
+            Ok(Shared::Ended(Attempt::Retry {
+                reason,
+                code,
+                received,
+                base_delay_secs,
+            })) => (reason, code, received, base_delay_secs),
+            Err(e) => {
+                finish(lanes, options, &cancel).await?;
+                return Err(e);
+            }
+        };
+
+        // Every subscription shares the connection, so each one reconnects
+        if received {
+            budget.reset();
+            pool.succeeded();
+        }
+        pool.failed();
+        let delay = budget.failed(base_delay_secs);
+        let message = redact(
+            &reason,
+            options.endpoints().iter().map(|(_, token)| token.as_str()),
+        );
+        warn!(
+            stream = names.as_str(),
+            retry = budget.attempt() - 1,
+            code = code.map(|code| format!("{:?}", code)),
+            delay_ms = delay.as_ref().ok().map(|delay| delay.as_millis() as u64),
+            "{}",
+            message
+        );
+        for lane in &mut lanes {
+            lane.output.reconnected();
+            lane.progress.reconnects += 1;
+            if options.report.is_some() {
+                lane.progress.reconnect_events.push(ReconnectEvent {
+                    at_ms: chrono::Utc::now().timestamp_millis(),
+                    stream_type: lane.stream_type,
+                    retry: budget.attempt() - 1,
+                    code,
+                    message: message.clone(),
+                    delay: delay.as_ref().ok().copied(),
+                    last_block: lane.progress.last_block,
+                });
+            }
+        }
+        let delay = match delay {
+            Ok(delay) => delay,
+            Err(give_up) => {
+                pool.report();
+                for lane in lanes {
+                    lane.progress.report();
+                    let compression = lane.output.finish().await?;
+                    lane.progress.write_run_report(
+                        lane.stream_type,
+                        options,
+                        &compression,
+                        give_up.into(),
+                    )?;
+                }
+                return Err(give_up.error(&reason).into());
+            }
+        };
+        info!("Waiting {:.1}s before reconnecting...", delay.as_secs_f64());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = cancel.cancelled() => info!("Interrupted, shutting down"),
+        }
+    }
+
+    pool.report();
+    finish(lanes, options, &cancel).await
+}
+
+/// One subscription on the shared connection.
+struct Lane {
+    stream_type: StreamType,
+    progress: Progress,
+    output: Pipeline,
+    /// Had a message attributed to it, on any connection
+    seen: bool,
+    /// Reached `sample` or `to_block`; later messages for it are dropped
+    done: bool,
+}
+
+/// How a shared connection ended.
+enum Shared {
+    Ended(Attempt),
+    /// The server can't carry every subscription on one connection
+    Unsupported(String),
+}
+
+/// Run one shared connection until it ends, fails, or turns out not to
+/// carry every subscription.
+async fn stream_shared(
+    pool: &EndpointPool,
+    lanes: &mut [Lane],
+    demux: &mut Demux,
+    filters: &HashMap<String, FilterValues>,
+    options: &StreamOptions,
+    probe: bool,
+    cancel: &CancellationToken,
+) -> Result<Shared> {
+    let connected = tokio::select! {
+        connected = pool.connect() => connected,
+        _ = cancel.cancelled() => {
+            info!("Interrupted, shutting down");
+            return Ok(Shared::Ended(Attempt::Done));
+        }
+    };
+    let channel = match connected {
+        Ok(channel) => channel,
+        Err(e) => {
+            return Ok(Shared::Ended(Attempt::Retry {
+                reason: format!("Connection failed: {}", e),
+                code: None,
+                received: false,
+                base_delay_secs: BASE_DELAY_SECS,
+            }))
+        }
+    };
+    let mut client = streaming_client(channel, &options.channel);
+
+    // One subscription per type still streaming, each resuming from the
+    // last block it saw
+    let (tx, rx) = mpsc::channel(options.request_capacity.max(lanes.len()));
+    for lane in lanes.iter().filter(|lane| !lane.done) {
+        let subscribe = StreamSubscribe {
+            stream_type: lane.stream_type as i32,
+            start_block: lane
+                .progress
+                .last_block
+                .or(options.start_block)
+                .unwrap_or(0),
+            filters: filters.clone(),
+            filter_name: options.filter_name.clone(),
+        };
+        tx.send(SubscribeRequest {
+            request: Some(hyperliquid::subscribe_request::Request::Subscribe(
+                subscribe,
+            )),
+        })
+        .await?;
+    }
+
+    // One keep-alive for the whole connection
+    let _ping = PingTask::spawn(tx.clone());
+
+    let request = authed_request(ReceiverStream::new(rx), pool.token(), pool.auth())?;
+    let requested_start = options
+        .start_block
+        .filter(|&block| block > 0 && lanes.iter().all(|lane| lane.progress.last_block.is_none()));
+
+    let subscribed = tokio::select! {
+        subscribed = client.stream_data(request) => subscribed,
+        _ = cancel.cancelled() => {
+            info!("Interrupted, shutting down");
+            return Ok(Shared::Ended(Attempt::Done));
+        }
+    };
+    let response_stream = match subscribed {
+        Ok(response) => response.into_inner(),
+        Err(status) => return ended(status, false, requested_start),
+    };
+    let mut source =
+        GrpcSource::new(response_stream, options.read_timeout).print_pongs(options.proto_dump);
+
+    // Only unfiltered streams are sure to send data, so only they can show
+    // that a subscription was ignored
+    let mut deadline = None;
+    let mut probing = probe && lanes.iter().any(|lane| !lane.seen);
+    loop {
+        let silent_until = deadline.unwrap_or_else(Instant::now);
+        let next = tokio::select! {
+            next = source.next() => next,
+            _ = tokio::time::sleep_until(silent_until), if deadline.is_some() => {
+                let silent: Vec<_> = lanes
+                    .iter()
+                    .filter(|lane| !lane.seen)
+                    .map(|lane| lane.stream_type.as_str_name())
+                    .collect();
+                if silent.is_empty() {
+                    deadline = None;
+                    probing = false;
+                    continue;
+                }
+                return Ok(Shared::Unsupported(format!(
+                    "no {} data within {}s of the other subscriptions",
+                    silent.join(", "),
+                    SILENT_SUBSCRIPTION_TIMEOUT.as_secs()
+                )));
+            }
+            _ = cancel.cancelled() => {
+                info!("Interrupted, shutting down");
+                return Ok(Shared::Ended(Attempt::Done));
+            }
+        };
+        let data = match next {
+            Ok(Some(data)) => data,
+            Ok(None) => return Ok(Shared::Ended(Attempt::Done)),
+            Err(e) => match e.downcast::<Status>() {
+                Ok(status) => return ended(*status, source.received(), requested_start),
+                Err(e) => return Err(e),
+            },
+        };
+        if probing && deadline.is_none() {
+            deadline = Some(Instant::now() + SILENT_SUBSCRIPTION_TIMEOUT);
+        }
+
+        let lane = match demux.route(&data) {
+            Ok(Some(index)) => &mut lanes[index],
+            // Nothing in it to attribute or write
+            Ok(None) => continue,
+            Err(reason) => return Ok(Shared::Unsupported(reason)),
+        };
+        lane.seen = true;
+        if lane.done {
+            continue;
+        }
+        if handle_message(data, options, &mut lane.progress, &mut lane.output).await? {
+            lane.done = true;
+            if lanes.iter().all(|lane| lane.done) {
+                return Ok(Shared::Ended(Attempt::Done));
+            }
+        }
+    }
+}
+
+/// How the shared connection ended with `status`. Every subscription is
+/// sent up front, so a rejection (even after the first type started
+/// streaming) is taken to mean the server allows only one per connection.
+/// If it was really about one type's request, that type's own connection
+/// fails the same way after the fallback. Other statuses are handled as for
+/// a single type.
+fn ended(status: Status, received: bool, requested_start: Option<u64>) -> Result<Shared> {
+    let rejected = matches!(
+        status.code(),
+        Code::InvalidArgument
+            | Code::FailedPrecondition
+            | Code::AlreadyExists
+            | Code::Unimplemented
+    );
+    if rejected {
+        return Ok(Shared::Unsupported(format!(
+            "server rejected the subscriptions ({:?}): {}",
+            status.code(),
+            status.message()
+        )));
+    }
+    disconnected(status, received, requested_start).map(Shared::Ended)
+}
+
+/// Hand each type still streaming its own connection, picking up where the
+/// shared one left off.
+async fn fall_back(
+    lanes: Vec<Lane>,
+    filters: &HashMap<String, FilterValues>,
+    options: &StreamOptions,
+    reason: &str,
+    cancel: &CancellationToken,
+) -> Result<Vec<StreamSummary>> {
+    warn!(
+        "Can't stream every type over one connection ({}), using one connection per type",
+        reason
+    );
+    let stream_types: Vec<_> = lanes.iter().map(|lane| lane.stream_type).collect();
+    let mut streams = Vec::with_capacity(lanes.len());
+    for mut lane in lanes {
+        let pool = EndpointPool::new(&options.endpoints(), &options.channel)?;
+        // The last block seen is subscribed again; records already written
+        // from it are dropped
+        lane.output.reconnected();
+        streams.push(async move {
+            if lane.done {
+                return finish(vec![lane], options, cancel)
+                    .await
+                    .map(|mut summaries| summaries.remove(0));
+            }
+            let span = info_span!("stream", stream = lane.stream_type.as_str_name());
+            run_connections(
+                pool,
+                lane.stream_type,
+                filters,
+                options.clone(),
+                lane.progress,
+                lane.output,
+                cancel,
+            )
+            .instrument(span)
+            .await
+        });
+    }
+    summaries(&stream_types, join_all(streams).await)
+}
+
+/// Flush every lane and write its report, as a stream that stopped cleanly.
+async fn finish(
+    lanes: Vec<Lane>,
+    options: &StreamOptions,
+    cancel: &CancellationToken,
+) -> Result<Vec<StreamSummary>> {
+    let mut summaries = Vec::with_capacity(lanes.len());
+    for lane in lanes {
+        lane.progress.report();
+        let compression = lane.output.finish().await?;
+        let exit = match cancel.is_cancelled() {
+            true => ExitReason::Interrupted,
+            false => lane.progress.exit,
+        };
+        lane.progress
+            .write_run_report(lane.stream_type, options, &compression, exit)?;
+        summaries.push(lane.progress.summary(lane.stream_type, cancel));
+    }
+    Ok(summaries)
+}
+
+/// Every summary, or the first error once each stream has stopped.
+fn summaries(
+    stream_types: &[StreamType],
+    results: Vec<Result<StreamSummary>>,
+) -> Result<Vec<StreamSummary>> {
+    let mut summaries = Vec::with_capacity(results.len());
+    let mut first_error = None;
+    for (stream_type, result) in stream_types.iter().zip(results) {
+        match result {
+            Ok(summary) => summaries.push(summary),
+            Err(e) => {
+                error!("{} stream failed: {}", stream_type.as_str_name(), e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(summaries), Err)
+}
+
+/// Attributes each message on the shared connection to a subscription.
+struct Demux {
+    stream_types: Vec<StreamType>,
+    payload_encoding: PayloadEncoding,
+    zstd_dict: Option<ZstdDict>,
+    /// Decompression buffer reused across messages
+    scratch: Vec<u8>,
+}
+
+impl Demux {
+    fn new(stream_types: &[StreamType], options: &StreamOptions) -> Result<Self> {
+        Ok(Self {
+            stream_types: stream_types.to_vec(),
+            payload_encoding: options.payload_encoding,
+            zstd_dict: options
+                .zstd_dict
+                .as_deref()
+                .map(ZstdDict::load)
+                .transpose()?,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// The index of the subscription `data` belongs to, `None` if it has no
+    /// records, or why it can't be told.
+    fn route(&mut self, data: &StreamResponse) -> Result<Option<usize>, String> {
+        let wire = data.data.as_bytes();
+        let text: &[u8] = if self.payload_encoding == PayloadEncoding::Plain {
+            wire
+        } else {
+            decompress_with(wire, &mut self.scratch, self.zstd_dict.as_ref())
+                .map_err(|e| format!("block {} could not be decoded: {}", data.block_number, e))?;
+            &self.scratch
+        };
+        let payload: Value = serde_json::from_slice(text)
+            .map_err(|e| format!("block {} is not JSON: {}", data.block_number, e))?;
+        if payload.as_array().is_some_and(Vec::is_empty) {
+            return Ok(None);
+        }
+
+        let candidates: Vec<usize> = payload_types(&payload)
+            .iter()
+            .filter_map(|stream_type| self.stream_types.iter().position(|t| t == stream_type))
+            .collect();
+        match candidates[..] {
+            [index] => Ok(Some(index)),
+            [] => Err(format!(
+                "block {} doesn't look like any subscribed stream type",
+                data.block_number
+            )),
+            _ => Err(format!(
+                "block {} could belong to more than one subscribed stream type",
+                data.block_number
+            )),
+        }
+    }
+}
+
+/// The stream types whose payloads look like `payload`, judged by the
+/// fields of its first record:
+///
+/// - BLOCKS: an `abci_block` object
+/// - TWAP: `twap_id`
+/// - BOOK_UPDATES: `raw_book_diff`
+/// - TRADES: `tid`
+/// - ORDERS: `status` with the order under `order`
+/// - WRITER_ACTIONS: `action`, or a `type`
+/// - EVENTS: a `type`
+///
+/// A record with a top-level `type` could be either of the last two.
+pub fn payload_types(payload: &Value) -> Vec<StreamType> {
+    if payload.get("abci_block").is_some() {
+        return vec![StreamType::Blocks];
+    }
+    let Some(record) = split_records(payload).into_iter().next() else {
+        return Vec::new();
+    };
+    let has = |field| record.get(field).is_some();
+    if has("twap_id") {
+        vec![StreamType::Twap]
+    } else if has("raw_book_diff") {
+        vec![StreamType::BookUpdates]
+    } else if has("tid") {
+        vec![StreamType::Trades]
+    } else if has("order") && has("status") {
+        vec![StreamType::Orders]
+    } else if has("action") {
+        vec![StreamType::WriterActions]
+    } else if has("type") {
+        vec![StreamType::Events, StreamType::WriterActions]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(block_number: u64, payload: Value) -> StreamResponse {
+        StreamResponse {
+            block_number,
+            timestamp: 0,
+            data: payload.to_string(),
+        }
+    }
+
+    fn demux_of(stream_types: &[StreamType]) -> Demux {
+        Demux {
+            stream_types: stream_types.to_vec(),
+            payload_encoding: PayloadEncoding::Auto,
+            zstd_dict: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn payloads_are_told_apart_by_their_records() {
+        let trade = json!([{"coin": "BTC", "side": "B", "px": "1", "sz": "1", "tid": 7}]);
+        let order = json!([{"status": "open", "order": {"coin": "BTC", "oid": 1}}]);
+        let diff = json!([{"coin": "BTC", "oid": 1, "raw_book_diff": "remove"}]);
+        let twap = json!([{"twap_id": 3, "state": {}, "status": "activated"}]);
+        let block = json!({"abci_block": {"round": 1}});
+        let action = json!([{"user": "0x1", "action": {"type": "order"}}]);
+        let event = json!({"events": [["0x1", {"type": "deposit"}]]});
+
+        assert_eq!(payload_types(&trade), [StreamType::Trades]);
+        assert_eq!(payload_types(&order), [StreamType::Orders]);
+        assert_eq!(payload_types(&diff), [StreamType::BookUpdates]);
+        assert_eq!(payload_types(&twap), [StreamType::Twap]);
+        assert_eq!(payload_types(&block), [StreamType::Blocks]);
+        assert_eq!(payload_types(&action), [StreamType::WriterActions]);
+        assert_eq!(
+            payload_types(&event),
+            [StreamType::Events, StreamType::WriterActions]
+        );
+        assert!(payload_types(&json!([{"coin": "BTC"}])).is_empty());
+    }
+
+    #[test]
+    fn messages_go_to_their_subscription() {
+        let mut demux = demux_of(&[StreamType::Orders, StreamType::Trades]);
+        let trade = message(1, json!([{"tid": 7}]));
+        let order = message(2, json!([{"status": "open", "order": {}}]));
+        assert_eq!(demux.route(&trade), Ok(Some(1)));
+        assert_eq!(demux.route(&order), Ok(Some(0)));
+        assert_eq!(demux.route(&message(3, json!([]))), Ok(None));
+    }
+
+    #[test]
+    fn unattributable_messages_are_reported() {
+        let mut demux = demux_of(&[StreamType::Trades, StreamType::Orders]);
+        // A type that wasn't subscribed, and one that isn't JSON
+        assert!(demux.route(&message(1, json!({"abci_block": {}}))).is_err());
+        let garbled = StreamResponse {
+            block_number: 2,
+            timestamp: 0,
+            data: "not json".to_string(),
+        };
+        assert!(demux.route(&garbled).is_err());
+
+        // EVENTS and WRITER_ACTIONS records can both have a top-level type
+        let mut demux = demux_of(&[StreamType::Events, StreamType::WriterActions]);
+        let event = message(3, json!([{"type": "deposit"}]));
+        assert!(demux.route(&event).is_err());
+    }
+}
@@ -0,0 +1,987 @@
+//! The orderbook example (`orderbookStreamExample`, and `hyperliquid
+//! orderbook`): stream L2 and L4 orderbook data via gRPC.
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyperliquid_grpc::book::{book_checksum, order_changes, DepthBook, OrderChange};
+use hyperliquid_grpc::client_filter::{is_hex_address, normalize_address};
+use hyperliquid_grpc::compressed::OutputCompression;
+use hyperliquid_grpc::common::{authed_request, order_book_client, ChannelArgs, ChannelOptions};
+use hyperliquid_grpc::endpoints::EndpointPool;
+use hyperliquid_grpc::error::CliError;
+use hyperliquid_grpc::export::L2CsvWriter;
+use hyperliquid_grpc::hyperliquid::{
+    l4_book_update, L2BookRequest, L2BookUpdate, L2Level, L4BookRequest, L4BookUpdate, L4Order,
+};
+#[cfg(feature = "postgres")]
+use hyperliquid_grpc::postgres_sink::{PgSink, DEFAULT_BATCH_SIZE};
+use hyperliquid_grpc::price::mid_and_spread_bps;
+use hyperliquid_grpc::reconnect::{with_reconnect, BreakerArgs, CircuitBreaker, Flow, Resubscribe, DEFAULT_READ_TIMEOUT};
+use hyperliquid_grpc::Result;
+use tonic::Streaming;
+use tracing::{error, info, info_span, warn, Instrument};
+
+#[derive(clap::Args)]
+pub struct OrderbookArgs {
+    /// Streaming mode
+    #[arg(long, default_value = "l2", value_parser = ["l2", "l4"])]
+    mode: String,
+
+    /// Coin symbol(s) to stream, comma-separated or repeated (default: BTC)
+    #[arg(long, value_delimiter = ',')]
+    coin: Vec<String>,
+
+    /// Number of price levels for L2
+    #[arg(long, default_value_t = 20)]
+    levels: u32,
+
+    /// Significant figures for L2 price bucketing (2-5)
+    #[arg(long, visible_alias = "sig-figs", value_name = "N")]
+    n_sig_figs: Option<u32>,
+
+    /// Mantissa for L2 price bucketing (1, 2, or 5; requires --n-sig-figs)
+    #[arg(long, value_name = "N")]
+    mantissa: Option<u64>,
+
+    /// Stop after N messages (L2 and L4)
+    #[arg(long, value_name = "N")]
+    max_messages: Option<usize>,
+
+    /// Print one line per L2 update: block | coin | bid x sz / ask x sz | spread_bps
+    #[arg(long, conflicts_with = "live")]
+    compact: bool,
+
+    /// Redraw a single coin's L2 book in place on a terminal
+    #[arg(long)]
+    live: bool,
+
+    /// Print each coin's L2 book once it has --levels on both sides, then
+    /// exit; a thinner book is printed as is after a few updates
+    #[arg(long, conflicts_with_all = ["live", "max_messages"])]
+    once: bool,
+
+    /// Write one L2 top-of-book row per update to this CSV file
+    #[arg(long, value_name = "PATH")]
+    csv: Option<PathBuf>,
+
+    /// Compress the --csv file as it is written (default: from its extension, .zst or .gz)
+    #[arg(long, value_enum, requires = "csv")]
+    compress_output: Option<OutputCompression>,
+
+    /// Batch-insert L2 top-of-book rows into Postgres/TimescaleDB at this URL
+    #[cfg(feature = "postgres")]
+    #[arg(long, value_name = "URL")]
+    pg_url: Option<String>,
+
+    /// Table for --pg-url (created if missing)
+    #[cfg(feature = "postgres")]
+    #[arg(long, default_value = "l2_book", requires = "pg_url")]
+    pg_table: String,
+
+    /// Rows per INSERT for --pg-url
+    #[cfg(feature = "postgres")]
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE, requires = "pg_url")]
+    pg_batch_size: usize,
+
+    /// Reconnect when no message arrives for N seconds
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    read_timeout_secs: Option<u64>,
+
+    #[command(flatten)]
+    breaker: BreakerArgs,
+
+    /// Print full user addresses in the L4 snapshot sample
+    #[arg(long)]
+    full_addresses: bool,
+
+    /// For L4, print one line per order each diff touches: + new, - filled
+    /// or cancelled, ~ resized, with side, size, price and oid
+    #[arg(long)]
+    show_orders: bool,
+
+    /// For L4, mark this user's orders in the snapshot sample and print
+    /// every order of theirs each diff touches
+    #[arg(long, value_name = "ADDRESS", value_parser = |s: &str| Ok::<_, String>(normalize_address(s)))]
+    highlight_user: Option<String>,
+
+    /// Print at most N orders per diff with --show-orders
+    #[arg(long, value_name = "N", default_value_t = 20, requires = "show_orders", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_orders_per_diff: usize,
+
+    /// Plain ASCII output and uncolored logs (automatic when not on a terminal)
+    #[arg(long)]
+    pub no_color: bool,
+
+    #[command(flatten)]
+    channel: ChannelArgs,
+}
+
+/// Where and how every coin's task connects.
+#[derive(Clone)]
+struct Connection {
+    /// `(endpoint, token)` pairs, rotated through on reconnect
+    endpoints: Vec<(String, String)>,
+    channel: ChannelOptions,
+    /// Applied to each coin's reconnects separately
+    breaker: Option<CircuitBreaker>,
+    max_outage: Option<Duration>,
+}
+
+/// Whether stdout gets Unicode decoration (rules, check marks). Set once in
+/// `run`; plain ASCII when stdout isn't a terminal or with `--no-color`.
+static FANCY: AtomicBool = AtomicBool::new(true);
+
+/// A horizontal rule `width` characters wide.
+fn rule(width: usize) -> String {
+    if FANCY.load(Ordering::Relaxed) { "─" } else { "-" }.repeat(width)
+}
+
+/// Marker for a status line that reports success.
+fn ok_mark() -> &'static str {
+    if FANCY.load(Ordering::Relaxed) { "✓" } else { "OK:" }
+}
+
+/// Print to stdout, prefixing every non-empty line with `$prefix` so that
+/// output from concurrent per-coin streams stays attributable.
+macro_rules! out {
+    ($prefix:expr, $($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        let mut lines = String::with_capacity(text.len());
+        for line in text.split('\n') {
+            if !line.is_empty() {
+                lines.push_str($prefix);
+            }
+            lines.push_str(line);
+            lines.push('\n');
+        }
+        print!("{}", lines);
+    }};
+}
+
+/// Consecutive checksum mismatches before resubscribing. When no checksum
+/// has matched yet by then, the server is taken to compute them
+/// differently and validation is switched off instead
+const MAX_CHECKSUM_MISMATCHES: usize = 3;
+
+/// L2 updates `--once` waits for a book with `--levels` on both sides
+/// before printing the latest anyway
+const ONCE_MAX_UPDATES: usize = 10;
+
+/// L4 diffs over which references to unknown orders are counted
+const ANOMALY_WINDOW: usize = 50;
+/// More unknown-order references than this within the window means the
+/// local book has drifted, so a fresh snapshot is fetched
+const MAX_ANOMALIES: usize = 10;
+
+/// Unknown-order references per L4 diff over the last [`ANOMALY_WINDOW`]
+/// diffs, plus the running total for the stream.
+struct Anomalies {
+    window: VecDeque<usize>,
+    total: usize,
+}
+
+impl Anomalies {
+    fn new() -> Self {
+        Self { window: VecDeque::with_capacity(ANOMALY_WINDOW), total: 0 }
+    }
+
+    /// Count the unknown oids `book` collected while applying one diff.
+    fn record(&mut self, book: &mut DepthBook) -> Vec<u64> {
+        let unknown = book.take_unknown_oids();
+        self.total += unknown.len();
+        if self.window.len() == ANOMALY_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(unknown.len());
+        unknown
+    }
+
+    /// Forget the window when a new connection starts a fresh book.
+    fn clear(&mut self) {
+        self.window.clear();
+    }
+
+    fn recent(&self) -> usize {
+        self.window.iter().sum()
+    }
+
+    fn drifted(&self) -> bool {
+        self.recent() > MAX_ANOMALIES
+    }
+}
+
+/// How each L2 update is printed
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum L2Display {
+    /// The full book block (default)
+    Verbose,
+    /// One line: `block | coin | bid x sz / ask x sz | spread_bps`
+    Compact,
+    /// The full book redrawn in place on a terminal
+    Live,
+}
+
+/// `block | coin | bid x sz / ask x sz | spread_bps`, with `-` for an empty side.
+fn compact_line(update: &L2BookUpdate) -> String {
+    let side = |level: Option<&L2Level>| level.map_or("-".to_string(), |l| format!("{} x {}", l.px, l.sz));
+    let spread_bps = match (update.bids.first(), update.asks.first()) {
+        (Some(bid), Some(ask)) => mid_and_spread_bps(&bid.px, &ask.px)
+            .map_or("-".to_string(), |(_, spread_bps)| format!("{:.2} bps", spread_bps)),
+        _ => "-".to_string(),
+    };
+    format!(
+        "{} | {} | {} / {} | {}",
+        update.block_number, update.coin, side(update.bids.first()), side(update.asks.first()), spread_bps
+    )
+}
+
+/// L2 stream settings shared by every coin's task.
+#[derive(Clone)]
+struct L2Options {
+    display: L2Display,
+    n_levels: u32,
+    n_sig_figs: Option<u32>,
+    mantissa: Option<u64>,
+    max_messages: Option<usize>,
+    /// Print one full book and stop (`--once`)
+    once: bool,
+    read_timeout: Duration,
+    /// Top-of-book CSV shared by all coins
+    csv: Option<Arc<Mutex<L2CsvWriter>>>,
+    /// Top-of-book Postgres table shared by all coins
+    #[cfg(feature = "postgres")]
+    pg: Option<Arc<tokio::sync::Mutex<PgSink>>>,
+}
+
+/// The full L2 book block: header, top 10 asks, spread, top 10 bids and counters.
+fn print_l2_book(prefix: &str, update: &L2BookUpdate, msg_count: usize, checksum_mismatches: usize) {
+    out!(prefix, "\n{}", rule(60));
+    out!(prefix, "Block: {} | Time: {} | Coin: {}", update.block_number, update.time, update.coin);
+    out!(prefix, "{}", rule(60));
+
+    // Display asks (reversed)
+    if !update.asks.is_empty() {
+        out!(prefix, "\n  ASKS:");
+        let ask_count = update.asks.len().min(10);
+        for level in update.asks.iter().take(ask_count).rev() {
+            out!(prefix, "    {:>12} | {:>12} | ({} orders)", level.px, level.sz, level.n);
+        }
+    }
+
+    // Display spread
+    if !update.bids.is_empty() && !update.asks.is_empty() {
+        out!(prefix, "\n  {}", rule(44));
+        out!(prefix, "  SPREAD: (best bid: {}, best ask: {})", update.bids[0].px, update.asks[0].px);
+        out!(prefix, "  {}", rule(44));
+    }
+
+    // Display bids
+    if !update.bids.is_empty() {
+        out!(prefix, "\n  BIDS:");
+        let bid_count = update.bids.len().min(10);
+        for level in update.bids.iter().take(bid_count) {
+            out!(prefix, "    {:>12} | {:>12} | ({} orders)", level.px, level.sz, level.n);
+        }
+    }
+
+    out!(prefix, "\n  Messages received: {}", msg_count);
+    if checksum_mismatches > 0 {
+        out!(prefix, "  Checksum mismatches: {}", checksum_mismatches);
+    }
+}
+
+/// One coin's L2 subscription, reopened by [`with_reconnect`].
+struct L2Stream<'a> {
+    coin: &'a str,
+    prefix: &'a str,
+    pool: EndpointPool,
+    /// Whether a connection was attempted yet; each later open follows a failure
+    opened: bool,
+    options: &'a L2Options,
+    total_msg_count: usize,
+    checksum_mismatches: usize,
+    consecutive_mismatches: usize,
+    /// Whether any reported checksum has matched the one computed here
+    checksum_matched: bool,
+    /// Set once checksums turn out not to follow [`book_checksum`]'s layout
+    checksum_off: bool,
+}
+
+#[async_trait]
+impl Resubscribe for L2Stream<'_> {
+    type Message = L2BookUpdate;
+    type Stream = Streaming<L2BookUpdate>;
+
+    async fn open(&mut self) -> Result<Self::Stream> {
+        if self.opened {
+            self.pool.failed();
+        }
+        self.opened = true;
+        let mut client = order_book_client(self.pool.connect().await?, self.pool.channel());
+
+        let request = L2BookRequest {
+            coin: self.coin.to_string(),
+            n_levels: self.options.n_levels,
+            n_sig_figs: self.options.n_sig_figs,
+            mantissa: self.options.mantissa,
+        };
+
+        let request_with_metadata = authed_request(request, self.pool.token(), self.pool.auth())?;
+        Ok(client.stream_l2_book(request_with_metadata).await?.into_inner())
+    }
+
+    async fn on_message(&mut self, update: L2BookUpdate, index: usize) -> Result<Flow> {
+        let (prefix, options) = (self.prefix, self.options);
+        let msg_count = index + 1;
+        self.total_msg_count += 1;
+
+        if index == 0 {
+            self.pool.succeeded();
+            info!("First L2 update received");
+            self.consecutive_mismatches = 0;
+        }
+
+        // Validate against the server checksum when one is provided
+        let mut consistent = true;
+        if let Some(reported) = update.checksum.filter(|_| !self.checksum_off) {
+            let computed = book_checksum(&update.bids, &update.asks);
+            if computed != reported {
+                consistent = false;
+                self.checksum_mismatches += 1;
+                self.consecutive_mismatches += 1;
+                warn!(
+                    "Checksum mismatch at block {}: computed {:08x}, reported {:08x}",
+                    update.block_number, computed, reported
+                );
+
+                if self.consecutive_mismatches >= MAX_CHECKSUM_MISMATCHES {
+                    // Resubscribing can't fix checksums that never matched
+                    if !self.checksum_matched {
+                        warn!(
+                            "None of the first {} checksums matched; the server computes them differently, so they are no longer checked",
+                            self.consecutive_mismatches
+                        );
+                        self.checksum_off = true;
+                        consistent = true;
+                    } else {
+                        return Ok(Flow::Reconnect(format!(
+                            "{} consecutive checksum mismatches, resubscribing",
+                            self.consecutive_mismatches
+                        )));
+                    }
+                }
+            } else {
+                self.consecutive_mismatches = 0;
+                self.checksum_matched = true;
+            }
+        }
+
+        // Each update is the whole top of the book, so --once only has to
+        // wait for one that is deep enough and matches its checksum
+        if options.once {
+            let depth = options.n_levels as usize;
+            let full = update.bids.len() >= depth && update.asks.len() >= depth;
+            if !consistent || (!full && self.total_msg_count < ONCE_MAX_UPDATES) {
+                return Ok(Flow::Continue);
+            }
+            if !full {
+                warn!(
+                    "Book has {} bid and {} ask level(s) of {} after {} updates, printing it as is",
+                    update.bids.len(), update.asks.len(), depth, self.total_msg_count
+                );
+            }
+        }
+
+        match options.display {
+            L2Display::Compact => out!(prefix, "{}", compact_line(&update)),
+            L2Display::Live => {
+                // Clear the screen and home the cursor, then redraw
+                print!("\x1b[2J\x1b[H");
+                print_l2_book(prefix, &update, msg_count, self.checksum_mismatches);
+            }
+            L2Display::Verbose => print_l2_book(prefix, &update, msg_count, self.checksum_mismatches),
+        }
+
+        if let Some(csv) = &options.csv {
+            csv.lock().unwrap().write_update(&update)?;
+        }
+        #[cfg(feature = "postgres")]
+        if let Some(pg) = &options.pg {
+            pg.lock().await.write_l2(&update).await?;
+        }
+
+        if options.once {
+            return Ok(Flow::Stop);
+        }
+        if let Some(max) = options.max_messages {
+            if self.total_msg_count >= max {
+                info!("Reached max messages ({}), stopping...", max);
+                return Ok(Flow::Stop);
+            }
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+async fn stream_l2_orderbook(
+    coin: &str,
+    prefix: &str,
+    connection: &Connection,
+    options: &L2Options,
+) -> Result<()> {
+    let L2Options { n_levels, n_sig_figs, mantissa, read_timeout, .. } = *options;
+
+    info!(levels = n_levels, sig_figs = ?n_sig_figs, mantissa = ?mantissa, "Streaming L2 Orderbook for {}", coin);
+
+    // Building the endpoints can't succeed on a retry, so failures there are
+    // fatal. Only connecting is retried.
+    let pool = EndpointPool::new(&connection.endpoints, &connection.channel)?;
+    info!("Connecting to {}...", pool.url());
+
+    let mut stream = L2Stream {
+        coin,
+        prefix,
+        pool,
+        opened: false,
+        options,
+        total_msg_count: 0,
+        checksum_mismatches: 0,
+        consecutive_mismatches: 0,
+        checksum_matched: false,
+        checksum_off: false,
+    };
+    let result = with_reconnect(&mut stream, read_timeout, connection.breaker, connection.max_outage).await;
+    stream.pool.report();
+    result
+}
+
+/// `user` shortened for display. Addresses keep their first 10 characters;
+/// anything else is cut on a char boundary and shown quoted so malformed
+/// values stand out.
+fn short_addr(user: &str) -> String {
+    if is_hex_address(user) {
+        return format!("{}...", &user[..10]);
+    }
+    match user.char_indices().nth(10) {
+        Some((end, _)) => format!("{:?}...", &user[..end]),
+        None => format!("{:?}", user),
+    }
+}
+
+/// L4 stream settings shared by every coin's task.
+#[derive(Clone)]
+struct L4Options {
+    max_messages: Option<usize>,
+    read_timeout: Duration,
+    full_addresses: bool,
+    /// With --show-orders, the most orders to print per diff
+    show_orders: Option<usize>,
+    /// Normalized address whose orders are marked (`--highlight-user`)
+    highlight_user: Option<String>,
+}
+
+impl L4Options {
+    fn is_highlighted(&self, user: &str) -> bool {
+        self.highlight_user.as_deref().is_some_and(|h| h.eq_ignore_ascii_case(user))
+    }
+
+    /// Suffix for a line about an order of `user`.
+    fn mark(&self, user: Option<&str>) -> &'static str {
+        if user.is_some_and(|user| self.is_highlighted(user)) { "  <== highlighted user" } else { "" }
+    }
+}
+
+/// `+ B 0.5 @ 97000.0 oid 123 (open)`
+fn change_line(change: &OrderChange) -> String {
+    format!("{} {} {} @ {} oid {} ({})", change.kind.symbol(), change.side, change.sz, change.px, change.oid, change.status)
+}
+
+/// One coin's L4 subscription, reopened by [`with_reconnect`]. Every
+/// connection starts with a fresh snapshot, so the local book is rebuilt
+/// per connection.
+struct L4Stream<'a> {
+    coin: &'a str,
+    prefix: &'a str,
+    pool: EndpointPool,
+    /// Whether a connection was attempted yet; each later open follows a failure
+    opened: bool,
+    options: &'a L4Options,
+    snapshot_received: bool,
+    // Local book from the snapshot plus diffs, and unknown-order
+    // references per recent diff
+    book: DepthBook,
+    anomalies: Anomalies,
+    total_msg_count: usize,
+}
+
+#[async_trait]
+impl Resubscribe for L4Stream<'_> {
+    type Message = L4BookUpdate;
+    type Stream = Streaming<L4BookUpdate>;
+
+    async fn open(&mut self) -> Result<Self::Stream> {
+        if self.opened {
+            self.pool.failed();
+        }
+        self.opened = true;
+        let mut client = order_book_client(self.pool.connect().await?, self.pool.channel());
+
+        let request = L4BookRequest {
+            coin: self.coin.to_string(),
+        };
+
+        let request_with_metadata = authed_request(request, self.pool.token(), self.pool.auth())?;
+        Ok(client.stream_l4_book(request_with_metadata).await?.into_inner())
+    }
+
+    async fn on_message(&mut self, update: L4BookUpdate, index: usize) -> Result<Flow> {
+        let (coin, prefix, options) = (self.coin, self.prefix, self.options);
+        let full_addresses = options.full_addresses;
+        self.total_msg_count += 1;
+
+        if index == 0 {
+            self.pool.succeeded();
+            self.snapshot_received = false;
+            self.book = DepthBook::new();
+            self.anomalies.clear();
+        }
+
+        if let Some(l4_book_update::Update::Snapshot(snapshot)) = update.update {
+            self.snapshot_received = true;
+            self.book.load_snapshot(&snapshot);
+
+            out!(prefix, "\n{} L4 Snapshot Received!", ok_mark());
+            out!(prefix, "{}", rule(60));
+            out!(prefix, "Coin: {}", snapshot.coin);
+            out!(prefix, "Height: {}", snapshot.height);
+            out!(prefix, "Time: {}", snapshot.time);
+            out!(prefix, "Bids: {} orders", snapshot.bids.len());
+            out!(prefix, "Asks: {} orders", snapshot.asks.len());
+            if let Some(user) = &options.highlight_user {
+                let resting = |orders: &[L4Order]| orders.iter().filter(|o| options.is_highlighted(&o.user)).count();
+                out!(prefix, "Highlighted user {}: {} bid(s), {} ask(s) resting",
+                    user, resting(&snapshot.bids), resting(&snapshot.asks));
+            }
+            out!(prefix, "{}", rule(60));
+
+            // Sample bids
+            if !snapshot.bids.is_empty() {
+                out!(prefix, "\nSample Bids (first 5):");
+                for order in snapshot.bids.iter().take(5) {
+                    let user = if full_addresses { order.user.clone() } else { short_addr(&order.user) };
+                    out!(prefix, "  OID: {} | Price: {} | Size: {} | User: {}{}",
+                        order.oid, order.limit_px, order.sz, user, options.mark(Some(&order.user)));
+                }
+            }
+
+            // Sample asks
+            if !snapshot.asks.is_empty() {
+                out!(prefix, "\nSample Asks (first 5):");
+                for order in snapshot.asks.iter().take(5) {
+                    let user = if full_addresses { order.user.clone() } else { short_addr(&order.user) };
+                    out!(prefix, "  OID: {} | Price: {} | Size: {} | User: {}{}",
+                        order.oid, order.limit_px, order.sz, user, options.mark(Some(&order.user)));
+                }
+            }
+
+        } else if let Some(l4_book_update::Update::Diff(diff)) = update.update {
+            if !self.snapshot_received {
+                warn!("Received diff before snapshot");
+            }
+
+            match serde_json::from_str::<serde_json::Value>(&diff.data) {
+                Ok(diff_data) => {
+                    let order_statuses = diff_data["order_statuses"].as_array()
+                        .map(|v| v.len()).unwrap_or(0);
+                    let book_diffs = diff_data["book_diffs"].as_array()
+                        .map(|v| v.len()).unwrap_or(0);
+
+                    out!(prefix, "\n[Block {}] L4 Diff:", diff.height);
+                    out!(prefix, "  Time: {}", diff.time);
+                    out!(prefix, "  Order Statuses: {}", order_statuses);
+                    out!(prefix, "  Book Diffs: {}", book_diffs);
+
+                    // Diffs before the snapshot have nothing to apply to
+                    if self.snapshot_received {
+                        self.book.apply_payload(&diff_data["book_diffs"]);
+                        for oid in self.anomalies.record(&mut self.book) {
+                            warn!("Diff at block {} references unknown order {}", diff.height, oid);
+                        }
+
+                        out!(prefix, "  Resting Orders: {}", self.book.order_count(coin));
+                        if self.anomalies.total > 0 {
+                            out!(prefix, "  Unknown Order Refs: {}", self.anomalies.total);
+                        }
+                    }
+
+                    if let Some(max_orders) = options.show_orders {
+                        let changes = order_changes(&diff_data);
+                        for change in changes.iter().take(max_orders) {
+                            out!(prefix, "  {}{}", change_line(change), options.mark(change.user.as_deref()));
+                        }
+                        if changes.len() > max_orders {
+                            out!(prefix, "  ... and {} more", changes.len() - max_orders);
+                        }
+                    } else if options.highlight_user.is_some() {
+                        for change in order_changes(&diff_data) {
+                            if change.user.as_deref().is_some_and(|user| options.is_highlighted(user)) {
+                                out!(prefix, "  {}{}", change_line(&change), options.mark(change.user.as_deref()));
+                            }
+                        }
+                    }
+                    if options.show_orders.is_none() && book_diffs > 0 && book_diffs <= 5 {
+                        if let Some(diffs_array) = diff_data["book_diffs"].as_array() {
+                            out!(prefix, "  Diffs: {}", serde_json::to_string_pretty(diffs_array)?);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Error parsing diff: {}", e);
+                }
+            }
+
+            if self.anomalies.drifted() {
+                return Ok(Flow::Reconnect(format!(
+                    "{} unknown order references in the last {} diffs, resubscribing for a fresh snapshot",
+                    self.anomalies.recent(), self.anomalies.window.len()
+                )));
+            }
+        }
+
+        if let Some(max) = options.max_messages {
+            if self.total_msg_count >= max {
+                info!("Reached max messages ({}), stopping...", max);
+                return Ok(Flow::Stop);
+            }
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+async fn stream_l4_orderbook(
+    coin: &str,
+    prefix: &str,
+    connection: &Connection,
+    options: &L4Options,
+) -> Result<()> {
+    info!("Streaming L4 Orderbook for {}", coin);
+
+    // Building the endpoints can't succeed on a retry, so failures there are
+    // fatal. Only connecting is retried.
+    let pool = EndpointPool::new(&connection.endpoints, &connection.channel)?;
+    info!("Connecting to {}...", pool.url());
+
+    let mut stream = L4Stream {
+        coin,
+        prefix,
+        pool,
+        opened: false,
+        options,
+        snapshot_received: false,
+        book: DepthBook::new(),
+        anomalies: Anomalies::new(),
+        total_msg_count: 0,
+    };
+    let result = with_reconnect(&mut stream, options.read_timeout, connection.breaker, connection.max_outage).await;
+    stream.pool.report();
+    result
+}
+
+/// Check L2 price bucketing parameters against the ranges the server accepts:
+/// `n_sig_figs` in 2-5, and `mantissa` in {1, 2, 5} only alongside `n_sig_figs`.
+fn validate_aggregation(n_sig_figs: Option<u32>, mantissa: Option<u64>) -> Result<(), String> {
+    if let Some(nsf) = n_sig_figs {
+        if !(2..=5).contains(&nsf) {
+            return Err(format!("--n-sig-figs must be between 2 and 5 (got {})", nsf));
+        }
+    }
+    if let Some(m) = mantissa {
+        if n_sig_figs.is_none() {
+            return Err("--mantissa requires --n-sig-figs to be set".to_string());
+        }
+        if ![1, 2, 5].contains(&m) {
+            return Err(format!("--mantissa must be 1, 2, or 5 (got {})", m));
+        }
+    }
+    Ok(())
+}
+
+/// Run with `endpoints` as `(endpoint, token)` pairs; reconnects rotate
+/// through them.
+pub async fn run(args: OrderbookArgs, endpoints: &[(String, String)]) -> Result<()> {
+    let mode = args.mode.as_str();
+    let (levels, n_sig_figs, mantissa, max_messages) = (args.levels, args.n_sig_figs, args.mantissa, args.max_messages);
+    let mut display = if args.live {
+        L2Display::Live
+    } else if args.compact {
+        L2Display::Compact
+    } else {
+        L2Display::Verbose
+    };
+    let read_timeout = args.read_timeout_secs.map_or(DEFAULT_READ_TIMEOUT, Duration::from_secs);
+    let show_orders = args.show_orders.then_some(args.max_orders_per_diff);
+    let connection = Connection {
+        endpoints: endpoints.to_vec(),
+        channel: args.channel.options()?,
+        breaker: args.breaker.breaker(),
+        max_outage: args.breaker.max_outage(),
+    };
+
+    // Accept both --coin=BTC,ETH and repeated --coin flags
+    let mut coins: Vec<String> = Vec::new();
+    for c in args.coin.iter().map(|c| c.trim()).filter(|c| !c.is_empty()) {
+        if !coins.iter().any(|existing| existing == c) {
+            coins.push(c.to_string());
+        }
+    }
+    if coins.is_empty() {
+        coins.push("BTC".to_string());
+    }
+
+    if let Err(e) = validate_aggregation(n_sig_figs, mantissa) {
+        return Err(CliError::BadArgs(e).into());
+    }
+
+    FANCY.store(!args.no_color && hyperliquid_grpc::logging::color_allowed(&std::io::stdout()), Ordering::Relaxed);
+    // Fail on a bad endpoint or token before any coin's task starts
+    EndpointPool::new(endpoints, &connection.channel)?;
+
+    info!("Hyperliquid Orderbook Stream Example");
+    for (endpoint, _) in endpoints {
+        info!("Endpoint: {}", endpoint);
+    }
+    info!("Coins: {}", coins.join(", "));
+    if mode == "l2" {
+        match (n_sig_figs, mantissa) {
+            (Some(nsf), Some(m)) => info!("Aggregation: {} sig figs, mantissa {}", nsf, m),
+            (Some(nsf), None) => info!("Aggregation: {} sig figs", nsf),
+            _ => info!("Aggregation: none (full precision)"),
+        }
+    }
+
+    if display != L2Display::Verbose && mode != "l2" {
+        return Err(CliError::BadArgs("--compact and --live are only supported with --mode=l2".to_string()).into());
+    }
+    if args.once && mode != "l2" {
+        return Err(CliError::BadArgs("--once is only supported with --mode=l2".to_string()).into());
+    }
+    if show_orders.is_some() && mode != "l4" {
+        return Err(CliError::BadArgs("--show-orders is only supported with --mode=l4".to_string()).into());
+    }
+    if let Some(user) = &args.highlight_user {
+        if mode != "l4" {
+            return Err(CliError::BadArgs("--highlight-user is only supported with --mode=l4".to_string()).into());
+        }
+        if !is_hex_address(user) {
+            warn!("--highlight-user {} is not a 0x-prefixed 40-digit hex address; nothing may match it", user);
+        }
+    }
+    if display == L2Display::Live {
+        if coins.len() > 1 {
+            return Err(CliError::BadArgs("--live draws a single book; stream one coin or use --compact".to_string()).into());
+        }
+        // Redrawing only makes sense on a terminal; piped output stays plain
+        if !std::io::stdout().is_terminal() {
+            info!("stdout is not a terminal, --live falls back to normal output");
+            display = L2Display::Verbose;
+        }
+    }
+
+    let csv = match &args.csv {
+        Some(_) if mode != "l2" => {
+            return Err(CliError::BadArgs("--csv is only supported with --mode=l2".to_string()).into());
+        }
+        Some(path) => Some(Arc::new(Mutex::new(L2CsvWriter::create(path, args.compress_output)?))),
+        None => None,
+    };
+    #[cfg(feature = "postgres")]
+    let pg = match &args.pg_url {
+        Some(_) if mode != "l2" => {
+            return Err(CliError::BadArgs("--pg-url is only supported with --mode=l2".to_string()).into());
+        }
+        Some(url) => Some(Arc::new(tokio::sync::Mutex::new(
+            PgSink::l2(url, &args.pg_table, args.pg_batch_size).await?,
+        ))),
+        None => None,
+    };
+    let l2_options = L2Options {
+        display,
+        n_levels: levels,
+        n_sig_figs,
+        mantissa,
+        max_messages,
+        once: args.once,
+        read_timeout,
+        csv: csv.clone(),
+        #[cfg(feature = "postgres")]
+        pg: pg.clone(),
+    };
+    let l4_options = L4Options {
+        max_messages,
+        read_timeout,
+        full_addresses: args.full_addresses,
+        show_orders,
+        highlight_user: args.highlight_user,
+    };
+
+    // One independent stream (with its own channel and reconnect loop) per
+    // coin. Output is only prefixed when more than one coin is streamed.
+    let multi = coins.len() > 1;
+    let mut handles = Vec::with_capacity(coins.len());
+    for coin in &coins {
+        let coin = coin.clone();
+        let mode = mode.to_string();
+        let connection = connection.clone();
+        let l2_options = l2_options.clone();
+        let l4_options = l4_options.clone();
+        let prefix = if multi { format!("[{}] ", coin) } else { String::new() };
+        let span = info_span!("orderbook", coin = %coin, mode = %mode);
+        handles.push(tokio::spawn(async move {
+            match mode.as_str() {
+                "l2" => stream_l2_orderbook(&coin, &prefix, &connection, &l2_options).await,
+                _ => stream_l4_orderbook(&coin, &prefix, &connection, &l4_options).await,
+            }
+        }.instrument(span)));
+    }
+
+    // A coin whose stream fails permanently doesn't stop the others. On
+    // Ctrl-C every task is aborted and waited for, so none writes a row
+    // after the final flush below
+    let aborts: Vec<_> = handles.iter().map(|handle| handle.abort_handle()).collect();
+    let mut all = join_all(handles);
+    let results = tokio::select! {
+        results = &mut all => results,
+        _ = tokio::signal::ctrl_c() => {
+            info!("Interrupted, shutting down");
+            aborts.iter().for_each(|abort| abort.abort());
+            all.await
+        }
+    };
+
+    if let Some(csv) = &csv {
+        csv.lock().unwrap().flush()?;
+    }
+    #[cfg(feature = "postgres")]
+    if let Some(pg) = &pg {
+        pg.lock().await.flush().await?;
+    }
+
+    // The exit status reflects the first failure
+    let mut failed = 0;
+    let mut first_error: Option<hyperliquid_grpc::Error> = None;
+    for (coin, result) in coins.iter().zip(results) {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                error!("[{}] Stream failed: {}", coin, e);
+                failed += 1;
+                first_error.get_or_insert(e);
+            }
+            Err(e) if e.is_cancelled() => {}
+            Err(e) => {
+                error!("[{}] Stream task panicked: {}", coin, e);
+                failed += 1;
+                first_error.get_or_insert(e.to_string().into());
+            }
+        }
+    }
+
+    if let Some(e) = first_error {
+        error!("{} of {} streams failed", failed, coins.len());
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperliquid_grpc::hyperliquid::L4BookSnapshot;
+    use serde_json::json;
+
+    fn snapshot(oids: &[u64]) -> L4BookSnapshot {
+        let order = |oid| L4Order {
+            coin: "BTC".to_string(),
+            side: "B".to_string(),
+            limit_px: "100".to_string(),
+            sz: "1".to_string(),
+            oid,
+            ..Default::default()
+        };
+        L4BookSnapshot {
+            coin: "BTC".to_string(),
+            bids: oids.iter().copied().map(order).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn remove(oid: u64) -> serde_json::Value {
+        json!({"coin": "BTC", "oid": oid, "side": "B", "px": "100", "raw_book_diff": "remove"})
+    }
+
+    #[test]
+    fn diff_for_unknown_oid_counts_an_anomaly() {
+        let mut book = DepthBook::new();
+        book.load_snapshot(&snapshot(&[1, 2]));
+        let mut anomalies = Anomalies::new();
+
+        book.apply_payload(&json!([
+            remove(1),
+            remove(7),
+            {"coin": "BTC", "oid": 8, "side": "B", "px": "100", "raw_book_diff": {"update": {"origSz": "1", "newSz": "2"}}},
+        ]));
+        assert_eq!(anomalies.record(&mut book), vec![7, 8]);
+        assert_eq!(anomalies.total, 2);
+        assert_eq!(anomalies.recent(), 2);
+
+        // Known orders add nothing
+        book.apply_payload(&json!([remove(2)]));
+        assert!(anomalies.record(&mut book).is_empty());
+        assert_eq!(anomalies.total, 2);
+        assert!(!anomalies.drifted());
+    }
+
+    #[test]
+    fn anomalies_past_the_limit_within_the_window_mean_drift() {
+        let mut book = DepthBook::new();
+        let mut anomalies = Anomalies::new();
+        for oid in 0..=MAX_ANOMALIES as u64 {
+            book.apply(&remove(oid));
+            anomalies.record(&mut book);
+        }
+        assert!(anomalies.drifted());
+
+        // Old diffs age out of the window but stay in the total
+        for _ in 0..ANOMALY_WINDOW {
+            anomalies.record(&mut book);
+        }
+        assert!(!anomalies.drifted());
+        assert_eq!(anomalies.total, MAX_ANOMALIES + 1);
+    }
+
+    #[test]
+    fn short_addr_keeps_the_start_of_an_address() {
+        let user = "0x31ca8395cf837de08b24da3f660e77761dfb974b";
+        assert_eq!(short_addr(user), "0x31ca8395...");
+    }
+
+    #[test]
+    fn short_addr_quotes_short_and_non_hex_values() {
+        assert_eq!(short_addr(""), "\"\"");
+        assert_eq!(short_addr("0x31ca"), "\"0x31ca\"");
+        // Address-length but not hex
+        assert_eq!(
+            short_addr("0xzzca8395cf837de08b24da3f660e77761dfb974b"),
+            "\"0xzzca8395\"..."
+        );
+        // Multi-byte characters are cut on a char boundary
+        assert_eq!(short_addr("ééééééééééééé"), "\"éééééééééé\"...");
+    }
+}
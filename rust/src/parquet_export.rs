@@ -0,0 +1,136 @@
+//! Parquet export of TRADES (behind the `parquet` feature).
+//!
+//! Trades are buffered and written as Arrow record batches, one row group
+//! per `ROW_GROUP_ROWS` trades. The writer must be closed for the Parquet
+//! footer to be written; [`TradeParquetWriter::close`] does that and is
+//! called when the stream ends or is interrupted.
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::builder::{Float64Builder, Int64Builder, StringBuilder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use async_trait::async_trait;
+use parquet::arrow::ArrowWriter;
+use serde_json::Value;
+
+use crate::records::{field_str, split_records};
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+const ROW_GROUP_ROWS: usize = 10_000;
+
+/// Columns: `block_number, timestamp, coin, px, sz, side, hash`. Fields
+/// missing from a trade are written as nulls.
+pub struct TradeParquetWriter {
+    writer: Option<ArrowWriter<File>>,
+    schema: SchemaRef,
+    block_number: UInt64Builder,
+    timestamp: Int64Builder,
+    coin: StringBuilder,
+    px: Float64Builder,
+    sz: Float64Builder,
+    side: StringBuilder,
+    hash: StringBuilder,
+    rows: usize,
+}
+
+impl TradeParquetWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("block_number", DataType::UInt64, false),
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("coin", DataType::Utf8, true),
+            Field::new("px", DataType::Float64, true),
+            Field::new("sz", DataType::Float64, true),
+            Field::new("side", DataType::Utf8, true),
+            Field::new("hash", DataType::Utf8, true),
+        ]));
+        let writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), None)?;
+
+        Ok(Self {
+            writer: Some(writer),
+            schema,
+            block_number: UInt64Builder::new(),
+            timestamp: Int64Builder::new(),
+            coin: StringBuilder::new(),
+            px: Float64Builder::new(),
+            sz: Float64Builder::new(),
+            side: StringBuilder::new(),
+            hash: StringBuilder::new(),
+            rows: 0,
+        })
+    }
+
+    /// Buffer every trade in a decoded TRADES payload, writing a row group
+    /// once enough have accumulated.
+    pub fn write_payload(
+        &mut self,
+        block_number: u64,
+        timestamp: u64,
+        payload: &Value,
+    ) -> Result<()> {
+        for trade in split_records(payload) {
+            let number = |name| field_str(trade, name).and_then(|v| v.parse::<f64>().ok());
+
+            self.block_number.append_value(block_number);
+            self.timestamp.append_value(timestamp as i64);
+            self.coin.append_option(field_str(trade, "coin"));
+            self.px.append_option(number("px"));
+            self.sz.append_option(number("sz"));
+            self.side.append_option(field_str(trade, "side"));
+            self.hash.append_option(field_str(trade, "hash"));
+            self.rows += 1;
+        }
+
+        if self.rows >= ROW_GROUP_ROWS {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.block_number.finish()),
+            Arc::new(self.timestamp.finish()),
+            Arc::new(self.coin.finish()),
+            Arc::new(self.px.finish()),
+            Arc::new(self.sz.finish()),
+            Arc::new(self.side.finish()),
+            Arc::new(self.hash.finish()),
+        ];
+        writer.write(&RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        writer.flush()?;
+        self.rows = 0;
+        Ok(())
+    }
+
+    /// Write any buffered trades and the file footer. Later calls are no-ops.
+    pub fn close(&mut self) -> Result<()> {
+        self.write_row_group()?;
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RecordSink for TradeParquetWriter {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        self.write_payload(record.block_number, record.timestamp, record.payload)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.close()
+    }
+}
@@ -0,0 +1,290 @@
+//! Postgres / TimescaleDB sink (behind the `postgres` feature).
+//!
+//! Rows are buffered and written with one multi-row `INSERT` per batch. The
+//! table is created if it doesn't exist. If the connection drops, the sink
+//! reconnects with the usual backoff and retries the same batch, so a batch
+//! is either written whole or reported as an error.
+//!
+//! Times are stored as `BIGINT` milliseconds. To turn a table into a
+//! TimescaleDB hypertable, run e.g.
+//! `SELECT create_hypertable('trades', by_range('timestamp', 86400000));`
+//! and set an integer now function for compression/retention policies.
+
+use async_trait::async_trait;
+use rust_decimal::prelude::ToPrimitive;
+use serde_json::Value;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, NoTls};
+use tracing::{info, warn};
+
+use crate::hyperliquid::L2BookUpdate;
+use crate::price::mid_and_spread_bps;
+use crate::reconnect::{reconnect_delay, BASE_DELAY_SECS, MAX_DELAY_SECS, MAX_RETRIES};
+use crate::records::{field_str, split_records};
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Postgres accepts at most this many bind parameters per statement.
+const MAX_PARAMS: usize = 65_535;
+
+const TRADE_COLUMNS: &[(&str, &str)] = &[
+    ("block_number", "BIGINT NOT NULL"),
+    ("timestamp", "BIGINT NOT NULL"),
+    ("coin", "TEXT"),
+    ("px", "DOUBLE PRECISION"),
+    ("sz", "DOUBLE PRECISION"),
+    ("side", "TEXT"),
+    ("hash", "TEXT"),
+];
+
+const L2_COLUMNS: &[(&str, &str)] = &[
+    ("block_number", "BIGINT NOT NULL"),
+    ("time", "BIGINT NOT NULL"),
+    ("coin", "TEXT NOT NULL"),
+    ("best_bid", "DOUBLE PRECISION"),
+    ("best_ask", "DOUBLE PRECISION"),
+    ("mid", "DOUBLE PRECISION"),
+    ("spread_bps", "DOUBLE PRECISION"),
+];
+
+type Row = Vec<Box<dyn ToSql + Sync + Send>>;
+
+pub struct PgSink {
+    url: String,
+    table: String,
+    columns: &'static [(&'static str, &'static str)],
+    batch_size: usize,
+    client: Option<Client>,
+    rows: Vec<Row>,
+}
+
+impl PgSink {
+    /// Sink for TRADES, with the same columns as the CSV export:
+    /// `block_number, timestamp, coin, px, sz, side, hash`.
+    pub async fn trades(url: &str, table: &str, batch_size: usize) -> Result<Self> {
+        Self::connect(url, table, batch_size, TRADE_COLUMNS).await
+    }
+
+    /// Sink for L2 top of book:
+    /// `block_number, time, coin, best_bid, best_ask, mid, spread_bps`.
+    pub async fn l2(url: &str, table: &str, batch_size: usize) -> Result<Self> {
+        Self::connect(url, table, batch_size, L2_COLUMNS).await
+    }
+
+    async fn connect(
+        url: &str,
+        table: &str,
+        batch_size: usize,
+        columns: &'static [(&'static str, &'static str)],
+    ) -> Result<Self> {
+        if !valid_table_name(table) {
+            return Err(format!("invalid Postgres table name: {:?}", table).into());
+        }
+        if batch_size == 0 {
+            return Err("Postgres batch size must be at least 1".into());
+        }
+
+        let mut sink = Self {
+            url: url.to_string(),
+            table: table.to_string(),
+            columns,
+            batch_size: batch_size.min(MAX_PARAMS / columns.len()),
+            client: None,
+            rows: Vec::new(),
+        };
+        // Fail fast on a bad URL or credentials rather than on the first batch
+        sink.client = Some(sink.open().await?);
+        info!("Writing to Postgres table {}", table);
+        Ok(sink)
+    }
+
+    async fn open(&self) -> Result<Client> {
+        let (client, connection) = tokio_postgres::connect(&self.url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Postgres connection closed: {}", e);
+            }
+        });
+
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(name, ty)| format!("\"{}\" {}", name, ty))
+            .collect();
+        client
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                self.table,
+                columns.join(", ")
+            ))
+            .await?;
+        Ok(client)
+    }
+
+    /// Buffer every trade in a decoded TRADES payload.
+    pub async fn write_trades(
+        &mut self,
+        block_number: u64,
+        timestamp: u64,
+        payload: &Value,
+    ) -> Result<()> {
+        for trade in split_records(payload) {
+            let number = |name| field_str(trade, name).and_then(|v| v.parse::<f64>().ok());
+            self.rows.push(vec![
+                Box::new(block_number as i64),
+                Box::new(timestamp as i64),
+                Box::new(field_str(trade, "coin")),
+                Box::new(number("px")),
+                Box::new(number("sz")),
+                Box::new(field_str(trade, "side")),
+                Box::new(field_str(trade, "hash")),
+            ]);
+        }
+        self.write_full_batches().await
+    }
+
+    /// Buffer the top of book for `update`. Mid and spread are null when
+    /// either side is empty or unparseable.
+    pub async fn write_l2(&mut self, update: &L2BookUpdate) -> Result<()> {
+        let best_bid = update.bids.first().and_then(|l| l.px.parse::<f64>().ok());
+        let best_ask = update.asks.first().and_then(|l| l.px.parse::<f64>().ok());
+        // Derived in exact decimals, then stored as the table's float type
+        let derived = update
+            .bids
+            .first()
+            .zip(update.asks.first())
+            .and_then(|(bid, ask)| mid_and_spread_bps(&bid.px, &ask.px));
+        let mid = derived.and_then(|(mid, _)| mid.to_f64());
+        let spread_bps = derived.and_then(|(_, spread_bps)| spread_bps.to_f64());
+
+        self.rows.push(vec![
+            Box::new(update.block_number as i64),
+            Box::new(update.time as i64),
+            Box::new(update.coin.clone()),
+            Box::new(best_bid),
+            Box::new(best_ask),
+            Box::new(mid),
+            Box::new(spread_bps),
+        ]);
+        self.write_full_batches().await
+    }
+
+    async fn write_full_batches(&mut self) -> Result<()> {
+        while self.rows.len() >= self.batch_size {
+            self.write_batch(self.batch_size).await?;
+        }
+        Ok(())
+    }
+
+    /// Write any buffered rows.
+    pub async fn flush(&mut self) -> Result<()> {
+        while !self.rows.is_empty() {
+            self.write_batch(self.rows.len().min(self.batch_size))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Insert the first `len` buffered rows, reconnecting and retrying the
+    /// batch if the connection has dropped.
+    async fn write_batch(&mut self, len: usize) -> Result<()> {
+        let width = self.columns.len();
+        let placeholders: Vec<String> = (0..len)
+            .map(|row| {
+                let params: Vec<String> = (1..=width)
+                    .map(|col| format!("${}", row * width + col))
+                    .collect();
+                format!("({})", params.join(", "))
+            })
+            .collect();
+        let names: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(name, _)| format!("\"{}\"", name))
+            .collect();
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            self.table,
+            names.join(", "),
+            placeholders.join(", ")
+        );
+
+        let mut retry_count = 0;
+        loop {
+            let result = match &self.client {
+                Some(client) if !client.is_closed() => {
+                    let params: Vec<&(dyn ToSql + Sync)> = self.rows[..len]
+                        .iter()
+                        .flatten()
+                        .map(|value| value.as_ref() as &(dyn ToSql + Sync))
+                        .collect();
+                    client
+                        .execute(sql.as_str(), &params)
+                        .await
+                        .map(|_| ())
+                        .map_err(Into::into)
+                }
+                _ => match self.open().await {
+                    Ok(client) => {
+                        self.client = Some(client);
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+
+            match result {
+                Ok(()) => {
+                    self.rows.drain(..len);
+                    return Ok(());
+                }
+                // Only a lost connection is retried; anything else (bad
+                // data, permissions, schema mismatch) would fail again
+                Err(e)
+                    if retry_count < MAX_RETRIES
+                        && self.client.as_ref().is_none_or(Client::is_closed) =>
+                {
+                    retry_count += 1;
+                    self.client = None;
+                    let delay = reconnect_delay(retry_count, BASE_DELAY_SECS, MAX_DELAY_SECS);
+                    warn!(
+                        "Postgres write failed: {} (retry {}/{} in {:.1}s)",
+                        e,
+                        retry_count,
+                        MAX_RETRIES,
+                        delay.as_secs_f64()
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Writes TRADES payloads; L2 rows go through [`PgSink::write_l2`].
+#[async_trait]
+impl RecordSink for PgSink {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        self.write_trades(record.block_number, record.timestamp, record.payload)
+            .await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        PgSink::flush(self).await
+    }
+}
+
+/// `table` or `schema.table`, each part a plain identifier. Table names are
+/// interpolated into SQL, so nothing else is accepted.
+fn valid_table_name(table: &str) -> bool {
+    let parts: Vec<&str> = table.split('.').collect();
+    parts.len() <= 2
+        && parts.iter().all(|part| {
+            let mut chars = part.chars();
+            matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
@@ -0,0 +1,97 @@
+//! Exact arithmetic on prices and sizes.
+//!
+//! The API sends `px` and `sz` as decimal strings. Parsed into `f64`, values
+//! like `0.1` aren't representable, so sums of sizes drift as a book is
+//! updated (`0.1 + 0.2` resting orders make a `0.30000000000000004` level)
+//! and mids of fine-tick prices pick up noise digits. Everything derived
+//! from them (level sizes, mid, spread) is computed as [`Decimal`] instead;
+//! the original strings are kept for display.
+
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde_json::Value;
+
+use crate::Result;
+
+/// Parse a price or size string, e.g. `"97000.5"` or `"1e-5"`.
+pub fn parse_px(s: &str) -> Result<Decimal> {
+    let s = s.trim();
+    Decimal::from_str(s)
+        .or_else(|_| Decimal::from_scientific(s))
+        .map_err(|e| format!("invalid price or size {:?}: {}", s, e).into())
+}
+
+/// A price or size field given as a string or a JSON number.
+pub fn parse_value(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::String(s) => parse_px(s).ok(),
+        Value::Number(n) => parse_px(&n.to_string()).ok(),
+        _ => None,
+    }
+}
+
+/// Mid price and spread in basis points of the mid, from the best bid and
+/// ask strings. `None` if either doesn't parse or the mid is zero.
+pub fn mid_and_spread_bps(best_bid: &str, best_ask: &str) -> Option<(Decimal, Decimal)> {
+    let (bid, ask) = (parse_px(best_bid).ok()?, parse_px(best_ask).ok()?);
+    let mid = (bid + ask) / Decimal::TWO;
+    let spread_bps = (ask - bid).checked_div(mid)? * Decimal::from(10_000);
+    Some((mid.normalize(), spread_bps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        parse_px(s).unwrap()
+    }
+
+    #[test]
+    fn summed_sizes_stay_exact_where_f64_drifts() {
+        let sizes = ["0.1", "0.2"];
+
+        let float: f64 = sizes.iter().map(|s| s.parse::<f64>().unwrap()).sum();
+        assert_ne!(float, 0.3);
+        assert_eq!(float.to_string(), "0.30000000000000004");
+
+        let exact: Decimal = sizes.iter().map(|s| dec(s)).sum();
+        assert_eq!(exact, dec("0.3"));
+        assert_eq!(exact.to_string(), "0.3");
+    }
+
+    #[test]
+    fn fine_tick_mid_keeps_every_digit() {
+        let (bid, ask) = ("12345.678901234567", "12345.678901234569");
+
+        let float = (bid.parse::<f64>().unwrap() + ask.parse::<f64>().unwrap()) / 2.0;
+        assert_eq!(float.to_string(), "12345.678901234569");
+
+        let (mid, spread_bps) = mid_and_spread_bps(bid, ask).unwrap();
+        assert_eq!(mid.to_string(), "12345.678901234568");
+        assert!(spread_bps > Decimal::ZERO);
+    }
+
+    #[test]
+    fn parses_plain_and_scientific_values() {
+        assert_eq!(dec("97000.5"), Decimal::new(970005, 1));
+        assert_eq!(dec(" 1e-5 "), Decimal::new(1, 5));
+        assert_eq!(parse_value(&Value::from("2.5")), Some(Decimal::new(25, 1)));
+        assert_eq!(parse_value(&serde_json::json!(3)), Some(Decimal::from(3)));
+        assert_eq!(parse_value(&Value::Null), None);
+    }
+
+    #[test]
+    fn malformed_values_are_errors() {
+        for s in ["", "abc", "1.2.3", "NaN"] {
+            let err = parse_px(s).unwrap_err();
+            assert!(
+                err.to_string().contains("invalid price or size"),
+                "{s}: {err}"
+            );
+        }
+        assert_eq!(mid_and_spread_bps("1.0", "oops"), None);
+        assert_eq!(mid_and_spread_bps("0", "0"), None);
+    }
+}
@@ -0,0 +1,179 @@
+//! Per-record projection for `--project`.
+//!
+//! Wide records (writer actions, events) carry far more than most consumers
+//! read. A projection rebuilds each record as an object holding only the
+//! chosen fields, renamed if asked, before any sink sees it, which keeps
+//! what gets stored or forwarded small. A record with none of the fields is
+//! dropped; one with some of them keeps those.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use crate::client_filter::JsonPath;
+use crate::records::split_records;
+
+/// `<path>[:<alias>]`: the value at a [`JsonPath`], stored under `alias`,
+/// or under the path's last key if no alias is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectedField {
+    path: JsonPath,
+    alias: String,
+}
+
+impl FromStr for ProjectedField {
+    type Err = String;
+
+    fn from_str(expr: &str) -> Result<Self, String> {
+        // A `:` inside a bracketed key belongs to the path
+        let (path, alias) = match expr.rsplit_once(':') {
+            Some((path, alias)) if !alias.contains(']') => (path, Some(alias.trim())),
+            _ => (expr, None),
+        };
+        let path: JsonPath = path.parse()?;
+        let alias = match alias {
+            Some("") => return Err("empty alias".to_string()),
+            Some(alias) => alias.to_string(),
+            None => path
+                .last_key()
+                .ok_or("a path ending in an index needs an alias")?
+                .to_string(),
+        };
+        Ok(Self { path, alias })
+    }
+}
+
+/// The fields of a `--project` list, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    fields: Vec<ProjectedField>,
+}
+
+impl Projection {
+    /// Project onto `fields`, which must have distinct aliases.
+    pub fn new(fields: Vec<ProjectedField>) -> Result<Self, String> {
+        let mut aliases = HashSet::new();
+        for field in &fields {
+            if !aliases.insert(field.alias.as_str()) {
+                return Err(format!("alias {:?} is used twice", field.alias));
+            }
+        }
+        if fields.is_empty() {
+            return Err("no fields to project".to_string());
+        }
+        Ok(Self { fields })
+    }
+
+    /// `record` cut down to the projected fields, or `None` if it has none
+    /// of them. A field that is present but null is kept.
+    pub fn project(&self, record: &Value) -> Option<Value> {
+        let mut projected = Map::new();
+        for field in &self.fields {
+            if let Some(value) = field.path.get(record) {
+                projected.insert(field.alias.clone(), value.clone());
+            }
+        }
+        (!projected.is_empty()).then_some(Value::Object(projected))
+    }
+
+    /// Replace `payload` with the projections of its records: an array of
+    /// them, or a single one for a payload that is a single record. Returns
+    /// the number of records kept.
+    pub fn apply(&self, payload: &mut Value) -> usize {
+        let many = payload.is_array() || matches!(payload.get("events"), Some(Value::Array(_)));
+        let mut projected: Vec<Value> = split_records(payload)
+            .into_iter()
+            .filter_map(|record| self.project(record))
+            .collect();
+        let kept = projected.len();
+        if many {
+            *payload = Value::Array(projected);
+        } else if let Some(record) = projected.pop() {
+            *payload = record;
+        }
+        kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn projection(exprs: &[&str]) -> Projection {
+        Projection::new(exprs.iter().map(|expr| expr.parse().unwrap()).collect()).unwrap()
+    }
+
+    fn trade() -> Value {
+        json!({
+            "coin": "BTC", "px": "97000.5", "sz": "0.1", "note": null,
+            "users": ["0xbuyer", "0xseller"],
+            "fee": { "token": "USDC", "amount": "0.05" },
+        })
+    }
+
+    #[test]
+    fn present_fields_are_kept_under_their_aliases() {
+        let projection = projection(&["$.px:price", "$.sz:size", "coin"]);
+
+        assert_eq!(
+            projection.project(&trade()),
+            Some(json!({ "price": "97000.5", "size": "0.1", "coin": "BTC" }))
+        );
+    }
+
+    #[test]
+    fn absent_fields_are_left_out() {
+        let projection = projection(&["$.px:price", "$.side:side", "note"]);
+
+        // A null field is present; a missing one isn't
+        assert_eq!(
+            projection.project(&trade()),
+            Some(json!({ "price": "97000.5", "note": null }))
+        );
+        assert_eq!(projection.project(&json!({ "coin": "ETH" })), None);
+    }
+
+    #[test]
+    fn nested_paths_reach_into_objects_and_arrays() {
+        let projection = projection(&[
+            "$.fee.amount",
+            "$['fee']['token']:fee_token",
+            "$.users[1]:seller",
+        ]);
+
+        assert_eq!(
+            projection.project(&trade()),
+            Some(json!({ "amount": "0.05", "fee_token": "USDC", "seller": "0xseller" }))
+        );
+        assert_eq!(
+            projection.project(&json!({ "fee": "flat", "users": ["0xbuyer"] })),
+            None
+        );
+    }
+
+    #[test]
+    fn records_with_no_projected_field_are_dropped_from_a_payload() {
+        let projection = projection(&["$.px:price"]);
+        let mut payload = json!([trade(), { "coin": "ETH" }, { "px": "3000" }]);
+
+        assert_eq!(projection.apply(&mut payload), 2);
+        assert_eq!(
+            payload,
+            json!([{ "price": "97000.5" }, { "price": "3000" }])
+        );
+
+        let mut single = trade();
+        assert_eq!(projection.apply(&mut single), 1);
+        assert_eq!(single, json!({ "price": "97000.5" }));
+    }
+
+    #[test]
+    fn invalid_projections_are_rejected() {
+        assert!("$.px:".parse::<ProjectedField>().is_err());
+        assert!("$.users[0]".parse::<ProjectedField>().is_err());
+        assert!(Projection::new(vec![]).is_err());
+        assert!(Projection::new(vec!["px".parse().unwrap(), "$.sz:px".parse().unwrap()]).is_err());
+    }
+}
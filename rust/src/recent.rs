@@ -0,0 +1,247 @@
+//! The last N decoded records, kept in memory and served over HTTP.
+//!
+//! For live debugging: `--ring-size N` keeps the most recent N records and
+//! `GET /recent?coin=BTC&n=20` on `--control-port` returns them as JSON, so
+//! what is flowing can be inspected without re-running or touching the
+//! other sinks. The buffer holds at most N records; the oldest is dropped
+//! for each new one. The read loop only takes the lock to push, and a
+//! query only holds it long enough to clone the matching handles.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+use crate::records::{field_str, split_records};
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+pub const DEFAULT_RING_SIZE: usize = 1000;
+/// Records returned by `/recent` when `n` isn't given
+const DEFAULT_RECENT: usize = 20;
+/// Longest request line and headers accepted
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One buffered record and the message it came from.
+struct Entry {
+    block_number: u64,
+    timestamp: u64,
+    coin: Option<String>,
+    record: Value,
+}
+
+/// Handle to the ring buffer, shared with the control server.
+#[derive(Clone)]
+pub struct RecentRecords {
+    entries: Arc<Mutex<VecDeque<Arc<Entry>>>>,
+    capacity: usize,
+}
+
+impl RecentRecords {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Keep the last `capacity` records and serve them on
+    /// `127.0.0.1:port`. Local only, since the records may name accounts.
+    pub async fn bind(port: u16, capacity: usize) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        info!(
+            "Control endpoint listening on http://127.0.0.1:{}/recent (last {} records)",
+            port, capacity
+        );
+
+        let recent = Self::new(capacity);
+        let served = recent.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve_request(stream, served.clone()));
+                    }
+                    Err(e) => warn!("Control endpoint accept failed: {}", e),
+                }
+            }
+        });
+
+        Ok(recent)
+    }
+
+    /// Add `entries`, dropping the oldest past `capacity`.
+    fn push(&self, entries: impl ExactSizeIterator<Item = Entry>) {
+        // Records that would be dropped again straight away aren't kept
+        let skip = entries.len().saturating_sub(self.capacity);
+        let entries: Vec<_> = entries.skip(skip).map(Arc::new).collect();
+
+        let mut buffer = self.entries.lock();
+        for entry in entries {
+            if buffer.len() == self.capacity {
+                buffer.pop_front();
+            }
+            buffer.push_back(entry);
+        }
+    }
+
+    /// Up to `n` of the newest records, oldest first, optionally only those
+    /// whose `coin` is `coin` (ignoring case).
+    fn recent(&self, coin: Option<&str>, n: usize) -> Vec<Arc<Entry>> {
+        let mut matched: Vec<_> = {
+            let buffer = self.entries.lock();
+            buffer
+                .iter()
+                .rev()
+                .filter(|entry| {
+                    coin.is_none_or(|coin| {
+                        entry
+                            .coin
+                            .as_deref()
+                            .is_some_and(|c| c.eq_ignore_ascii_case(coin))
+                    })
+                })
+                .take(n)
+                .cloned()
+                .collect()
+        };
+        matched.reverse();
+        matched
+    }
+}
+
+/// Buffers each record of the message.
+#[async_trait]
+impl RecordSink for RecentRecords {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        let records = split_records(record.payload);
+        self.push(records.into_iter().map(|value| Entry {
+            block_number: record.block_number,
+            timestamp: record.timestamp,
+            coin: field_str(value, "coin"),
+            record: value.clone(),
+        }));
+        Ok(())
+    }
+}
+
+/// Answer one HTTP request and close the connection.
+async fn serve_request(mut stream: TcpStream, recent: RecentRecords) {
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+        Ok(Ok(head)) => head,
+        Ok(Err(e)) => {
+            warn!("Control endpoint read failed: {}", e);
+            return;
+        }
+        Err(_) => return,
+    };
+    let request_line = head.lines().next().unwrap_or_default();
+    let (status, body) = respond(&recent, request_line);
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        warn!("Control endpoint write failed: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Read up to the blank line ending the request headers. A head that
+/// doesn't end within [`MAX_REQUEST_HEAD`] bytes is cut off there.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while head.len() < MAX_REQUEST_HEAD && !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// The status line and JSON body for a request line like
+/// `GET /recent?coin=BTC&n=20 HTTP/1.1`.
+fn respond(recent: &RecentRecords, request_line: &str) -> (&'static str, Value) {
+    let error = |status, message: String| (status, json!({ "error": message }));
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return error("400 Bad Request", "malformed request".to_string());
+    };
+    if method != "GET" {
+        return error("405 Method Not Allowed", format!("{} not allowed", method));
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/recent" {
+        return error("404 Not Found", format!("no such path: {}", path));
+    }
+
+    let mut coin = None;
+    let mut n = DEFAULT_RECENT;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let Some(value) = percent_decode(value) else {
+            return error("400 Bad Request", format!("bad encoding in {}", pair));
+        };
+        match key {
+            "coin" => coin = Some(value),
+            "n" => match value.parse() {
+                Ok(value) => n = value,
+                Err(_) => {
+                    return error(
+                        "400 Bad Request",
+                        format!("n must be a number, got {:?}", value),
+                    )
+                }
+            },
+            _ => return error("400 Bad Request", format!("unknown parameter: {}", key)),
+        }
+    }
+
+    let entries = recent.recent(coin.as_deref(), n);
+    let records: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "block_number": entry.block_number,
+                "timestamp": entry.timestamp,
+                "data": entry.record,
+            })
+        })
+        .collect();
+    ("200 OK", Value::Array(records))
+}
+
+/// Decode `%XX` escapes and `+` (a space) in a query value. `None` if an
+/// escape is malformed or the result isn't UTF-8.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(b) = input.next() {
+        match b {
+            b'%' => {
+                let hex = [input.next()?, input.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,669 @@
+//! Reconnect policy shared by the streaming examples.
+//!
+//! [`with_reconnect`] runs the whole connect / read / back off / reconnect
+//! cycle for a [`Resubscribe`] implementation; the raw stream, which also
+//! resumes from a block and watches its start block, keeps its own loop but
+//! shares the [`RetryBudget`].
+//!
+//! The budget alone resets whenever a connection delivers data, so a
+//! connection that works briefly and then fails, over and over, would
+//! reconnect forever. A [`CircuitBreaker`] also counts every reconnect
+//! within a time window, and pauses for a longer cooldown once there are
+//! too many.
+//!
+//! Both bound attempts, whose spacing varies with backoff and cooldowns.
+//! `max_outage` bounds wall-clock time instead: a reconnect sequence that
+//! hasn't delivered data within it gives up with [`CliError::Outage`], for
+//! orchestrators that would rather restart the process.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use rand::Rng;
+use tokio::time::Instant;
+use tonic::{Code, Status};
+use tracing::{error, info, warn};
+
+use crate::error::{is_message_too_large, CliError};
+use crate::Result;
+
+pub const MAX_RETRIES: usize = 10;
+pub const BASE_DELAY_SECS: u64 = 2;
+pub const MAX_DELAY_SECS: u64 = 60;
+/// A stream with no message (data or pong) for this long is treated as
+/// stalled and reconnected. Longer than the 30s ping interval, so a healthy
+/// but quiet subscription still gets pongs in time.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+/// Base delay after `ResourceExhausted`, where retrying quickly only adds
+/// to the load
+pub const RESOURCE_EXHAUSTED_BASE_DELAY_SECS: u64 = 10;
+/// Reconnects within [`DEFAULT_BREAKER_WINDOW`] that open the breaker
+pub const DEFAULT_BREAKER_THRESHOLD: usize = 20;
+pub const DEFAULT_BREAKER_WINDOW: Duration = Duration::from_secs(300);
+pub const DEFAULT_BREAKER_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Whether a stream that failed with `status` is worth reconnecting:
+/// `DataLoss` (server reinitialized), `Unavailable` (restart or network),
+/// `DeadlineExceeded` and `ResourceExhausted`, unless it's a message over a
+/// size limit ([`is_message_too_large`]). Anything else, notably
+/// `Unauthenticated` and `InvalidArgument`, would fail the same way again.
+pub fn is_retryable(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        Code::DataLoss | Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted
+    ) && !is_message_too_large(status)
+}
+
+/// Base for [`reconnect_delay`] after a retryable `status`.
+pub fn retry_base_delay(status: &Status) -> u64 {
+    match status.code() {
+        Code::ResourceExhausted => RESOURCE_EXHAUSTED_BASE_DELAY_SECS,
+        _ => BASE_DELAY_SECS,
+    }
+}
+
+/// Backoff before reconnect attempt `retry` (1-based): `base * 2^(retry-1)`
+/// seconds, capped at `cap`, with ±25% random jitter so that many clients
+/// dropped at once don't reconnect in lockstep. Never exceeds `cap`.
+pub fn reconnect_delay(retry: usize, base: u64, cap: u64) -> Duration {
+    let exp = retry.saturating_sub(1).min(63) as u32;
+    let delay = base.saturating_mul(2_u64.saturating_pow(exp)).min(cap) as f64;
+    let jitter = rand::thread_rng().gen_range(0.75..=1.25);
+    Duration::from_secs_f64((delay * jitter).min(cap as f64))
+}
+
+/// Opens after `threshold` reconnects within `window`, however long each
+/// connection lasted, and holds off the next attempt for `cooldown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    pub threshold: usize,
+    pub window: Duration,
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_BREAKER_THRESHOLD,
+            window: DEFAULT_BREAKER_WINDOW,
+            cooldown: DEFAULT_BREAKER_COOLDOWN,
+        }
+    }
+}
+
+/// `--breaker-*` and `--max-outage-secs` flags for the examples that
+/// reconnect.
+#[derive(Debug, Clone, clap::Args)]
+pub struct BreakerArgs {
+    /// After N reconnects within --breaker-window-secs, even ones where the
+    /// connection worked for a while, wait --breaker-cooldown-secs before
+    /// the next attempt (0 = never)
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_BREAKER_THRESHOLD)]
+    pub breaker_threshold: usize,
+
+    /// Window in which --breaker-threshold reconnects are counted
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_BREAKER_WINDOW.as_secs(), value_parser = clap::value_parser!(u64).range(1..))]
+    pub breaker_window_secs: u64,
+
+    /// How long to wait once the breaker opens
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_BREAKER_COOLDOWN.as_secs(), value_parser = clap::value_parser!(u64).range(1..))]
+    pub breaker_cooldown_secs: u64,
+
+    /// Exit with code 7 once reconnecting has gone on for N seconds without
+    /// a connection delivering data, rather than retrying further
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_outage_secs: Option<u64>,
+}
+
+impl BreakerArgs {
+    /// The breaker these flags describe, or `None` when it's turned off.
+    pub fn breaker(&self) -> Option<CircuitBreaker> {
+        (self.breaker_threshold > 0).then(|| CircuitBreaker {
+            threshold: self.breaker_threshold,
+            window: Duration::from_secs(self.breaker_window_secs),
+            cooldown: Duration::from_secs(self.breaker_cooldown_secs),
+        })
+    }
+
+    /// `--max-outage-secs`, if given.
+    pub fn max_outage(&self) -> Option<Duration> {
+        self.max_outage_secs.map(Duration::from_secs)
+    }
+}
+
+/// Why a [`RetryBudget`] ran out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GiveUp {
+    /// [`MAX_RETRIES`] attempts in a row failed
+    MaxRetries,
+    /// Reconnecting has gone on for this long
+    Outage(Duration),
+}
+
+impl GiveUp {
+    /// The error to exit with, `reason` being the last failure.
+    pub fn error(self, reason: &str) -> CliError {
+        match self {
+            Self::MaxRetries => CliError::ConnectFailed(format!(
+                "max retries ({}) reached, last error: {}",
+                MAX_RETRIES, reason
+            )),
+            Self::Outage(outage) => CliError::Outage(format!(
+                "no data for {}s of reconnecting (--max-outage-secs), last error: {}",
+                outage.as_secs(),
+                reason
+            )),
+        }
+    }
+}
+
+/// Consecutive failed connection attempts, counted against
+/// [`MAX_RETRIES`] and an optional outage limit, and recent reconnects,
+/// counted against an optional [`CircuitBreaker`].
+#[derive(Debug, Default)]
+pub struct RetryBudget {
+    failures: usize,
+    breaker: Option<CircuitBreaker>,
+    /// When each reconnect within the breaker's window happened
+    recent: VecDeque<Instant>,
+    max_outage: Option<Duration>,
+    /// First failure since a connection last delivered data
+    outage_since: Option<Instant>,
+}
+
+impl RetryBudget {
+    pub fn new(breaker: Option<CircuitBreaker>) -> Self {
+        Self {
+            breaker,
+            ..Self::default()
+        }
+    }
+
+    /// Give up once failures have gone on for `max_outage` without a reset.
+    pub fn with_max_outage(mut self, max_outage: Option<Duration>) -> Self {
+        self.max_outage = max_outage;
+        self
+    }
+
+    /// Forget earlier failures, once a connection has delivered data. Recent
+    /// reconnects still count towards the breaker.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.outage_since = None;
+    }
+
+    /// Count a failure and return the backoff before the next attempt (the
+    /// breaker's cooldown if this opens it), or why to give up. With an
+    /// outage limit, the backoff is cut short so the last attempt is made
+    /// as the limit runs out.
+    pub fn failed(&mut self, base_delay_secs: u64) -> Result<Duration, GiveUp> {
+        let outage = self.outage_since.get_or_insert_with(Instant::now).elapsed();
+        if let Some(max) = self.max_outage.filter(|&max| outage >= max) {
+            error!(
+                "Reconnecting for {}s without data, over --max-outage-secs {}. Giving up.",
+                outage.as_secs(),
+                max.as_secs()
+            );
+            return Err(GiveUp::Outage(outage));
+        }
+        self.failures += 1;
+        if self.failures >= MAX_RETRIES {
+            error!("Max retries ({}) reached. Giving up.", MAX_RETRIES);
+            return Err(GiveUp::MaxRetries);
+        }
+        let delay = self
+            .trip()
+            .unwrap_or_else(|| reconnect_delay(self.failures, base_delay_secs, MAX_DELAY_SECS));
+        Ok(match self.max_outage {
+            Some(max) => delay.min(max - outage),
+            None => delay,
+        })
+    }
+
+    /// Record a reconnect, returning the cooldown if that makes
+    /// `threshold` within the window. The count starts over afterwards.
+    fn trip(&mut self) -> Option<Duration> {
+        let breaker = self.breaker?;
+        let now = Instant::now();
+        while self
+            .recent
+            .front()
+            .is_some_and(|&at| now.duration_since(at) > breaker.window)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+        if self.recent.len() < breaker.threshold {
+            return None;
+        }
+        warn!(
+            "Circuit breaker open: {} reconnects in the last {}s, pausing for {}s",
+            self.recent.len(),
+            breaker.window.as_secs(),
+            breaker.cooldown.as_secs()
+        );
+        self.recent.clear();
+        Some(breaker.cooldown)
+    }
+
+    /// The next attempt's number, from 1.
+    pub fn attempt(&self) -> usize {
+        self.failures + 1
+    }
+}
+
+/// What [`with_reconnect`] does after handing over a message.
+#[derive(Debug)]
+pub enum Flow {
+    Continue,
+    /// Stop streaming; `with_reconnect` returns `Ok`
+    Stop,
+    /// Drop the connection and open a new one after the usual backoff
+    Reconnect(String),
+}
+
+/// A subscription [`with_reconnect`] can open again after a failure.
+#[async_trait]
+pub trait Resubscribe: Send {
+    type Message: Send;
+    type Stream: Stream<Item = Result<Self::Message, Status>> + Unpin + Send;
+
+    /// Connect and subscribe. A transport error or a retryable status is
+    /// retried; any other error is returned from `with_reconnect`.
+    async fn open(&mut self) -> Result<Self::Stream>;
+
+    /// Handle one message. `index` counts from 0 on each connection, so
+    /// per-connection state can be reset on the first message.
+    async fn on_message(&mut self, message: Self::Message, index: usize) -> Result<Flow>;
+}
+
+/// Open `subscription` and feed it every message until the stream ends or
+/// it returns [`Flow::Stop`]. A failed connect, a retryable status (see
+/// [`is_retryable`]), no message for `read_timeout` or [`Flow::Reconnect`]
+/// backs off and opens it again; the first message on a connection resets
+/// the retry budget, though not the `breaker`. After [`MAX_RETRIES`]
+/// failures in a row this gives up with [`CliError::ConnectFailed`], and
+/// after `max_outage` of failures with [`CliError::Outage`].
+pub async fn with_reconnect<S: Resubscribe>(
+    subscription: &mut S,
+    read_timeout: Duration,
+    breaker: Option<CircuitBreaker>,
+    max_outage: Option<Duration>,
+) -> Result<()> {
+    let mut budget = RetryBudget::new(breaker).with_max_outage(max_outage);
+    loop {
+        let (reason, base_delay_secs) = match subscription.open().await {
+            Ok(messages) => {
+                match read_messages(subscription, messages, read_timeout, &mut budget).await? {
+                    Some(failure) => failure,
+                    None => return Ok(()),
+                }
+            }
+            Err(e) => match open_failure(&e) {
+                Some(failure) => failure,
+                None => return Err(e),
+            },
+        };
+
+        warn!("{}", reason);
+        let delay = match budget.failed(base_delay_secs) {
+            Ok(delay) => delay,
+            Err(give_up) => return Err(give_up.error(&reason).into()),
+        };
+        info!("Waiting {:.1}s before reconnecting...", delay.as_secs_f64());
+        tokio::time::sleep(delay).await;
+        info!(
+            "Reconnecting (attempt {}/{})...",
+            budget.attempt(),
+            MAX_RETRIES
+        );
+    }
+}
+
+/// Read one connection's messages. `None` when streaming is over, otherwise
+/// why to reconnect and the backoff base.
+async fn read_messages<S: Resubscribe>(
+    subscription: &mut S,
+    mut messages: S::Stream,
+    read_timeout: Duration,
+    budget: &mut RetryBudget,
+) -> Result<Option<(String, u64)>> {
+    let mut index = 0;
+    loop {
+        // A half-open connection never errors; treat silence as a drop
+        let message = match tokio::time::timeout(read_timeout, messages.next()).await {
+            Ok(message) => message,
+            Err(_) => Some(Err(Status::unavailable(format!(
+                "no message for {}s",
+                read_timeout.as_secs()
+            )))),
+        };
+        let message = match message {
+            Some(Ok(message)) => message,
+            Some(Err(status)) if is_retryable(&status) => {
+                return Ok(Some(interrupted(&status)));
+            }
+            Some(Err(status)) => {
+                let e = CliError::from_status(&status);
+                error!("gRPC error: {}", e);
+                return Err(e.into());
+            }
+            None => {
+                info!("Stream ended");
+                return Ok(None);
+            }
+        };
+
+        if index == 0 {
+            budget.reset();
+        }
+        match subscription.on_message(message, index).await? {
+            Flow::Continue => index += 1,
+            Flow::Stop => return Ok(None),
+            Flow::Reconnect(reason) => return Ok(Some((reason, BASE_DELAY_SECS))),
+        }
+    }
+}
+
+/// Why to retry a failed [`Resubscribe::open`], or `None` if it's fatal.
+fn open_failure(error: &crate::Error) -> Option<(String, u64)> {
+    if let Some(e) = error.downcast_ref::<tonic::transport::Error>() {
+        return Some((format!("Connection failed: {}", e), BASE_DELAY_SECS));
+    }
+    error
+        .downcast_ref::<Status>()
+        .filter(|status| is_retryable(status))
+        .map(interrupted)
+}
+
+fn interrupted(status: &Status) -> (String, u64) {
+    (
+        format!(
+            "Stream interrupted ({:?}): {}",
+            status.code(),
+            status.message()
+        ),
+        retry_base_delay(status),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Connection::{Refused, Streams};
+
+    /// How one scripted connection goes.
+    enum Connection {
+        /// `open` fails with this status
+        Refused(Code),
+        /// `open` succeeds and the stream yields these, then ends
+        Streams(Vec<Result<u32, Status>>),
+    }
+
+    /// A subscription whose connections are scripted: each `open` takes the
+    /// next one, and once they run out the source stays down.
+    #[derive(Default)]
+    struct Scripted {
+        connections: VecDeque<Connection>,
+        opens: usize,
+        /// Each message with its index on its connection
+        received: Vec<(u32, usize)>,
+    }
+
+    impl Scripted {
+        fn new(connections: impl IntoIterator<Item = Connection>) -> Self {
+            Self {
+                connections: connections.into_iter().collect(),
+                ..Self::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Resubscribe for Scripted {
+        type Message = u32;
+        type Stream = futures::stream::Iter<std::vec::IntoIter<Result<u32, Status>>>;
+
+        async fn open(&mut self) -> Result<Self::Stream> {
+            self.opens += 1;
+            match self.connections.pop_front() {
+                Some(Streams(messages)) => Ok(futures::stream::iter(messages)),
+                Some(Refused(code)) => Err(Status::new(code, "refused").into()),
+                None => Err(Status::unavailable("source down").into()),
+            }
+        }
+
+        async fn on_message(&mut self, message: u32, index: usize) -> Result<Flow> {
+            self.received.push((message, index));
+            Ok(Flow::Continue)
+        }
+    }
+
+    async fn run(subscription: &mut Scripted) -> Result<()> {
+        with_reconnect(subscription, DEFAULT_READ_TIMEOUT, None, None).await
+    }
+
+    #[test]
+    fn delay_never_exceeds_cap() {
+        for retry in 1..=100 {
+            assert!(
+                reconnect_delay(retry, BASE_DELAY_SECS, MAX_DELAY_SECS)
+                    <= Duration::from_secs(MAX_DELAY_SECS)
+            );
+        }
+        // Far past the point where the doubling would overflow
+        assert!(reconnect_delay(usize::MAX, u64::MAX, 60) <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn delay_jitter_stays_within_a_quarter() {
+        for retry in 1..=4 {
+            let nominal = (2 << (retry - 1)) as f64;
+            for _ in 0..200 {
+                let delay = reconnect_delay(retry, 2, 60).as_secs_f64();
+                assert!(
+                    (nominal * 0.75..=nominal * 1.25).contains(&delay),
+                    "retry {}: {}s outside {}s ± 25%",
+                    retry,
+                    delay,
+                    nominal
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transient_codes_are_retried() {
+        for code in [
+            Code::DataLoss,
+            Code::Unavailable,
+            Code::DeadlineExceeded,
+            Code::ResourceExhausted,
+        ] {
+            assert!(
+                is_retryable(&Status::new(code, "stream reset")),
+                "{:?}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn permanent_codes_are_not_retried() {
+        for code in [
+            Code::Ok,
+            Code::Cancelled,
+            Code::Unknown,
+            Code::InvalidArgument,
+            Code::NotFound,
+            Code::AlreadyExists,
+            Code::PermissionDenied,
+            Code::FailedPrecondition,
+            Code::Aborted,
+            Code::OutOfRange,
+            Code::Unimplemented,
+            Code::Internal,
+            Code::Unauthenticated,
+        ] {
+            assert!(
+                !is_retryable(&Status::new(code, "stream reset")),
+                "{:?}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_messages_are_not_retried() {
+        let status = Status::resource_exhausted("message larger than max (5000000 vs. 4194304)");
+        assert!(!is_retryable(&status));
+        assert!(!is_retryable(&Status::out_of_range("Message too large")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_connects_are_retried_until_one_succeeds() {
+        let mut subscription = Scripted::new([
+            Refused(Code::Unavailable),
+            Refused(Code::Unavailable),
+            Streams(vec![Ok(1), Ok(2)]),
+        ]);
+        run(&mut subscription).await.unwrap();
+        assert_eq!(subscription.opens, 3);
+        assert_eq!(subscription.received, [(1, 0), (2, 1)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_dropped_stream_is_reopened_with_indexes_from_zero() {
+        let mut subscription = Scripted::new([
+            Streams(vec![Ok(1), Ok(2), Err(Status::data_loss("reinitialized"))]),
+            Streams(vec![Ok(3)]),
+        ]);
+        run(&mut subscription).await.unwrap();
+        assert_eq!(subscription.opens, 2);
+        assert_eq!(subscription.received, [(1, 0), (2, 1), (3, 0)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn data_resets_the_retry_count() {
+        // More failures in all than MAX_RETRIES, but never that many in a
+        // row: the drop after the data message starts the next run
+        let flaky = || {
+            (0..MAX_RETRIES - 2)
+                .map(|_| Refused(Code::Unavailable))
+                .chain([Streams(vec![Ok(1), Err(Status::unavailable("dropped"))])])
+        };
+        let mut subscription = Scripted::new(flaky().chain(flaky()).chain([Streams(vec![])]));
+        run(&mut subscription).await.unwrap();
+        assert_eq!(subscription.opens, 2 * (MAX_RETRIES - 1) + 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_retries() {
+        let mut subscription = Scripted::default();
+        let error = run(&mut subscription).await.unwrap_err();
+        assert_eq!(subscription.opens, MAX_RETRIES);
+        assert!(matches!(
+            error.downcast_ref::<CliError>(),
+            Some(CliError::ConnectFailed(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_fatal_status_is_not_retried() {
+        let mut subscription = Scripted::new([Refused(Code::Unauthenticated)]);
+        assert!(run(&mut subscription).await.is_err());
+        assert_eq!(subscription.opens, 1);
+    }
+
+    const BREAKER: CircuitBreaker = CircuitBreaker {
+        threshold: 3,
+        window: Duration::from_secs(60),
+        cooldown: Duration::from_secs(600),
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn rapid_reconnects_open_the_breaker() {
+        let mut budget = RetryBudget::new(Some(BREAKER));
+        // Each connection delivers data, so the retry count never builds up
+        for _ in 0..BREAKER.threshold - 1 {
+            budget.reset();
+            assert!(budget.failed(BASE_DELAY_SECS).unwrap() < BREAKER.cooldown);
+            tokio::time::advance(Duration::from_secs(5)).await;
+        }
+        budget.reset();
+        assert_eq!(budget.failed(BASE_DELAY_SECS), Ok(BREAKER.cooldown));
+
+        // The count starts over once it has opened
+        budget.reset();
+        assert!(budget.failed(BASE_DELAY_SECS).unwrap() < BREAKER.cooldown);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnects_spread_past_the_window_keep_the_breaker_closed() {
+        let mut budget = RetryBudget::new(Some(BREAKER));
+        for _ in 0..3 * BREAKER.threshold {
+            budget.reset();
+            assert!(budget.failed(BASE_DELAY_SECS).unwrap() < BREAKER.cooldown);
+            tokio::time::advance(BREAKER.window / 2 + Duration::from_secs(1)).await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_flapping_source_waits_out_the_cooldown() {
+        let flap = || Streams(vec![Ok(1), Err(Status::unavailable("dropped"))]);
+        let mut subscription = Scripted::new(
+            std::iter::repeat_with(flap)
+                .take(BREAKER.threshold)
+                .chain([Streams(vec![])]),
+        );
+
+        let started = Instant::now();
+        with_reconnect(&mut subscription, DEFAULT_READ_TIMEOUT, Some(BREAKER), None)
+            .await
+            .unwrap();
+        assert_eq!(subscription.opens, BREAKER.threshold + 1);
+        assert!(started.elapsed() >= BREAKER.cooldown);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_source_down_past_max_outage_gives_up_on_time() {
+        let max_outage = Duration::from_secs(20);
+        let mut subscription = Scripted::default();
+
+        let started = Instant::now();
+        let error = with_reconnect(
+            &mut subscription,
+            DEFAULT_READ_TIMEOUT,
+            None,
+            Some(max_outage),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CliError>(),
+            Some(CliError::Outage(_))
+        ));
+        // Well before MAX_RETRIES, with the last backoff cut short so the
+        // final attempt lands on the limit
+        assert_eq!(subscription.opens, 5);
+        assert_eq!(started.elapsed().as_secs(), max_outage.as_secs());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn data_restarts_the_outage_clock() {
+        let mut budget = RetryBudget::new(None).with_max_outage(Some(Duration::from_secs(20)));
+        budget.failed(BASE_DELAY_SECS).unwrap();
+        tokio::time::advance(Duration::from_secs(15)).await;
+        budget.reset();
+        budget.failed(BASE_DELAY_SECS).unwrap();
+        tokio::time::advance(Duration::from_secs(15)).await;
+        assert!(budget.failed(BASE_DELAY_SECS).is_ok());
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(matches!(
+            budget.failed(BASE_DELAY_SECS),
+            Err(GiveUp::Outage(outage)) if outage >= Duration::from_secs(20)
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+//! Helpers for pulling individual records out of decoded stream payloads.
+//!
+//! A `StreamResponse.data` payload is the raw JSON written by the node, which
+//! is either a single record, an array of records, or a per-block wrapper
+//! object with an `events` array. In the wrapper form each event may itself
+//! be a `[user, record]` pair.
+
+use serde_json::Value;
+
+/// Split a decoded payload into its individual records.
+pub fn split_records(payload: &Value) -> Vec<&Value> {
+    match payload {
+        Value::Array(items) => items.iter().map(unwrap_pair).collect(),
+        Value::Object(obj) => match obj.get("events") {
+            Some(Value::Array(events)) => events.iter().map(unwrap_pair).collect(),
+            _ => vec![payload],
+        },
+        _ => vec![payload],
+    }
+}
+
+/// `[user, record]` pairs carry the record in their last element.
+fn unwrap_pair(item: &Value) -> &Value {
+    match item.as_array() {
+        Some(pair) if pair.len() == 2 && pair[1].is_object() => &pair[1],
+        _ => item,
+    }
+}
+
+/// A field rendered as plain text: strings unquoted, numbers and bools as
+/// JSON, and missing or null fields as `None`.
+pub fn field_str(record: &Value, field: &str) -> Option<String> {
+    value_str(record.get(field)?)
+}
+
+/// `value` rendered as plain text, as for [`field_str`].
+pub fn value_str(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Drop the records of `payload` for which `keep` returns false, preserving
+/// its shape (array or `events` wrapper). A payload that is a single record
+/// is left untouched either way. Returns the number of records kept.
+pub fn retain_records(payload: &mut Value, mut keep: impl FnMut(&Value) -> bool) -> usize {
+    let has_events = matches!(payload.get("events"), Some(Value::Array(_)));
+    let items = if payload.is_array() {
+        payload.as_array_mut()
+    } else if has_events {
+        payload.get_mut("events").and_then(Value::as_array_mut)
+    } else {
+        None
+    };
+
+    match items {
+        Some(items) => {
+            items.retain(|item| keep(unwrap_pair(item)));
+            items.len()
+        }
+        None => keep(payload) as usize,
+    }
+}
@@ -0,0 +1,166 @@
+//! Discover the stream types an endpoint supports.
+//!
+//! Asks the server for the descriptor of `hyperliquid.StreamType` over gRPC
+//! server reflection, so stream types added server-side are listed without
+//! rebuilding. Endpoints that don't offer reflection get the list compiled
+//! into this build instead. Both come from the same kind of descriptor, so
+//! they're read the same way.
+
+use std::fmt;
+
+use prost::Message;
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use tonic_reflection::pb::{
+    server_reflection_client::ServerReflectionClient, server_reflection_request::MessageRequest,
+    server_reflection_response::MessageResponse, ServerReflectionRequest,
+};
+use tracing::warn;
+
+use crate::common::{authed_request, build_endpoint, validate_token};
+use crate::error::CliError;
+use crate::health::{describe_connect_error, DEFAULT_TIMEOUT};
+use crate::hyperliquid::{StreamType, FILE_DESCRIPTOR_SET};
+use crate::stream::StreamOptions;
+use crate::Result;
+
+/// Fully qualified name of the stream type enum
+pub const STREAM_TYPE_SYMBOL: &str = "hyperliquid.StreamType";
+
+/// One value of the `StreamType` enum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamTypeInfo {
+    pub name: String,
+    pub number: i32,
+}
+
+impl StreamTypeInfo {
+    /// Whether this build has a name for the type. Unknown ones can't be
+    /// subscribed to until the protos here are updated.
+    pub fn is_known(&self) -> bool {
+        StreamType::try_from(self.number).is_ok()
+    }
+}
+
+/// Where a list of stream types came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTypeSource {
+    Reflection,
+    CompiledIn,
+}
+
+impl fmt::Display for StreamTypeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Reflection => "server reflection",
+            Self::CompiledIn => "the protos compiled into this build",
+        })
+    }
+}
+
+/// The stream types the endpoint in `options` supports, from server
+/// reflection if it's available and the compiled-in list otherwise.
+/// Failing to connect at all is an error rather than a fallback.
+pub async fn list_stream_types(
+    options: &StreamOptions,
+) -> Result<(Vec<StreamTypeInfo>, StreamTypeSource)> {
+    match tokio::time::timeout(DEFAULT_TIMEOUT, reflect_stream_types(options)).await {
+        Ok(Ok(types)) => return Ok((types, StreamTypeSource::Reflection)),
+        Ok(Err(e)) if e.downcast_ref::<CliError>().is_some() => return Err(e),
+        Ok(Err(e)) => match e.downcast_ref::<tonic::Status>() {
+            Some(status) => warn!(
+                "Server reflection unavailable: {}",
+                status.code().description()
+            ),
+            None => warn!("Server reflection unavailable: {}", e),
+        },
+        Err(_) => warn!(
+            "Server reflection unavailable: no answer in {}s",
+            DEFAULT_TIMEOUT.as_secs()
+        ),
+    }
+    Ok((compiled_stream_types()?, StreamTypeSource::CompiledIn))
+}
+
+/// The stream types compiled into this build.
+pub fn compiled_stream_types() -> Result<Vec<StreamTypeInfo>> {
+    let set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)?;
+    stream_types_in(&set.file).ok_or_else(|| {
+        format!(
+            "{} missing from the compiled-in descriptors",
+            STREAM_TYPE_SYMBOL
+        )
+        .into()
+    })
+}
+
+/// Ask the server for the file defining `StreamType` and read its values.
+pub async fn reflect_stream_types(options: &StreamOptions) -> Result<Vec<StreamTypeInfo>> {
+    validate_token(&options.token, options.channel.auth)?;
+    let channel = build_endpoint(&options.endpoint, &options.channel)?
+        .connect()
+        .await
+        .map_err(|e| CliError::ConnectFailed(describe_connect_error(&e)))?;
+    let mut client = ServerReflectionClient::new(channel);
+
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::FileContainingSymbol(
+            STREAM_TYPE_SYMBOL.to_string(),
+        )),
+    };
+    let request = authed_request(
+        tokio_stream::once(request),
+        &options.token,
+        options.channel.auth,
+    )?;
+    let mut responses = client.server_reflection_info(request).await?.into_inner();
+    let response = responses
+        .message()
+        .await?
+        .ok_or("reflection stream ended without a response")?;
+
+    match response.message_response {
+        Some(MessageResponse::FileDescriptorResponse(found)) => {
+            let files = found
+                .file_descriptor_proto
+                .iter()
+                .map(|bytes| FileDescriptorProto::decode(bytes.as_slice()))
+                .collect::<Result<Vec<_>, _>>()?;
+            stream_types_in(&files).ok_or_else(|| {
+                format!(
+                    "the server's descriptors don't define {}",
+                    STREAM_TYPE_SYMBOL
+                )
+                .into()
+            })
+        }
+        Some(MessageResponse::ErrorResponse(e)) => Err(format!(
+            "server couldn't describe {}: {}",
+            STREAM_TYPE_SYMBOL, e.error_message
+        )
+        .into()),
+        _ => Err("unexpected reflection response".into()),
+    }
+}
+
+/// The values of `StreamType` in `files`, other than the zero value
+/// (`UNKNOWN`), which can't be subscribed to.
+fn stream_types_in(files: &[FileDescriptorProto]) -> Option<Vec<StreamTypeInfo>> {
+    let (package, name) = STREAM_TYPE_SYMBOL.rsplit_once('.')?;
+    let stream_type = files
+        .iter()
+        .filter(|file| file.package() == package)
+        .flat_map(|file| &file.enum_type)
+        .find(|e| e.name() == name)?;
+    Some(
+        stream_type
+            .value
+            .iter()
+            .filter(|value| value.number() != 0)
+            .map(|value| StreamTypeInfo {
+                name: value.name().to_string(),
+                number: value.number(),
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,153 @@
+//! Replay recorded data through the raw stream's output path.
+//!
+//! Two line-delimited formats are accepted, and may be mixed:
+//!
+//! - envelopes `{"block_number": N, "timestamp": MS, "data": ...}`, as sent
+//!   by `--ws-port`, where `data` is the decoded payload or its JSON text;
+//! - raw lines from an S3 block-range file, numbered from the first block
+//!   in the file name (e.g. `830000000-830010000`) and timed by
+//!   `abci_block.time`.
+//!
+//! [`ReplaySource`] yields each line as the message the live stream would
+//! have received, and [`replay`] runs it through the live stream's read
+//! loop, so decoding, limits, printing and every sink behave identically.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::hyperliquid::{StreamResponse, StreamType};
+use crate::s3::block_timestamp_ms;
+use crate::source::{MessageSource, RawMessage};
+use crate::stream::{stream_source, until_ctrl_c, StreamOptions};
+use crate::Result;
+
+/// Replay `path` as `stream_type`. Records are paced by their timestamps
+/// divided by `speed`; a `speed` of 0 replays as fast as possible.
+/// Server-side filters don't apply to replays and are only reported.
+pub async fn replay(
+    path: &Path,
+    stream_type: StreamType,
+    filters: HashMap<String, Vec<String>>,
+    options: &StreamOptions,
+    speed: f64,
+) -> Result<()> {
+    let source = ReplaySource::open(path, speed).await?;
+    if !filters.is_empty() {
+        warn!("Filters are not applied when replaying");
+    }
+    info!(
+        "Replaying {} as {}...",
+        path.display(),
+        stream_type.as_str_name()
+    );
+    until_ctrl_c(|cancel| stream_source(stream_type, &filters, source, options, cancel))
+        .await
+        .map(|_| ())
+}
+
+/// The lines of a recorded file as data messages, paced like the original
+/// stream.
+pub struct ReplaySource {
+    lines: Lines<BufReader<File>>,
+    /// Block of a raw S3 line, from the file name
+    first_block: u64,
+    line_index: u64,
+    speed: f64,
+    /// First timestamp and when it was yielded
+    clock: Option<(u64, Instant)>,
+    yielded: usize,
+}
+
+impl ReplaySource {
+    /// Open `path`, pacing messages by their timestamps divided by `speed`
+    /// (0 for as fast as possible).
+    pub async fn open(path: &Path, speed: f64) -> Result<Self> {
+        let file = File::open(path)
+            .await
+            .map_err(|e| format!("cannot open replay file {}: {}", path.display(), e))?;
+        let first_block = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split('-').next())
+            .and_then(|start| start.parse::<u64>().ok())
+            .unwrap_or(0);
+        Ok(Self {
+            lines: BufReader::new(file).lines(),
+            first_block,
+            line_index: 0,
+            speed,
+            clock: None,
+            yielded: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl MessageSource for ReplaySource {
+    async fn next(&mut self) -> Result<Option<RawMessage>> {
+        loop {
+            let Some(line) = self.lines.next_line().await? else {
+                info!("Replay finished ({} message(s))", self.yielded);
+                return Ok(None);
+            };
+            let index = self.line_index;
+            self.line_index += 1;
+            let Some(response) = parse_line(&line, index, self.first_block) else {
+                continue;
+            };
+
+            if self.speed > 0.0 && response.timestamp > 0 {
+                let (first_ts, started) = *self
+                    .clock
+                    .get_or_insert((response.timestamp, Instant::now()));
+                let offset = response.timestamp.saturating_sub(first_ts) as f64 / self.speed;
+                tokio::time::sleep_until(started + Duration::from_secs_f64(offset / 1000.0)).await;
+            }
+            self.yielded += 1;
+            return Ok(Some(response));
+        }
+    }
+}
+
+/// A replay line as a `StreamResponse`, or `None` for blank or unparseable
+/// lines (which are reported).
+fn parse_line(line: &str, index: u64, first_block: u64) -> Option<StreamResponse> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("Skipping unparseable line {}: {}", index + 1, e);
+            return None;
+        }
+    };
+
+    if let (Some(block_number), Some(data)) = (
+        value.get("block_number").and_then(Value::as_u64),
+        value.get("data"),
+    ) {
+        return Some(StreamResponse {
+            block_number,
+            timestamp: value.get("timestamp").and_then(Value::as_u64).unwrap_or(0),
+            data: match data {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            },
+        });
+    }
+
+    Some(StreamResponse {
+        block_number: first_block + index,
+        timestamp: block_timestamp_ms(&value),
+        data: line.to_string(),
+    })
+}
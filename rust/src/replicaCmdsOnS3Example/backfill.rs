@@ -0,0 +1,638 @@
+//! S3 Blocks Backfill Example
+//! ==========================
+//!
+//! WHY THIS EXISTS:
+//! ----------------
+//! The `blocks` stream contains raw HyperLiquid replica commands - the fundamental
+//! building blocks of the chain. Unlike other streams (trades, orders, book_updates),
+//! blocks are ONLY available via gRPC streaming.
+//!
+//! The problem: gRPC connections can disconnect. When that happens, you miss blocks.
+//! There's no HTTP API to query historical blocks.
+//!
+//! The solution: The Hyperliquid Foundation maintains a public S3 bucket with
+//! historical data. You can backfill missed blocks directly from there.
+//!
+//!
+//! S3 BUCKET STRUCTURE:
+//! --------------------
+//! Bucket: s3://hl-mainnet-node-data/ (testnet: s3://hl-testnet-node-data/)
+//! Access: Requester pays (you pay for data transfer)
+//!
+//! Available prefixes:
+//!   - replica_cmds/          <- blocks data
+//!
+//! replica_cmds/ directory structure:
+//!   replica_cmds/{CHECKPOINT_TIMESTAMP}/{DATE}/{BLOCK_RANGE_FILE}
+//!
+//!   Example: replica_cmds/1704067200/20240101/830000000-830010000
+//!
+//!
+//! FILE FORMAT:
+//! ------------
+//! - JSON Lines format (one JSON object per line)
+//! - NO block_number field in the JSON (ordering is implicit by line position)
+//! - Files are MASSIVE: 3-7 GB each (uncompressed)
+//! - Files may be gzip or zstd compressed (`.gz` / `.zst` suffix, or detected
+//!   from the first bytes); they are decompressed while streaming
+//!
+//!
+//! USAGE:
+//! ------
+//! Add to Cargo.toml:
+//!   aws-config = "1.0"
+//!   aws-sdk-s3 = "1.0"
+//!   tokio = { version = "1", features = ["full"] }
+//!
+//! cargo run --bin s3_blocks_backfill -- --discover
+//! cargo run --bin s3_blocks_backfill -- --discover --network testnet
+//! cargo run --bin s3_blocks_backfill -- --discover --bucket my-mirror-bucket
+//! cargo run --bin s3_blocks_backfill -- --block 830000000 --progress
+//! cargo run --bin s3_blocks_backfill -- --start-block 830000000 --end-block 830000999 --output blocks.ndjson
+//! cargo run --bin s3_blocks_backfill -- --block 830000000 --max-mbps 50
+//! cargo run --bin s3_blocks_backfill -- --block 830000000 --estimate-cost
+//! cargo run --bin s3_blocks_backfill -- --bucket my-mirror --requester-pays false --block 830000000
+//! cargo run --bin s3_blocks_backfill -- ranges
+//! cargo run --bin s3_blocks_backfill -- ranges --checkpoint 1704067200 --json
+//! cargo run --bin s3_blocks_backfill -- --local-dir ~/hl/data --start-block 830000000 --end-block 830000999
+//!
+//!
+//! LOCAL NODE DATA:
+//! ----------------
+//! A node writes the same `replica_cmds/` tree to its data directory.
+//! `--local-dir` reads blocks, ranges and the checkpoint listing from there
+//! instead of S3: no AWS credentials, and no transfer costs.
+//!
+//!
+//! COST CONSIDERATIONS:
+//! --------------------
+//! - Requester pays bucket - you pay for data transfer
+//! - Files are 3-7 GB each
+//! - Stream instead of downloading entirely when possible
+//! - `--estimate-cost` sums the sizes of the files a fetch would read and
+//!   prices them at `--cost-per-gb` first, stopping over `--cost-threshold`
+//!   unless `--confirm` is given
+//!
+//!
+//! OUTPUT:
+//! -------
+//! Fetched blocks are written as NDJSON, to stdout or `--output`: each line
+//! is the block's JSON object from the file with its `block_number` added.
+//!
+//! The reading itself lives in the library (`hyperliquid_grpc::s3`), so the
+//! raw stream can fill gaps from the same bucket; this file is the command.
+
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use aws_sdk_s3::Client;
+use clap::{Subcommand, ValueEnum};
+use hyperliquid_grpc::compressed::{OutputCompression, OutputFile};
+use hyperliquid_grpc::error::CliError;
+use hyperliquid_grpc::s3::{
+    backfill_local_range, backfill_range, files_size, find_block_ranges, list_local, list_s3,
+    Block, BlockRange, Bucket, RateLimit, BLOCKS_PREFIX, MAINNET_BUCKET, TESTNET_BUCKET,
+};
+use tracing::{info, warn};
+
+/// S3 transfer out to the internet, per GB, at the first pricing tier
+const DEFAULT_COST_PER_GB: f64 = 0.09;
+const DEFAULT_COST_THRESHOLD: f64 = 1.0;
+const BYTES_PER_GB: f64 = (1u64 << 30) as f64;
+
+/// Networks with a known public node-data bucket
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Network {
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    fn bucket(self) -> &'static str {
+        match self {
+            Network::Mainnet => MAINNET_BUCKET,
+            Network::Testnet => TESTNET_BUCKET,
+        }
+    }
+}
+
+/// Where blocks are read from
+enum Source {
+    S3 {
+        client: Client,
+        bucket: Bucket,
+    },
+    /// A node's data directory, holding `replica_cmds/`
+    Local(PathBuf),
+}
+
+impl Source {
+    /// Names under `prefix` (`replica_cmds/...`), sorted.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, CliError> {
+        match self {
+            Source::S3 { client, bucket } => list_s3(client, bucket, prefix)
+                .await
+                .map_err(|e| CliError::S3Error(format!("error listing S3: {}", e))),
+            Source::Local(root) => {
+                let dir = root.join(prefix);
+                list_local(&dir)
+                    .await
+                    .map_err(|e| CliError::Other(format!("error listing {}: {}", dir.display(), e)))
+            }
+        }
+    }
+
+    /// An error reading from this source, of the kind that matches it.
+    fn error(&self, message: String) -> CliError {
+        match self {
+            Source::S3 { .. } => CliError::S3Error(message),
+            Source::Local(_) => CliError::Other(message),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::S3 { bucket, .. } => write!(f, "{}", bucket),
+            Source::Local(root) => write!(f, "{}", root.display()),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct BackfillArgs {
+    /// Network whose public bucket to read
+    #[arg(long, value_enum, default_value = "mainnet")]
+    network: Network,
+
+    /// Bucket name, e.g. a mirror (overrides --network)
+    #[arg(long)]
+    bucket: Option<String>,
+
+    /// Whether the bucket is requester-pays, as the public ones are. Pass
+    /// false for mirrors that reject the requester-pays header
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    requester_pays: bool,
+
+    /// Read from a node's data directory (the one holding replica_cmds/, or
+    /// replica_cmds/ itself) instead of S3
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["network", "bucket", "requester_pays", "max_mbps"])]
+    local_dir: Option<PathBuf>,
+
+    /// Fetch this one block (downloads part of a multi-GB file). Same as
+    /// --start-block N alone
+    #[arg(long, value_name = "N", conflicts_with_all = ["start_block", "end_block"])]
+    block: Option<u64>,
+
+    /// First block to fetch
+    #[arg(long, value_name = "N")]
+    start_block: Option<u64>,
+
+    /// Last block to fetch, inclusive (default: --start-block)
+    #[arg(long, value_name = "N", requires = "start_block")]
+    end_block: Option<u64>,
+
+    /// Write the fetched blocks to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Compress --output as it is written (default: from its extension,
+    /// .zst or .gz)
+    #[arg(long, value_enum, requires = "output")]
+    compress_output: Option<OutputCompression>,
+
+    /// List the checkpoints and the first dates of the latest one instead
+    /// of fetching blocks
+    #[arg(long, conflicts_with_all = ["block", "start_block", "output"])]
+    discover: bool,
+
+    /// Report download progress and ETA on stderr
+    #[arg(long)]
+    progress: bool,
+
+    /// Cap the download rate at N megabits per second (0: unlimited). The
+    /// average rate is logged at the end
+    #[arg(long, value_name = "N", default_value_t = 0.0)]
+    max_mbps: f64,
+
+    /// Before fetching, log the size of the files the fetch would read and
+    /// what transferring all of them would cost
+    #[arg(long, conflicts_with = "local_dir")]
+    estimate_cost: bool,
+
+    /// Transfer price in dollars per GB for --estimate-cost
+    #[arg(long, value_name = "USD", default_value_t = DEFAULT_COST_PER_GB, requires = "estimate_cost")]
+    cost_per_gb: f64,
+
+    /// With --estimate-cost, don't fetch if the estimate is over this many
+    /// dollars, unless --confirm is given
+    #[arg(long, value_name = "USD", default_value_t = DEFAULT_COST_THRESHOLD, requires = "estimate_cost")]
+    cost_threshold: f64,
+
+    /// Fetch even if --estimate-cost comes out over --cost-threshold
+    #[arg(long, requires = "estimate_cost")]
+    confirm: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the block ranges available per date, and overall
+    Ranges {
+        /// Checkpoint to inspect (default: the latest)
+        #[arg(long)]
+        checkpoint: Option<String>,
+
+        /// Print the coverage as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// `block`'s JSON object with its `block_number` added, or the data wrapped
+/// as `{"block_number":..,"data":..}` if it isn't an object.
+fn block_line(block: Block) -> serde_json::Value {
+    match block.data {
+        serde_json::Value::Object(mut fields) => {
+            fields.insert("block_number".to_string(), block.block_number.into());
+            serde_json::Value::Object(fields)
+        }
+        data => serde_json::json!({ "block_number": block.block_number, "data": data }),
+    }
+}
+
+/// Fetch blocks `start..=end` and write them as NDJSON to `output`, or
+/// stdout.
+async fn write_blocks(
+    source: &Source,
+    start: u64,
+    end: u64,
+    output: Option<PathBuf>,
+    compression: Option<OutputCompression>,
+    progress: bool,
+    limit: Option<RateLimit>,
+) -> Result<(), CliError> {
+    let mut file =
+        match &output {
+            Some(path) => Some(OutputFile::create(path, compression).map_err(|e| {
+                CliError::BadArgs(format!("can't create {}: {}", path.display(), e))
+            })?),
+            None => None,
+        };
+    let mut stdout = std::io::stdout().lock();
+    let mut writer = BufWriter::new(match &mut file {
+        Some(file) => file as &mut dyn Write,
+        None => &mut stdout,
+    });
+    info!("Fetching blocks {} to {} from {}", start, end, source);
+
+    // The first write error stops further writes; the download still runs
+    // to the end of the range
+    let mut write_error = None;
+    let on_block = |block| {
+        if write_error.is_none() {
+            if let Err(e) = writeln!(writer, "{}", block_line(block)) {
+                write_error = Some(e);
+            }
+        }
+    };
+    let result = match source {
+        Source::S3 { client, bucket } => backfill_range(
+            client,
+            bucket,
+            start,
+            end,
+            progress,
+            limit.clone(),
+            on_block,
+        )
+        .await
+        .map_err(|e| e.to_string()),
+        Source::Local(root) => backfill_local_range(root, start, end, progress, on_block)
+            .await
+            .map_err(|e| e.to_string()),
+    };
+    let write_error = write_error.or_else(|| writer.flush().err());
+    drop(writer);
+    // A compressed file isn't readable to the end until it's finished
+    let write_error = write_error.or_else(|| file.as_mut().and_then(|f| f.finish().err()));
+    if let Some(limit) = &limit {
+        limit.report();
+    }
+
+    let fetched = result
+        .map_err(|e| source.error(format!("error fetching blocks {} to {}: {}", start, end, e)))?;
+    if let Some(e) = write_error {
+        return Err(CliError::Other(format!("error writing blocks: {}", e)));
+    }
+    if fetched == 0 {
+        return Err(source.error(format!(
+            "blocks {} to {} not found in {}",
+            start, end, source
+        )));
+    }
+    let expected = end - start + 1;
+    if fetched < expected {
+        warn!(
+            "{} of {} block(s) not found in {}",
+            expected - fetched,
+            expected,
+            source
+        );
+    }
+    match &output {
+        Some(path) => info!("Wrote {} block(s) to {}", fetched, path.display()),
+        None => info!("Wrote {} block(s)", fetched),
+    }
+    Ok(())
+}
+
+/// Log the most that fetching blocks `start..=end` could transfer (the
+/// whole of every file they're in) and its cost at `cost_per_gb`. Fails if
+/// that's over `threshold` dollars, unless `confirm`.
+async fn check_cost(
+    client: &Client,
+    bucket: &Bucket,
+    start: u64,
+    end: u64,
+    cost_per_gb: f64,
+    threshold: f64,
+    confirm: bool,
+) -> Result<(), CliError> {
+    let sized = async {
+        let ranges = find_block_ranges(client, bucket, start, end).await?;
+        let bytes = files_size(client, bucket, &ranges).await?;
+        Ok::<_, aws_sdk_s3::Error>((ranges.len(), bytes))
+    };
+    let (files, bytes) = sized
+        .await
+        .map_err(|e| CliError::S3Error(format!("error estimating the cost: {}", e)))?;
+    let gb = bytes as f64 / BYTES_PER_GB;
+    let cost = gb * cost_per_gb;
+    info!(
+        "Estimated transfer: up to {:.2} GB in {} file(s), about ${:.2} at ${}/GB",
+        gb, files, cost, cost_per_gb
+    );
+    if cost > threshold && !confirm {
+        return Err(CliError::BadArgs(format!(
+            "estimated cost ${:.2} is over --cost-threshold ${:.2}; pass --confirm to fetch anyway",
+            cost, threshold
+        )));
+    }
+    Ok(())
+}
+
+/// Blocks covered by one date directory of a checkpoint
+#[derive(Debug)]
+pub struct DateCoverage {
+    pub date: String,
+    pub files: usize,
+    pub min_block: u64,
+    pub max_block: u64,
+}
+
+/// Block coverage of each date in `checkpoint`, in date order. On S3 this
+/// costs one list call per date directory (more if it has over 1000
+/// files); dates with no block range files are left out.
+async fn checkpoint_coverage(
+    source: &Source,
+    checkpoint: &str,
+) -> Result<Vec<DateCoverage>, CliError> {
+    let dates = source
+        .list(&format!("{}/{}/", BLOCKS_PREFIX, checkpoint))
+        .await?;
+
+    let mut coverage = Vec::new();
+    for date in dates {
+        let files = source
+            .list(&format!("{}/{}/{}/", BLOCKS_PREFIX, checkpoint, date))
+            .await?;
+        let ranges: Vec<BlockRange> = files
+            .iter()
+            .filter_map(|file| {
+                BlockRange::from_s3_key(&format!(
+                    "{}/{}/{}/{}",
+                    BLOCKS_PREFIX, checkpoint, date, file
+                ))
+            })
+            .collect();
+        if let (Some(min_block), Some(max_block)) = (
+            ranges.iter().map(|br| br.start_block).min(),
+            ranges.iter().map(|br| br.end_block).max(),
+        ) {
+            coverage.push(DateCoverage {
+                date,
+                files: ranges.len(),
+                min_block,
+                max_block,
+            });
+        }
+    }
+    Ok(coverage)
+}
+
+/// Print the coverage of `checkpoint` (default: the latest) as a table or
+/// JSON.
+async fn print_ranges(
+    source: &Source,
+    checkpoint: Option<String>,
+    json: bool,
+) -> Result<(), CliError> {
+    let checkpoint = match checkpoint {
+        Some(checkpoint) => checkpoint,
+        None => source
+            .list(&format!("{}/", BLOCKS_PREFIX))
+            .await?
+            .pop()
+            .ok_or_else(|| source.error(format!("no checkpoints in {}", source)))?,
+    };
+    info!("Listing block ranges in checkpoint {}...", checkpoint);
+
+    let coverage = checkpoint_coverage(source, &checkpoint).await?;
+    let min_block = coverage.iter().map(|c| c.min_block).min();
+    let max_block = coverage.iter().map(|c| c.max_block).max();
+
+    if json {
+        let dates: Vec<serde_json::Value> = coverage
+            .iter()
+            .map(|c| {
+                serde_json::json!({
+                    "date": c.date,
+                    "files": c.files,
+                    "min_block": c.min_block,
+                    "max_block": c.max_block,
+                })
+            })
+            .collect();
+        let mut out = serde_json::json!({
+            "checkpoint": checkpoint,
+            "dates": dates,
+            "min_block": min_block,
+            "max_block": max_block,
+        });
+        match source {
+            Source::S3 { bucket, .. } => out["bucket"] = bucket.name.as_str().into(),
+            Source::Local(root) => out["local_dir"] = root.display().to_string().into(),
+        }
+        println!("{}", out);
+        return Ok(());
+    }
+
+    println!(
+        "{:<10} {:>6} {:>14} {:>14}",
+        "DATE", "FILES", "MIN BLOCK", "MAX BLOCK"
+    );
+    for c in &coverage {
+        println!(
+            "{:<10} {:>6} {:>14} {:>14}",
+            c.date, c.files, c.min_block, c.max_block
+        );
+    }
+    match (min_block, max_block) {
+        (Some(min), Some(max)) => println!(
+            "\nCheckpoint {}: blocks {} to {} across {} date(s)",
+            checkpoint,
+            min,
+            max,
+            coverage.len()
+        ),
+        _ => println!("\nCheckpoint {} has no block range files", checkpoint),
+    }
+    Ok(())
+}
+
+pub async fn run(args: BackfillArgs) -> Result<(), CliError> {
+    let bucket = args
+        .bucket
+        .unwrap_or_else(|| args.network.bucket().to_string());
+    if bucket.trim().is_empty() {
+        return Err(CliError::BadArgs("--bucket must not be empty".to_string()));
+    }
+    let fetch = args.block.is_some() || args.start_block.is_some();
+    if args.command.is_none() && !fetch && !args.discover {
+        return Err(CliError::BadArgs(
+            "nothing to do: pass --block or --start-block to fetch blocks, --discover to explore the archive, or the ranges command".to_string(),
+        ));
+    }
+    if let (Some(start), Some(end)) = (args.start_block, args.end_block) {
+        if end < start {
+            return Err(CliError::BadArgs(format!(
+                "--end-block {} is before --start-block {}",
+                end, start
+            )));
+        }
+    }
+    if !args.max_mbps.is_finite() || args.max_mbps < 0.0 {
+        return Err(CliError::BadArgs(format!(
+            "invalid --max-mbps {}",
+            args.max_mbps
+        )));
+    }
+    for (flag, value) in [
+        ("--cost-per-gb", args.cost_per_gb),
+        ("--cost-threshold", args.cost_threshold),
+    ] {
+        if !value.is_finite() || value < 0.0 {
+            return Err(CliError::BadArgs(format!("invalid {} {}", flag, value)));
+        }
+    }
+    if args.estimate_cost && !fetch {
+        return Err(CliError::BadArgs(
+            "--estimate-cost needs --block or --start-block".to_string(),
+        ));
+    }
+    if args.output.is_some() && !fetch {
+        return Err(CliError::BadArgs(
+            "--output needs --block or --start-block".to_string(),
+        ));
+    }
+
+    info!("S3 Blocks Backfill Example");
+    let source = match args.local_dir {
+        Some(dir) => {
+            // Accept replica_cmds/ itself as well as the directory holding it
+            let root = match dir.file_name() {
+                Some(name) if name == BLOCKS_PREFIX && !dir.join(BLOCKS_PREFIX).is_dir() => {
+                    dir.parent().map(PathBuf::from).unwrap_or_default()
+                }
+                _ => dir.clone(),
+            };
+            if !root.join(BLOCKS_PREFIX).is_dir() {
+                return Err(CliError::BadArgs(format!(
+                    "{} has no {}/ directory",
+                    dir.display(),
+                    BLOCKS_PREFIX
+                )));
+            }
+            info!("Local directory: {}", root.display());
+            Source::Local(root)
+        }
+        None => {
+            info!("Bucket: s3://{}/", bucket);
+            // Load AWS config
+            let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+            Source::S3 {
+                client: Client::new(&config),
+                bucket: Bucket {
+                    name: bucket,
+                    requester_pays: args.requester_pays,
+                },
+            }
+        }
+    };
+
+    if let Some(Command::Ranges { checkpoint, json }) = args.command {
+        return print_ranges(&source, checkpoint, json).await;
+    }
+
+    // Fetching blocks downloads (parts of) multi-GB files, so it's opt-in
+    if let Some(start) = args.block.or(args.start_block) {
+        let end = args.end_block.unwrap_or(start);
+        if let (true, Source::S3 { client, bucket }) = (args.estimate_cost, &source) {
+            check_cost(
+                client,
+                bucket,
+                start,
+                end,
+                args.cost_per_gb,
+                args.cost_threshold,
+                args.confirm,
+            )
+            .await?;
+        }
+        let limit = RateLimit::mbps(args.max_mbps);
+        return write_blocks(
+            &source,
+            start,
+            end,
+            args.output,
+            args.compress_output,
+            args.progress,
+            limit,
+        )
+        .await;
+    }
+
+    println!("{}", "=".repeat(60));
+    match source {
+        Source::S3 { .. } => println!("DISCOVERING S3 STRUCTURE"),
+        Source::Local(_) => println!("DISCOVERING LOCAL STRUCTURE"),
+    }
+    println!("{}\n", "=".repeat(60));
+
+    // List checkpoints
+    let checkpoints = source.list(&format!("{}/", BLOCKS_PREFIX)).await?;
+    println!("Checkpoints: {:?}", checkpoints);
+
+    if let Some(latest) = checkpoints.last() {
+        if let Ok(dates) = source.list(&format!("{}/{}/", BLOCKS_PREFIX, latest)).await {
+            let display: Vec<_> = dates.iter().take(5).collect();
+            println!("Dates in checkpoint {}: {:?} ...", latest, display);
+        }
+    }
+    Ok(())
+}
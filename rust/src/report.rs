@@ -0,0 +1,225 @@
+//! Machine-readable summary of a raw stream run, for `--report`.
+//!
+//! Batch jobs and CI can assert on this instead of scraping logs: how much
+//! arrived and how fast, how complete it was (reconnects, gaps, first and
+//! last block) and why the run stopped. Each reconnect is listed with what
+//! caused it, to line up with upstream incidents. It's written once, when the stream
+//! stops cleanly or gives up reconnecting, through a temporary file renamed
+//! into place so a reader never sees half a report.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::common::write_atomic;
+use crate::hyperliquid::StreamType;
+use crate::reconnect::GiveUp;
+use crate::Result;
+
+/// Why a run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExitReason {
+    /// The server (or replay file) ended the stream
+    #[default]
+    Ended,
+    /// `sample` messages were received
+    Sampled,
+    /// A message past `to_block` arrived
+    ToBlock,
+    /// Ctrl-C or the cancellation token
+    Interrupted,
+    /// Too many reconnects in a row failed
+    MaxRetries,
+    /// Reconnecting went on longer than `max_outage`
+    Outage,
+}
+
+impl ExitReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ended => "ended",
+            Self::Sampled => "sampled",
+            Self::ToBlock => "to_block",
+            Self::Interrupted => "interrupted",
+            Self::MaxRetries => "max_retries",
+            Self::Outage => "outage",
+        }
+    }
+}
+
+impl From<GiveUp> for ExitReason {
+    fn from(give_up: GiveUp) -> Self {
+        match give_up {
+            GiveUp::MaxRetries => Self::MaxRetries,
+            GiveUp::Outage(_) => Self::Outage,
+        }
+    }
+}
+
+/// Data messages per wall-clock second since the run started, keeping the
+/// busiest second.
+#[derive(Debug)]
+pub struct RateMeter {
+    started: Instant,
+    /// Second of the run being counted, and its messages so far
+    second: u64,
+    in_second: u64,
+    peak: u64,
+}
+
+impl RateMeter {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            second: 0,
+            in_second: 0,
+            peak: 0,
+        }
+    }
+
+    pub fn record(&mut self) {
+        let second = self.started.elapsed().as_secs();
+        if second != self.second {
+            self.second = second;
+            self.in_second = 0;
+        }
+        self.in_second += 1;
+        self.peak = self.peak.max(self.in_second);
+    }
+
+    /// Most messages seen in one second
+    pub fn peak(&self) -> u64 {
+        self.peak
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+impl Default for RateMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One reconnect of a raw stream: what dropped the connection and how long
+/// the client waited before the next attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectEvent {
+    /// Unix ms when the connection was dropped
+    pub at_ms: i64,
+    pub stream_type: StreamType,
+    /// Retry number of the attempt that follows, from 1
+    pub retry: usize,
+    /// gRPC status code, or `None` when connecting failed
+    pub code: Option<tonic::Code>,
+    /// What happened, with the endpoint's tokens redacted
+    pub message: String,
+    /// Backoff before the next attempt; `None` when the client gave up
+    pub delay: Option<Duration>,
+    /// Last block received before the drop
+    pub last_block: Option<u64>,
+}
+
+impl ReconnectEvent {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "at_ms": self.at_ms,
+            "stream_type": self.stream_type.as_str_name(),
+            "retry": self.retry,
+            "code": self.code.map(|code| format!("{:?}", code)),
+            "message": self.message,
+            "delay_secs": self.delay.map(|delay| round(delay.as_secs_f64())),
+            "last_block": self.last_block,
+        })
+    }
+}
+
+/// Secrets shorter than this aren't redacted: they would match ordinary
+/// words, and real tokens are far longer
+const MIN_SECRET_LEN: usize = 8;
+
+/// `message` with each of `secrets` replaced by `***`, so a token echoed in
+/// an error never reaches a log or report.
+pub fn redact<'a>(message: &str, secrets: impl IntoIterator<Item = &'a str>) -> String {
+    secrets
+        .into_iter()
+        .filter(|secret| secret.len() >= MIN_SECRET_LEN)
+        .fold(message.to_string(), |message, secret| {
+            message.replace(secret, "***")
+        })
+}
+
+/// What a run did, as written by `--report`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    pub stream_type: StreamType,
+    pub exit_reason: ExitReason,
+    /// Data messages received
+    pub messages: u64,
+    /// Payload bytes as received, before decompression
+    pub bytes_in: u64,
+    /// Payload bytes after decompression
+    pub bytes_out: u64,
+    /// Messages in the busiest second
+    pub peak_rate: u64,
+    pub reconnects: Vec<ReconnectEvent>,
+    /// Gaps in the BLOCKS stream (see [`crate::gaps`])
+    pub gaps: u64,
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    /// Wall-clock time from the start of the run
+    pub duration: Duration,
+}
+
+impl RunReport {
+    /// Messages per second over the whole run
+    pub fn average_rate(&self) -> f64 {
+        match self.duration.as_secs_f64() {
+            secs if secs > 0.0 => self.messages as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// Decompressed bytes per received byte; 1.0 before anything arrived
+    pub fn compression_ratio(&self) -> f64 {
+        if self.bytes_in == 0 {
+            return 1.0;
+        }
+        self.bytes_out as f64 / self.bytes_in as f64
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "stream_type": self.stream_type.as_str_name(),
+            "exit_reason": self.exit_reason.as_str(),
+            "messages": self.messages,
+            "average_rate": round(self.average_rate()),
+            "peak_rate": self.peak_rate,
+            "bytes_in": self.bytes_in,
+            "bytes_out": self.bytes_out,
+            "compression_ratio": round(self.compression_ratio()),
+            "reconnect_count": self.reconnects.len(),
+            "reconnects": self.reconnects.iter().map(ReconnectEvent::to_json).collect::<Vec<_>>(),
+            "gaps": self.gaps,
+            "first_block": self.first_block,
+            "last_block": self.last_block,
+            "duration_secs": round(self.duration.as_secs_f64()),
+        })
+    }
+
+    /// Write the report to `path` atomically.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut body = serde_json::to_string_pretty(&self.to_json())?;
+        body.push('\n');
+        write_atomic(path, body.as_bytes())
+            .map_err(|e| format!("failed to write report {}: {}", path.display(), e).into())
+    }
+}
+
+/// Three decimal places are plenty for rates and ratios
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
@@ -0,0 +1,1252 @@
+//! Reading blocks from the node-data S3 bucket.
+//!
+//! Blocks are archived under `replica_cmds/{CHECKPOINT}/{DATE}/{START}-{END}`
+//! as JSON Lines files of 3-7 GB, one block per line with no block number in
+//! it (the line index counts up from `START`), optionally gzip or zstd
+//! compressed. The public buckets are requester-pays; mirrors may not be,
+//! and may reject the header, so each [`Bucket`] says whether to send it.
+//! [`backfill_range`] finds the
+//! files covering a range of blocks in the latest checkpoint and streams
+//! them line by line; `s3_blocks_backfill` is the command-line front end.
+//!
+//! A node's own data directory has the same `replica_cmds/` layout on local
+//! disk. [`backfill_local_range`] reads it the same way, block numbering and
+//! range discovery included, without S3 or its transfer costs.
+
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::types::RequestPayer;
+use aws_sdk_s3::Client;
+use chrono::NaiveDateTime;
+use futures::future::BoxFuture;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader, ReadBuf};
+use tracing::{info, warn};
+
+use crate::common::is_zstd;
+use crate::reconnect::reconnect_delay;
+use crate::Result;
+
+pub const MAINNET_BUCKET: &str = "hl-mainnet-node-data";
+pub const TESTNET_BUCKET: &str = "hl-testnet-node-data";
+pub const BLOCKS_PREFIX: &str = "replica_cmds";
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+/// Decompressed bytes read from the body per call
+const READ_CHUNK: usize = 256 * 1024;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Attempts per S3 list request, including the first
+const S3_MAX_ATTEMPTS: usize = 4;
+const S3_MAX_DELAY_SECS: u64 = 10;
+/// Attempts to resume an interrupted object download, in a row without any
+/// data arriving in between
+const S3_MAX_RESUMES: usize = 5;
+
+/// A bucket holding `replica_cmds/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    pub name: String,
+    /// Send `x-amz-request-payer: requester`, which the public buckets
+    /// require and some mirrors reject
+    pub requester_pays: bool,
+}
+
+impl Bucket {
+    /// A requester-pays bucket, like the public ones
+    pub fn requester_pays(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            requester_pays: true,
+        }
+    }
+
+    /// Value for each request's `request_payer`
+    fn payer(&self) -> Option<RequestPayer> {
+        self.requester_pays.then_some(RequestPayer::Requester)
+    }
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s3://{}/", self.name)
+    }
+}
+
+/// Compression of a block range file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Compression implied by a file name suffix, and the name without it
+    fn from_file_name(name: &str) -> (Self, &str) {
+        if let Some(stem) = name.strip_suffix(".gz") {
+            (Compression::Gzip, stem)
+        } else if let Some(stem) = name.strip_suffix(".zst") {
+            (Compression::Zstd, stem)
+        } else {
+            (Compression::None, name)
+        }
+    }
+
+    /// Compression detected from the first bytes of a file
+    fn sniff(head: &[u8]) -> Self {
+        if head.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if is_zstd(head) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Represents a block range file in S3, or in a local `replica_cmds`
+/// directory
+#[derive(Debug, Clone)]
+pub struct BlockRange {
+    pub checkpoint: String,
+    pub date: String,
+    pub start_block: u64,
+    pub end_block: u64,
+    /// Key in the bucket; for a local file, its path under the data
+    /// directory
+    pub s3_key: String,
+    /// From the key's suffix; unsuffixed files are also sniffed when read
+    pub compression: Compression,
+}
+
+impl BlockRange {
+    /// Parse S3 key: replica_cmds/1704067200/20240101/830000000-830010000,
+    /// optionally with a `.gz` or `.zst` suffix
+    pub fn from_s3_key(key: &str) -> Option<Self> {
+        let parts: Vec<&str> = key.split('/').collect();
+        if parts.len() != 4 || parts[0] != BLOCKS_PREFIX {
+            return None;
+        }
+
+        let (compression, file) = Compression::from_file_name(parts[3]);
+        let range_parts: Vec<&str> = file.split('-').collect();
+        if range_parts.len() != 2 {
+            return None;
+        }
+
+        let start_block = range_parts[0].parse().ok()?;
+        let end_block = range_parts[1].parse().ok()?;
+
+        Some(Self {
+            checkpoint: parts[1].to_string(),
+            date: parts[2].to_string(),
+            start_block,
+            end_block,
+            s3_key: key.to_string(),
+            compression,
+        })
+    }
+}
+
+/// A parsed block from S3
+#[derive(Debug)]
+pub struct Block {
+    pub block_number: u64,
+    pub data: serde_json::Value,
+}
+
+impl Block {
+    /// Block time in unix milliseconds, from `abci_block.time` (a naive UTC
+    /// timestamp), or 0 if it's missing.
+    pub fn timestamp_ms(&self) -> u64 {
+        block_timestamp_ms(&self.data)
+    }
+}
+
+/// Block time of an S3 block line in unix milliseconds, or 0 if it has none.
+pub fn block_timestamp_ms(data: &serde_json::Value) -> u64 {
+    data.pointer("/abci_block/time")
+        .and_then(serde_json::Value::as_str)
+        .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f").ok())
+        .map(|time| time.and_utc().timestamp_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Whether a failed S3 request is worth retrying: timeouts, connection
+/// failures, throttling and 5xx responses.
+fn is_transient<E: ProvideErrorMetadata>(err: &SdkError<E>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            true
+        }
+        SdkError::ServiceError(service) => {
+            service.raw().status().is_server_error()
+                || matches!(
+                    service.err().code(),
+                    Some("SlowDown" | "Throttling" | "RequestTimeout")
+                )
+        }
+        _ => false,
+    }
+}
+
+/// List S3 objects under a prefix, following continuation tokens so
+/// directories with more than 1000 entries are listed in full. Each page is
+/// retried with backoff on transient errors.
+pub async fn list_s3(
+    client: &Client,
+    bucket: &Bucket,
+    prefix: &str,
+) -> Result<Vec<String>, aws_sdk_s3::Error> {
+    let mut items = Vec::new();
+    let mut continuation_token: Option<String> = None;
+
+    loop {
+        let mut attempt = 0;
+        let result = loop {
+            attempt += 1;
+            let sent = client
+                .list_objects_v2()
+                .bucket(&bucket.name)
+                .prefix(prefix)
+                .delimiter("/")
+                .set_continuation_token(continuation_token.clone())
+                .set_request_payer(bucket.payer())
+                .send()
+                .await;
+            match sent {
+                Ok(result) => break result,
+                Err(err) if attempt < S3_MAX_ATTEMPTS && is_transient(&err) => {
+                    let delay = reconnect_delay(attempt, 1, S3_MAX_DELAY_SECS);
+                    warn!(
+                        "Listing {} failed: {} (retry {}/{} in {:.1}s)",
+                        prefix,
+                        err,
+                        attempt,
+                        S3_MAX_ATTEMPTS - 1,
+                        delay.as_secs_f64()
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+
+        // Directories
+        for p in result.common_prefixes() {
+            if let Some(prefix_str) = p.prefix() {
+                let name = prefix_str.trim_start_matches(prefix).trim_end_matches('/');
+                if !name.is_empty() {
+                    items.push(name.to_string());
+                }
+            }
+        }
+
+        // Files
+        for obj in result.contents() {
+            if let Some(key) = obj.key() {
+                let name = key.trim_start_matches(prefix);
+                if !name.is_empty() {
+                    items.push(name.to_string());
+                }
+            }
+        }
+
+        match result.next_continuation_token() {
+            Some(token) if result.is_truncated() == Some(true) => {
+                continuation_token = Some(token.to_string());
+            }
+            _ => break,
+        }
+    }
+
+    items.sort();
+    Ok(items)
+}
+
+/// List the entries of a directory of a local data directory, by name and
+/// sorted, as [`list_s3`] lists a prefix.
+pub async fn list_local(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    let mut items = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(name) = entry.file_name().to_str() {
+            items.push(name.to_string());
+        }
+    }
+    items.sort();
+    Ok(items)
+}
+
+/// Find the S3 files in the latest checkpoint that overlap
+/// `start_block..=end_block`, in block order
+pub async fn find_block_ranges(
+    client: &Client,
+    bucket: &Bucket,
+    start_block: u64,
+    end_block: u64,
+) -> Result<Vec<BlockRange>, aws_sdk_s3::Error> {
+    let list = |prefix: String| async move { list_s3(client, bucket, &prefix).await };
+    find_ranges(list, start_block, end_block).await
+}
+
+/// Find the files in the latest checkpoint under `root` (a data directory
+/// holding `replica_cmds/`) that overlap `start_block..=end_block`, in block
+/// order
+pub async fn find_local_block_ranges(
+    root: &Path,
+    start_block: u64,
+    end_block: u64,
+) -> std::io::Result<Vec<BlockRange>> {
+    let list = |prefix: String| async move { list_local(&root.join(prefix)).await };
+    find_ranges(list, start_block, end_block).await
+}
+
+/// Walk the latest checkpoint's dates with `list`, which lists the names
+/// under a key prefix, collecting the ranges that overlap
+/// `start_block..=end_block`.
+async fn find_ranges<F, Fut, E>(
+    list: F,
+    start_block: u64,
+    end_block: u64,
+) -> Result<Vec<BlockRange>, E>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Vec<String>, E>>,
+{
+    let checkpoints = list(format!("{}/", BLOCKS_PREFIX)).await?;
+    let Some(checkpoint) = checkpoints.last() else {
+        return Ok(Vec::new());
+    };
+
+    let dates = list(format!("{}/{}/", BLOCKS_PREFIX, checkpoint)).await?;
+
+    let mut ranges = Vec::new();
+    for date in dates {
+        let files = list(format!("{}/{}/{}/", BLOCKS_PREFIX, checkpoint, date)).await?;
+
+        for file in files {
+            let key = format!("{}/{}/{}/{}", BLOCKS_PREFIX, checkpoint, date, file);
+            if let Some(br) = BlockRange::from_s3_key(&key) {
+                if br.start_block <= end_block && start_block <= br.end_block {
+                    ranges.push(br);
+                }
+            }
+        }
+    }
+
+    ranges.sort_by_key(|br| br.start_block);
+    Ok(ranges)
+}
+
+/// Find which S3 file contains a specific block number
+pub async fn find_block_file(
+    client: &Client,
+    bucket: &Bucket,
+    target_block: u64,
+) -> Option<BlockRange> {
+    find_block_ranges(client, bucket, target_block, target_block)
+        .await
+        .ok()?
+        .into_iter()
+        .next()
+}
+
+/// Throttled download progress and ETA, reported on stderr so it never
+/// mixes with block output on stdout. One `Progress` can span several files
+/// to report overall progress for a multi-file backfill.
+///
+/// Progress and ETA count bytes on the wire, which is what the object sizes
+/// (and the transfer cost) are measured in; for compressed files the
+/// decompressed volume is shown alongside.
+pub struct Progress {
+    total_bytes: u64,
+    read_bytes: u64,
+    decoded_bytes: u64,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl Progress {
+    pub fn new(total_bytes: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            total_bytes,
+            read_bytes: 0,
+            decoded_bytes: 0,
+            started: now,
+            last_report: now,
+        }
+    }
+
+    fn add(&mut self, wire_bytes: u64, decoded_bytes: u64) {
+        self.read_bytes += wire_bytes;
+        self.decoded_bytes += decoded_bytes;
+        if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+            self.report();
+        }
+    }
+
+    /// ", 1.2 GB decompressed" once the data was compressed on the wire
+    fn decoded_note(&self) -> String {
+        if self.decoded_bytes > self.read_bytes {
+            format!(
+                ", {:.1} MB decompressed",
+                self.decoded_bytes as f64 / 1_000_000.0
+            )
+        } else {
+            String::new()
+        }
+    }
+
+    fn report(&mut self) {
+        self.last_report = Instant::now();
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let mb_per_sec = self.read_bytes as f64 / 1_000_000.0 / elapsed.max(f64::EPSILON);
+
+        if self.total_bytes == 0 {
+            info!(
+                "Downloaded {:.1} MB ({:.1} MB/s{})",
+                self.read_bytes as f64 / 1_000_000.0,
+                mb_per_sec,
+                self.decoded_note()
+            );
+            return;
+        }
+
+        let pct = self.read_bytes as f64 / self.total_bytes as f64 * 100.0;
+        let remaining = self.total_bytes.saturating_sub(self.read_bytes) as f64;
+        let eta = remaining / 1_000_000.0 / mb_per_sec.max(f64::EPSILON);
+        info!(
+            "Downloaded {:.1}/{:.1} MB ({:.1}%) at {:.1} MB/s, ETA {:.0}s{}",
+            self.read_bytes as f64 / 1_000_000.0,
+            self.total_bytes as f64 / 1_000_000.0,
+            pct,
+            mb_per_sec,
+            eta,
+            self.decoded_note()
+        );
+    }
+
+    pub fn finish(&mut self) {
+        self.report();
+    }
+}
+
+/// A cap on the download rate, shared by every file of a backfill.
+///
+/// A token bucket holding a tenth of a second's worth of bytes (at least
+/// 64 KiB): reads take from it, and once it's overdrawn the next read waits
+/// until it has refilled. The wait is on the wire side, below any decoder,
+/// so the network reads themselves are slowed.
+#[derive(Clone)]
+pub struct RateLimit {
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+struct TokenBucket {
+    bytes_per_sec: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled: Instant,
+    started: Instant,
+    total_bytes: u64,
+}
+
+impl RateLimit {
+    /// A limit of `mbps` megabits per second, or `None` for 0 (unlimited).
+    pub fn mbps(mbps: f64) -> Option<Self> {
+        if mbps <= 0.0 {
+            return None;
+        }
+        let bytes_per_sec = mbps * 1_000_000.0 / 8.0;
+        let capacity = (bytes_per_sec / 10.0).max(64.0 * 1024.0);
+        let now = Instant::now();
+        Some(Self {
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                bytes_per_sec,
+                capacity,
+                tokens: capacity,
+                refilled: now,
+                started: now,
+                total_bytes: 0,
+            })),
+        })
+    }
+
+    /// Take `bytes` that were just read, returning how long to wait before
+    /// reading more.
+    fn take(&self, bytes: usize) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * bucket.bytes_per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(bucket.capacity) - bytes as f64;
+        bucket.refilled = now;
+        bucket.total_bytes += bytes as u64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / bucket.bytes_per_sec)
+        }
+    }
+
+    /// Log the average rate since the limit was created.
+    pub fn report(&self) {
+        let bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = bucket.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        let bytes_per_sec = bucket.total_bytes as f64 / elapsed;
+        info!(
+            "Downloaded {:.1} MB in {:.0}s: {:.1} Mbit/s on average (limit {:.1})",
+            bucket.total_bytes as f64 / 1_000_000.0,
+            elapsed,
+            bytes_per_sec * 8.0 / 1_000_000.0,
+            bucket.bytes_per_sec * 8.0 / 1_000_000.0
+        );
+    }
+}
+
+/// Holds reads back to a [`RateLimit`].
+struct Throttled<R> {
+    inner: R,
+    limit: Option<RateLimit>,
+    wait: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Throttled<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if let Some(wait) = &mut self.wait {
+            if wait.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.wait = None;
+        }
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(limit)) = (&result, &self.limit) {
+            let wait = limit.take(buf.filled().len() - before);
+            if !wait.is_zero() {
+                self.wait = Some(Box::pin(tokio::time::sleep(wait)));
+            }
+        }
+        result
+    }
+}
+
+/// Counts the bytes read through it, so progress can be measured on the
+/// compressed side of a decoder.
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.count
+                .fetch_add((buf.filled().len() - before) as u64, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+type BodyReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Requests the object from a byte offset on
+type Reopen = Box<dyn FnMut(u64) -> BoxFuture<'static, std::io::Result<BodyReader>> + Send>;
+
+enum BodyState {
+    Reading(BodyReader),
+    Reopening(BoxFuture<'static, std::io::Result<BodyReader>>),
+}
+
+/// An object body that survives the connection dropping partway through: on
+/// a read error, or the body ending short of its length, the rest is
+/// requested from the offset reached (`Range: bytes=<offset>-`) and reading
+/// carries on. The decoder and line splitting above it see one unbroken
+/// byte stream. Gives up after [`S3_MAX_RESUMES`] attempts that produce no
+/// data.
+struct ResumableBody {
+    state: BodyState,
+    reopen: Reopen,
+    /// Bytes of the object read so far
+    offset: u64,
+    /// Length of the object, when known
+    len: Option<u64>,
+    /// Resume attempts since data last arrived
+    attempts: usize,
+}
+
+impl ResumableBody {
+    fn new(body: BodyReader, len: Option<u64>, reopen: Reopen) -> Self {
+        Self {
+            state: BodyState::Reading(body),
+            reopen,
+            offset: 0,
+            len,
+            attempts: 0,
+        }
+    }
+
+    /// Start the next resume attempt after a backoff, or return `err` once
+    /// they're used up.
+    fn resume(&mut self, err: std::io::Error) -> std::io::Result<()> {
+        if self.attempts >= S3_MAX_RESUMES {
+            return Err(err);
+        }
+        self.attempts += 1;
+        let delay = reconnect_delay(self.attempts, 1, S3_MAX_DELAY_SECS);
+        warn!(
+            "S3 download interrupted after {} bytes: {} (resume {}/{} in {:.1}s)",
+            self.offset,
+            err,
+            self.attempts,
+            S3_MAX_RESUMES,
+            delay.as_secs_f64()
+        );
+        let open = (self.reopen)(self.offset);
+        self.state = BodyState::Reopening(Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            open.await
+        }));
+        Ok(())
+    }
+}
+
+impl AsyncRead for ResumableBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            let failed = match &mut this.state {
+                BodyState::Reading(body) => {
+                    let before = buf.filled().len();
+                    match body.as_mut().poll_read(cx, buf) {
+                        Poll::Ready(Ok(())) => {
+                            let read = (buf.filled().len() - before) as u64;
+                            this.offset += read;
+                            if read > 0 {
+                                this.attempts = 0;
+                                return Poll::Ready(Ok(()));
+                            }
+                            match this.len {
+                                Some(len) if this.offset < len => std::io::Error::new(
+                                    std::io::ErrorKind::UnexpectedEof,
+                                    format!("body ended {} bytes short", len - this.offset),
+                                ),
+                                _ => return Poll::Ready(Ok(())),
+                            }
+                        }
+                        Poll::Ready(Err(err)) => err,
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                BodyState::Reopening(open) => match open.as_mut().poll(cx) {
+                    Poll::Ready(Ok(body)) => {
+                        this.state = BodyState::Reading(body);
+                        continue;
+                    }
+                    Poll::Ready(Err(err)) => err,
+                    Poll::Pending => return Poll::Pending,
+                },
+            };
+            if let Err(err) = this.resume(failed) {
+                return Poll::Ready(Err(err));
+            }
+        }
+    }
+}
+
+/// Incremental reader over one block-range file, yielding blocks as their
+/// lines arrive instead of buffering the whole 3-7 GB object. Compressed
+/// files are decompressed on the fly, and numbered by their decompressed
+/// lines.
+///
+/// Block numbers come from the physical line index (`start_block + index`),
+/// counting blank and unparseable lines too, so one bad line never shifts the
+/// numbering of the blocks after it. Lines that aren't valid JSON are
+/// reported with the block number they occupy and skipped. A read that
+/// fails for good is returned as an error, never taken for the end of the
+/// file.
+pub struct BlockStream<'a> {
+    body: Pin<Box<dyn AsyncRead + Send>>,
+    /// Wire bytes read so far, and how many were already reported
+    wire_bytes: Arc<AtomicU64>,
+    reported_bytes: u64,
+    buf: Vec<u8>,
+    /// Start of the unconsumed part of `buf`
+    pos: usize,
+    /// Block number of the next line
+    next_block: u64,
+    eof: bool,
+    progress: Option<&'a mut Progress>,
+}
+
+impl BlockStream<'_> {
+    /// The next block, `None` at the end of the file.
+    pub async fn next(&mut self) -> std::io::Result<Option<Block>> {
+        loop {
+            if let Some(len) = self.buf[self.pos..].iter().position(|&b| b == b'\n') {
+                let line = self.pos..self.pos + len;
+                self.pos += len + 1;
+                if let Some(block) = self.parse_line(line) {
+                    return Ok(Some(block));
+                }
+                continue;
+            }
+
+            if self.eof {
+                if self.pos >= self.buf.len() {
+                    return Ok(None);
+                }
+                // Final line without a trailing newline
+                let line = self.pos..self.buf.len();
+                self.pos = self.buf.len();
+                if let Some(block) = self.parse_line(line) {
+                    return Ok(Some(block));
+                }
+                continue;
+            }
+
+            // Drop consumed lines before reading more
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+
+            let read = (&mut self.body)
+                .take(READ_CHUNK as u64)
+                .read_to_end(&mut self.buf)
+                .await;
+            match read? {
+                0 => self.eof = true,
+                decoded => {
+                    let wire = self.wire_bytes.load(Ordering::Relaxed);
+                    if let Some(progress) = self.progress.as_deref_mut() {
+                        progress.add(wire - self.reported_bytes, decoded as u64);
+                    }
+                    self.reported_bytes = wire;
+                }
+            }
+        }
+    }
+
+    fn parse_line(&mut self, line: std::ops::Range<usize>) -> Option<Block> {
+        let block_number = self.next_block;
+        self.next_block += 1;
+
+        let line = &self.buf[line];
+        if line.trim_ascii().is_empty() {
+            return None;
+        }
+        match serde_json::from_slice(line) {
+            Ok(data) => Some(Block { block_number, data }),
+            Err(err) => {
+                warn!("Skipping unparseable block {}: {}", block_number, err);
+                None
+            }
+        }
+    }
+}
+
+/// Total size in bytes of the files of `ranges`, from a HEAD request each.
+/// Reading a range ends at its last block, so this is an upper bound on
+/// what a backfill downloads.
+pub async fn files_size(
+    client: &Client,
+    bucket: &Bucket,
+    ranges: &[BlockRange],
+) -> Result<u64, aws_sdk_s3::Error> {
+    let mut total_bytes = 0;
+    for br in ranges {
+        let head = client
+            .head_object()
+            .bucket(&bucket.name)
+            .key(&br.s3_key)
+            .set_request_payer(bucket.payer())
+            .send()
+            .await?;
+        total_bytes += head.content_length().unwrap_or(0).max(0) as u64;
+    }
+    Ok(total_bytes)
+}
+
+/// Stream blocks from S3. Files are 3-7 GB - streams line-by-line,
+/// decompressing gzip or zstd files as they arrive. A download cut off
+/// partway through resumes from where it stopped rather than starting over
+/// (see [`ResumableBody`]). With a `limit`, the download is held to its
+/// rate.
+pub async fn stream_blocks<'a>(
+    client: &Client,
+    bucket: &Bucket,
+    block_range: &BlockRange,
+    progress: Option<&'a mut Progress>,
+    limit: Option<RateLimit>,
+) -> Result<BlockStream<'a>, aws_sdk_s3::Error> {
+    let output = client
+        .get_object()
+        .bucket(&bucket.name)
+        .key(&block_range.s3_key)
+        .set_request_payer(bucket.payer())
+        .send()
+        .await?;
+
+    // Resumed requests must get the same object, not one rewritten since
+    let reopen: Reopen = {
+        let client = client.clone();
+        let bucket = bucket.clone();
+        let key = block_range.s3_key.clone();
+        let e_tag = output.e_tag().map(str::to_string);
+        Box::new(move |offset| {
+            let request = client
+                .get_object()
+                .bucket(&bucket.name)
+                .key(&key)
+                .range(format!("bytes={}-", offset))
+                .set_if_match(e_tag.clone())
+                .set_request_payer(bucket.payer());
+            Box::pin(async move {
+                let output = request.send().await.map_err(std::io::Error::other)?;
+                Ok(Box::pin(output.body.into_async_read()) as BodyReader)
+            })
+        })
+    };
+    let len = output.content_length().map(|len| len.max(0) as u64);
+    let body = ResumableBody::new(Box::pin(output.body.into_async_read()), len, reopen);
+
+    let wire_bytes = Arc::new(AtomicU64::new(0));
+    let reader = CountingReader {
+        inner: Throttled {
+            inner: body,
+            limit,
+            wait: None,
+        },
+        count: wire_bytes.clone(),
+    };
+    Ok(decode_blocks(reader, wire_bytes, block_range, progress).await)
+}
+
+/// Stream blocks from a file of a local data directory `root`, as
+/// [`stream_blocks`] does from S3.
+pub async fn stream_local_blocks<'a>(
+    root: &Path,
+    block_range: &BlockRange,
+    progress: Option<&'a mut Progress>,
+) -> std::io::Result<BlockStream<'a>> {
+    let file = tokio::fs::File::open(root.join(&block_range.s3_key)).await?;
+    let wire_bytes = Arc::new(AtomicU64::new(0));
+    let reader = CountingReader {
+        inner: file,
+        count: wire_bytes.clone(),
+    };
+    Ok(decode_blocks(reader, wire_bytes, block_range, progress).await)
+}
+
+/// Number the lines of a block range file read through `reader`,
+/// decompressing it first if need be. `wire_bytes` is `reader`'s count.
+async fn decode_blocks<'a, R: AsyncRead + Send + Unpin + 'static>(
+    reader: CountingReader<R>,
+    wire_bytes: Arc<AtomicU64>,
+    block_range: &BlockRange,
+    progress: Option<&'a mut Progress>,
+) -> BlockStream<'a> {
+    let mut reader = BufReader::new(reader);
+
+    // Unsuffixed files may still be compressed; a read error here will
+    // resurface on the first read of the body
+    let compression = match block_range.compression {
+        Compression::None => match reader.fill_buf().await {
+            Ok(head) => Compression::sniff(head),
+            Err(_) => Compression::None,
+        },
+        suffixed => suffixed,
+    };
+    let body: Pin<Box<dyn AsyncRead + Send>> = match compression {
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+        Compression::Zstd => {
+            let mut decoder = ZstdDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+        Compression::None => Box::pin(reader),
+    };
+    if compression != Compression::None {
+        info!("Decompressing {} ({:?})", block_range.s3_key, compression);
+    }
+
+    BlockStream {
+        body,
+        wire_bytes,
+        reported_bytes: 0,
+        buf: Vec::new(),
+        pos: 0,
+        next_block: block_range.start_block,
+        eof: false,
+        progress,
+    }
+}
+
+/// Fetch blocks `start_block..=end_block` across however many files they
+/// span, calling `on_block` for each. Returns the number of blocks fetched.
+/// With `show_progress`, overall progress across all files is reported on
+/// stderr; with a `limit`, the downloads are held to its rate. A download
+/// that fails partway through, after its resumes are used up, is an error.
+pub async fn backfill_range(
+    client: &Client,
+    bucket: &Bucket,
+    start_block: u64,
+    end_block: u64,
+    show_progress: bool,
+    limit: Option<RateLimit>,
+    mut on_block: impl FnMut(Block),
+) -> Result<u64> {
+    let ranges = find_block_ranges(client, bucket, start_block, end_block).await?;
+
+    let mut progress = if show_progress {
+        Some(Progress::new(files_size(client, bucket, &ranges).await?))
+    } else {
+        None
+    };
+
+    let mut count = 0;
+    for br in &ranges {
+        let mut blocks =
+            stream_blocks(client, bucket, br, progress.as_mut(), limit.clone()).await?;
+        count += take_range(&mut blocks, start_block, end_block, &mut on_block).await?;
+    }
+
+    if let Some(progress) = progress.as_mut() {
+        progress.finish();
+    }
+
+    Ok(count)
+}
+
+/// Read blocks `start_block..=end_block` from the local data directory
+/// `root`, as [`backfill_range`] does from S3. Returns the number of blocks
+/// read.
+pub async fn backfill_local_range(
+    root: &Path,
+    start_block: u64,
+    end_block: u64,
+    show_progress: bool,
+    mut on_block: impl FnMut(Block),
+) -> std::io::Result<u64> {
+    let ranges = find_local_block_ranges(root, start_block, end_block).await?;
+
+    let mut progress = if show_progress {
+        let mut total_bytes = 0;
+        for br in &ranges {
+            total_bytes += tokio::fs::metadata(root.join(&br.s3_key)).await?.len();
+        }
+        Some(Progress::new(total_bytes))
+    } else {
+        None
+    };
+
+    let mut count = 0;
+    for br in &ranges {
+        let mut blocks = stream_local_blocks(root, br, progress.as_mut()).await?;
+        count += take_range(&mut blocks, start_block, end_block, &mut on_block).await?;
+    }
+
+    if let Some(progress) = progress.as_mut() {
+        progress.finish();
+    }
+
+    Ok(count)
+}
+
+/// Hand the blocks of `blocks` within `start_block..=end_block` to
+/// `on_block`, returning how many there were.
+async fn take_range(
+    blocks: &mut BlockStream<'_>,
+    start_block: u64,
+    end_block: u64,
+    on_block: &mut impl FnMut(Block),
+) -> std::io::Result<u64> {
+    let mut count = 0;
+    while let Some(block) = blocks.next().await? {
+        if block.block_number > end_block {
+            break;
+        }
+        if block.block_number >= start_block {
+            on_block(block);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use serde_json::{json, Value};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// An S3 stand-in on a local port. Each request gets its own
+    /// connection, answered with `respond(index, head)` as raw HTTP and
+    /// then closed. Returns the endpoint and the request heads received,
+    /// lowercased.
+    async fn fake_s3(
+        respond: impl Fn(usize, &str) -> Vec<u8> + Send + Sync + 'static,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut head = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    head.extend_from_slice(&buf[..n]);
+                }
+                let head = String::from_utf8_lossy(&head).to_lowercase();
+                let index = {
+                    let mut seen = seen.lock().unwrap();
+                    seen.push(head.clone());
+                    seen.len() - 1
+                };
+                let _ = socket.write_all(&respond(index, &head)).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (endpoint, requests)
+    }
+
+    fn client(endpoint: &str) -> Client {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .endpoint_url(endpoint)
+            .force_path_style(true)
+            .build();
+        Client::from_conf(config)
+    }
+
+    fn response(status: &str, headers: &[(&str, String)], body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn block_range(key: &str) -> BlockRange {
+        BlockRange::from_s3_key(key).unwrap()
+    }
+
+    async fn collect(blocks: &mut BlockStream<'_>) -> std::io::Result<Vec<(u64, Value)>> {
+        let mut all = Vec::new();
+        while let Some(block) = blocks.next().await? {
+            all.push((block.block_number, block.data));
+        }
+        Ok(all)
+    }
+
+    /// Blocks of an in-memory block range file.
+    async fn local_blocks(file: &'static [u8], key: &str) -> BlockStream<'static> {
+        let wire_bytes = Arc::new(AtomicU64::new(0));
+        let reader = CountingReader {
+            inner: file,
+            count: wire_bytes.clone(),
+        };
+        decode_blocks(reader, wire_bytes, &block_range(key), None).await
+    }
+
+    /// Fails every read.
+    struct Broken;
+
+    impl AsyncRead for Broken {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Err(std::io::Error::new(
+                ErrorKind::ConnectionReset,
+                "connection reset",
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn download_cut_off_resumes_from_offset_of_same_object() {
+        const FILE: &[u8] = b"{\"i\":0}\n{\"i\":1}\n{\"i\":2}\n";
+        const CUT: usize = 11;
+        let (endpoint, requests) = fake_s3(|index, _| match index {
+            // Promises the whole file, sends part of it and hangs up
+            0 => response(
+                "200 OK",
+                &[
+                    ("Content-Length", FILE.len().to_string()),
+                    ("ETag", "\"v1\"".to_string()),
+                ],
+                &FILE[..CUT],
+            ),
+            _ => response(
+                "206 Partial Content",
+                &[
+                    ("Content-Length", (FILE.len() - CUT).to_string()),
+                    (
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", CUT, FILE.len() - 1, FILE.len()),
+                    ),
+                    ("ETag", "\"v1\"".to_string()),
+                ],
+                &FILE[CUT..],
+            ),
+        })
+        .await;
+
+        let bucket = Bucket::requester_pays("bucket");
+        let range = block_range("replica_cmds/1/20240101/100-102");
+        let mut blocks = stream_blocks(&client(&endpoint), &bucket, &range, None, None)
+            .await
+            .unwrap();
+        let blocks = collect(&mut blocks).await.unwrap();
+
+        let expected: Vec<_> = (0..3).map(|i| (100 + i, json!({ "i": i }))).collect();
+        assert_eq!(blocks, expected);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains(&format!("range: bytes={}-", CUT)));
+        assert!(requests[1].contains("if-match: \"v1\""));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn download_failing_past_resumes_is_an_error() {
+        let reopened = Arc::new(Mutex::new(Vec::new()));
+        let offsets = reopened.clone();
+        let reopen: Reopen = Box::new(move |offset| {
+            offsets.lock().unwrap().push(offset);
+            Box::pin(async { Ok(Box::pin(Broken) as BodyReader) })
+        });
+        let first = b"{\"i\":0}\n{\"i\":1}";
+        let body = ResumableBody::new(Box::pin((&first[..]).chain(Broken)), None, reopen);
+        let wire_bytes = Arc::new(AtomicU64::new(0));
+        let reader = CountingReader {
+            inner: body,
+            count: wire_bytes.clone(),
+        };
+        let range = block_range("replica_cmds/1/20240101/100-102");
+        let mut blocks = decode_blocks(reader, wire_bytes, &range, None).await;
+
+        let err = blocks.next().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+        assert_eq!(
+            *reopened.lock().unwrap(),
+            vec![first.len() as u64; S3_MAX_RESUMES]
+        );
+    }
+
+    #[tokio::test]
+    async fn blank_and_bad_lines_keep_later_block_numbers() {
+        let file = b"{\"i\":0}\n\n{\"i\":2}\nnot json\n   \n{\"i\":5}";
+        let mut blocks = local_blocks(file, "replica_cmds/1/20240101/100-105").await;
+        let blocks = collect(&mut blocks).await.unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                (100, json!({ "i": 0 })),
+                (102, json!({ "i": 2 })),
+                (105, json!({ "i": 5 })),
+            ]
+        );
+    }
+
+    fn list_page(prefixes: &[&str], keys: &[&str], next: Option<&str>) -> Vec<u8> {
+        let mut body =
+            String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListBucketResult>");
+        body.push_str("<Name>bucket</Name><Prefix>replica_cmds/</Prefix><Delimiter>/</Delimiter>");
+        for prefix in prefixes {
+            body.push_str(&format!(
+                "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                prefix
+            ));
+        }
+        for key in keys {
+            body.push_str(&format!(
+                "<Contents><Key>{}</Key><Size>1</Size></Contents>",
+                key
+            ));
+        }
+        match next {
+            Some(token) => body.push_str(&format!(
+                "<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>",
+                token
+            )),
+            None => body.push_str("<IsTruncated>false</IsTruncated>"),
+        }
+        body.push_str("</ListBucketResult>");
+        response(
+            "200 OK",
+            &[
+                ("Content-Type", "application/xml".to_string()),
+                ("Content-Length", body.len().to_string()),
+            ],
+            body.as_bytes(),
+        )
+    }
+
+    #[tokio::test]
+    async fn listing_follows_continuation_tokens() {
+        let (endpoint, requests) = fake_s3(|index, _| match index {
+            0 => list_page(
+                &["replica_cmds/20240102/", "replica_cmds/20240101/"],
+                &["replica_cmds/notes.txt"],
+                Some("page-2"),
+            ),
+            _ => list_page(&["replica_cmds/20240103/"], &[], None),
+        })
+        .await;
+
+        let bucket = Bucket::requester_pays("bucket");
+        let items = list_s3(&client(&endpoint), &bucket, "replica_cmds/")
+            .await
+            .unwrap();
+
+        assert_eq!(items, ["20240101", "20240102", "20240103", "notes.txt"]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].contains("continuation-token="));
+        assert!(requests[1].contains("continuation-token=page-2"));
+    }
+}
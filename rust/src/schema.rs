@@ -0,0 +1,261 @@
+//! Validate decoded records against a JSON Schema.
+//!
+//! Meant to catch upstream format changes (a field renamed, a number sent
+//! as a string) before whatever consumes the output breaks on them. Each
+//! record is checked on its own; violations are logged and counted, never
+//! fatal. The first occurrence of each distinct violation (the record path
+//! and the schema keyword it failed) is logged as a warning with its block,
+//! repeats only at debug level, and every violation is summarised on exit.
+//!
+//! `--schema auto` picks the schema shipped for the stream type (TRADES,
+//! ORDERS and BOOK_UPDATES, in `schemas/`). They only require the fields
+//! these examples rely on and allow any others, so new fields upstream
+//! don't count as violations.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::error::CliError;
+use crate::hyperliquid::StreamType;
+use crate::records::split_records;
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+/// Where a `--schema` comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaSource {
+    /// The built-in schema for the stream type
+    Auto,
+    /// A JSON Schema file
+    File(PathBuf),
+}
+
+impl FromStr for SchemaSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "" => Err("expected auto or a schema file path".to_string()),
+            "auto" => Ok(Self::Auto),
+            path => Ok(Self::File(PathBuf::from(path))),
+        }
+    }
+}
+
+/// The schema shipped for `stream_type`, if there is one.
+pub fn builtin_schema(stream_type: StreamType) -> Option<&'static str> {
+    match stream_type {
+        StreamType::Trades => Some(include_str!("../schemas/trades.json")),
+        StreamType::Orders => Some(include_str!("../schemas/orders.json")),
+        StreamType::BookUpdates => Some(include_str!("../schemas/book_updates.json")),
+        _ => None,
+    }
+}
+
+/// Checks each record of every message it's given, counting violations.
+pub struct SchemaValidator {
+    schema: JSONSchema,
+    /// Where the schema came from, for the log
+    name: String,
+    records: u64,
+    invalid: u64,
+    /// Occurrences of each distinct violation, by record path and schema
+    /// path, with the message first logged for it
+    violations: BTreeMap<(String, String), (u64, String)>,
+}
+
+impl SchemaValidator {
+    /// Load the schema `source` names for `stream_type`.
+    pub fn load(source: &SchemaSource, stream_type: StreamType) -> Result<Self> {
+        let (name, text) = match source {
+            SchemaSource::Auto => {
+                let text = builtin_schema(stream_type).ok_or_else(|| {
+                    CliError::BadArgs(format!(
+                        "no built-in schema for {} (there are for TRADES, ORDERS and BOOK_UPDATES); pass a schema file to --schema",
+                        stream_type.as_str_name()
+                    ))
+                })?;
+                (
+                    format!("built-in {} schema", stream_type.as_str_name()),
+                    text.to_string(),
+                )
+            }
+            SchemaSource::File(path) => {
+                let text = std::fs::read_to_string(path).map_err(|e| {
+                    CliError::BadArgs(format!("can't read schema {}: {}", path.display(), e))
+                })?;
+                (format!("schema {}", path.display()), text)
+            }
+        };
+        Self::compile(&name, &text)
+    }
+
+    /// Compile a schema given as JSON text.
+    pub fn compile(name: &str, text: &str) -> Result<Self> {
+        let schema: Value = serde_json::from_str(text)
+            .map_err(|e| CliError::BadArgs(format!("{} is not valid JSON: {}", name, e)))?;
+        let schema = JSONSchema::compile(&schema)
+            .map_err(|e| CliError::BadArgs(format!("{} is not a valid schema: {}", name, e)))?;
+        info!("Validating records against the {}", name);
+        Ok(Self {
+            schema,
+            name: name.to_string(),
+            records: 0,
+            invalid: 0,
+            violations: BTreeMap::new(),
+        })
+    }
+
+    /// Check one record, logging what's wrong with it. Returns the number of
+    /// violations found.
+    pub fn validate(&mut self, block_number: u64, record: &Value) -> usize {
+        self.records += 1;
+        let Err(errors) = self.schema.validate(record) else {
+            return 0;
+        };
+        let mut found = 0;
+        for error in errors {
+            found += 1;
+            let path = match error.instance_path.to_string() {
+                path if path.is_empty() => "/".to_string(),
+                path => path,
+            };
+            let message = error.to_string();
+            let key = (path, error.schema_path.to_string());
+            match self.violations.get_mut(&key) {
+                Some((count, _)) => {
+                    *count += 1;
+                    debug!(
+                        "Schema violation in block {} at {}: {}",
+                        block_number, key.0, message
+                    );
+                }
+                None => {
+                    warn!(
+                        "Schema violation in block {} at {}: {}",
+                        block_number, key.0, message
+                    );
+                    self.violations.insert(key, (1, message));
+                }
+            }
+        }
+        if found > 0 {
+            self.invalid += 1;
+        }
+        found
+    }
+
+    /// Records that failed validation so far
+    pub fn invalid(&self) -> u64 {
+        self.invalid
+    }
+
+    /// Log how many records failed and each distinct violation.
+    pub fn report(&self) {
+        if self.invalid == 0 {
+            info!("All {} record(s) matched the {}", self.records, self.name);
+            return;
+        }
+        warn!(
+            "{} of {} record(s) didn't match the {}:",
+            self.invalid, self.records, self.name
+        );
+        for ((path, _), (count, message)) in &self.violations {
+            warn!("  {:>8} x at {}: {}", count, path, message);
+        }
+    }
+}
+
+#[async_trait]
+impl RecordSink for SchemaValidator {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        for item in split_records(record.payload) {
+            self.validate(record.block_number, item);
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.report();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn trade() -> Value {
+        json!({
+            "coin": "BTC", "side": "B", "px": "97000.5", "sz": "0.1",
+            "time": 1_700_000_000_000u64, "hash": "0xabc", "tid": 7,
+        })
+    }
+
+    fn trades_validator() -> SchemaValidator {
+        SchemaValidator::load(&SchemaSource::Auto, StreamType::Trades).unwrap()
+    }
+
+    #[test]
+    fn a_matching_record_has_no_violations() {
+        let mut validator = trades_validator();
+
+        assert_eq!(validator.validate(100, &trade()), 0);
+        assert_eq!(validator.invalid(), 0);
+    }
+
+    #[test]
+    fn a_violating_record_is_reported_with_its_path() {
+        let mut validator = trades_validator();
+        let mut record = trade();
+        record["px"] = json!(97000.5);
+        record.as_object_mut().unwrap().remove("hash");
+
+        assert_eq!(validator.validate(100, &record), 2);
+        assert_eq!(validator.invalid(), 1);
+        let paths: Vec<&str> = validator
+            .violations
+            .keys()
+            .map(|(path, _)| path.as_str())
+            .collect();
+        assert_eq!(paths, ["/", "/px"]);
+
+        // The same violation again is counted, not listed twice
+        assert_eq!(validator.validate(101, &record), 2);
+        assert_eq!(validator.invalid(), 2);
+        assert_eq!(validator.violations.len(), 2);
+        assert!(validator.violations.values().all(|(count, _)| *count == 2));
+    }
+
+    #[tokio::test]
+    async fn every_record_of_a_message_is_checked() {
+        let mut validator = trades_validator();
+        let payload = json!([trade(), { "coin": "ETH" }]);
+
+        validator
+            .write(&Record {
+                block_number: 100,
+                timestamp: 0,
+                latency_ms: None,
+                payload: &payload,
+            })
+            .await
+            .unwrap();
+        assert_eq!(validator.records, 2);
+        assert_eq!(validator.invalid(), 1);
+    }
+
+    #[test]
+    fn auto_needs_a_builtin_schema() {
+        assert!(SchemaValidator::load(&SchemaSource::Auto, StreamType::Blocks).is_err());
+        assert_eq!("auto".parse(), Ok(SchemaSource::Auto));
+        assert!(SchemaValidator::compile("test schema", "{").is_err());
+    }
+}
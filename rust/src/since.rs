@@ -0,0 +1,202 @@
+//! Resolve a wall-clock start time to an approximate `start_block`.
+//!
+//! The API only accepts block numbers, so this probes the server: it reads
+//! a few live blocks to find the tip and estimate the block rate, computes
+//! how far back the requested time is, then reads the estimated block and
+//! corrects for drift a couple of times. The result is approximate (block
+//! times aren't uniform, and `StreamResponse.timestamp` is the server's
+//! ingress time), typically within a second or so of the requested time.
+//!
+//! The same probing finds the current tip ([`current_tip`]) and the earliest
+//! block the server still retains ([`earliest_block`]), neither of which
+//! the API reports either.
+
+use std::time::Duration;
+
+use chrono::DateTime;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Channel;
+use tracing::info;
+
+use crate::common::{authed_request, build_channel, streaming_client, validate_token};
+use crate::hyperliquid::{self, StreamSubscribe, StreamType, SubscribeRequest};
+use crate::source::Received;
+use crate::stream::StreamOptions;
+use crate::Result;
+
+/// Distinct live blocks read to estimate the block rate
+const RATE_SAMPLE_BLOCKS: usize = 20;
+/// Corrections applied after the first estimate
+const REFINE_ROUNDS: usize = 2;
+/// An estimate this close to the requested time is good enough
+const TOLERANCE_MS: u64 = 1_000;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(20);
+/// A block that sends nothing for this long counts as not retained
+const RETAINED_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Bisection steps when searching for the earliest retained block
+const EARLIEST_SEARCH_STEPS: usize = 24;
+/// Wait at most this long for the tip block
+const TIP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The newest block the server has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tip {
+    pub block: u64,
+    /// `timestamp` of the block's message (ms)
+    pub timestamp: u64,
+}
+
+/// Parse `--since` as RFC 3339 (`2024-05-01T12:00:00Z`) or unix milliseconds.
+pub fn parse_since(value: &str) -> Result<u64> {
+    if let Ok(ms) = value.parse::<u64>() {
+        return Ok(ms);
+    }
+    let time = DateTime::parse_from_rfc3339(value).map_err(|e| {
+        format!(
+            "invalid --since {:?} (expected RFC 3339 or unix ms): {}",
+            value, e
+        )
+    })?;
+    u64::try_from(time.timestamp_millis())
+        .map_err(|_| format!("--since {:?} is before the unix epoch", value).into())
+}
+
+/// Estimate the first block at or after `since_ms`. Returns the live tip if
+/// `since_ms` is in the future.
+pub async fn resolve_since(options: &StreamOptions, since_ms: u64) -> Result<u64> {
+    validate_token(&options.token, options.channel.auth)?;
+    let channel = build_channel(&options.endpoint, &options.channel).await?;
+
+    let tip = probe(&channel, options, 0, RATE_SAMPLE_BLOCKS, PROBE_TIMEOUT).await?;
+    let (first, last) = match (tip.first(), tip.last()) {
+        (Some(first), Some(last)) if last.0 > first.0 && last.1 > first.1 => (*first, *last),
+        _ => return Err("could not estimate the block rate from the live stream".into()),
+    };
+    let blocks_per_ms = (last.0 - first.0) as f64 / (last.1 - first.1) as f64;
+    info!(
+        "Tip is block {} ({:.1} blocks/s)",
+        last.0,
+        blocks_per_ms * 1000.0
+    );
+
+    if since_ms >= last.1 {
+        return Ok(last.0);
+    }
+
+    let mut estimate = back_compute(last, since_ms, blocks_per_ms);
+    for _ in 0..REFINE_ROUNDS {
+        let Some(&(block, timestamp)) = probe(&channel, options, estimate, 1, PROBE_TIMEOUT)
+            .await?
+            .first()
+        else {
+            break;
+        };
+        if timestamp.abs_diff(since_ms) <= TOLERANCE_MS {
+            break;
+        }
+        estimate = back_compute((block, timestamp), since_ms, blocks_per_ms);
+    }
+    Ok(estimate)
+}
+
+/// Read one live block to learn the current tip.
+pub async fn current_tip(options: &StreamOptions) -> Result<Tip> {
+    validate_token(&options.token, options.channel.auth)?;
+    let channel = build_channel(&options.endpoint, &options.channel).await?;
+    let Some(&(block, timestamp)) = probe(&channel, options, 0, 1, TIP_TIMEOUT).await?.first()
+    else {
+        return Err("the live stream ended before sending a block".into());
+    };
+    Ok(Tip { block, timestamp })
+}
+
+/// The earliest block the server still serves, given that `unavailable`
+/// isn't. Bisects between `unavailable` and the live tip, so this takes up
+/// to a couple of dozen short subscriptions; `None` if the tip can't be
+/// read. After [`EARLIEST_SEARCH_STEPS`] the result may be slightly later
+/// than the true earliest block, but is always retained.
+pub async fn earliest_block(options: &StreamOptions, unavailable: u64) -> Result<Option<u64>> {
+    let channel = build_channel(&options.endpoint, &options.channel).await?;
+    let Some(&(tip, _)) = probe(&channel, options, 0, 1, PROBE_TIMEOUT).await?.first() else {
+        return Ok(None);
+    };
+
+    let (mut lo, mut hi) = (unavailable, tip);
+    for _ in 0..EARLIEST_SEARCH_STEPS {
+        if hi <= lo + 1 {
+            break;
+        }
+        let mid = lo + (hi - lo) / 2;
+        // A rejected subscription and a silent one both mean not retained
+        match probe(&channel, options, mid, 1, RETAINED_PROBE_TIMEOUT).await {
+            Ok(blocks) if !blocks.is_empty() => {
+                // The server may start later than asked; that block is
+                // retained too
+                hi = blocks[0].0.clamp(mid, hi);
+                if hi > mid {
+                    lo = mid;
+                }
+            }
+            _ => lo = mid,
+        }
+    }
+    Ok(Some(hi))
+}
+
+/// Block expected at `since_ms`, stepping from a known `(block, timestamp)`.
+fn back_compute(from: (u64, u64), since_ms: u64, blocks_per_ms: f64) -> u64 {
+    let offset = (since_ms as f64 - from.1 as f64) * blocks_per_ms;
+    // 0 means "live tip" to the server, so never go below block 1
+    (from.0 as f64 + offset).round().max(1.0) as u64
+}
+
+/// `(block_number, timestamp)` of the first `count` distinct blocks of the
+/// BLOCKS stream from `start_block`, waiting at most `timeout`.
+async fn probe(
+    channel: &Channel,
+    options: &StreamOptions,
+    start_block: u64,
+    count: usize,
+    timeout: Duration,
+) -> Result<Vec<(u64, u64)>> {
+    let mut client = streaming_client(channel.clone(), &options.channel);
+    let (tx, rx) = mpsc::channel(1);
+    tx.send(SubscribeRequest {
+        request: Some(hyperliquid::subscribe_request::Request::Subscribe(
+            StreamSubscribe {
+                stream_type: StreamType::Blocks as i32,
+                start_block,
+                filters: Default::default(),
+                filter_name: String::new(),
+            },
+        )),
+    })
+    .await?;
+    let request = authed_request(
+        ReceiverStream::new(rx),
+        &options.token,
+        options.channel.auth,
+    )?;
+
+    let read = async {
+        let mut stream = client.stream_data(request).await?.into_inner();
+        let mut blocks: Vec<(u64, u64)> = Vec::with_capacity(count);
+        while blocks.len() < count {
+            let Some(update) = stream.message().await? else {
+                break;
+            };
+            if let Received::Data(data) = Received::from_update(update) {
+                if blocks.last().map(|b| b.0) != Some(data.block_number) {
+                    blocks.push((data.block_number, data.timestamp));
+                }
+            }
+        }
+        Ok::<_, crate::Error>(blocks)
+    };
+
+    match tokio::time::timeout(timeout, read).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out probing blocks from {}", start_block).into()),
+    }
+}
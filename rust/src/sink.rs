@@ -0,0 +1,403 @@
+//! Output targets for decoded data messages.
+//!
+//! The raw stream opens one [`RecordSink`] per output flag and hands every
+//! decoded message to each of them in turn, so any combination of outputs
+//! (say stdout, CSV and Kafka) runs side by side. File and network sinks
+//! implement the trait next to their own types; the stdout printers live
+//! here.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use serde_json::Value;
+use tracing::info;
+
+use crate::book::{DepthBook, Side};
+use crate::hyperliquid::StreamType;
+use crate::records::split_records;
+use crate::typed::{project, Event, OrderEvent, OrderLifecycle, Twap, WriterAction};
+use crate::Result;
+
+/// Price levels shown per side of a reconstructed book
+const BOOK_DEPTH: usize = 10;
+
+/// One decoded data message.
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    pub block_number: u64,
+    pub timestamp: u64,
+    /// How long after `timestamp` the message arrived, when measured
+    pub latency_ms: Option<u64>,
+    pub payload: &'a Value,
+}
+
+#[async_trait]
+pub trait RecordSink: Send {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()>;
+
+    /// Write out anything buffered. Called once when the stream ends or is
+    /// interrupted; a sink may not accept writes afterwards.
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Decides which messages a display prints: every `sample_rate`th one, and
+/// at most one per `throttle`. Every message is still counted, so the totals
+/// reported at the end are exact.
+#[derive(Debug, Default)]
+pub struct DisplayThrottle {
+    sample_rate: Option<u64>,
+    throttle: Option<Duration>,
+    seen: u64,
+    shown: u64,
+    last_shown: Option<Instant>,
+}
+
+impl DisplayThrottle {
+    pub fn new(sample_rate: Option<u64>, throttle: Option<Duration>) -> Self {
+        Self {
+            sample_rate,
+            throttle,
+            ..Self::default()
+        }
+    }
+
+    /// Count one message and say whether to print it.
+    pub fn admit(&mut self) -> bool {
+        self.seen += 1;
+        if self
+            .sample_rate
+            .is_some_and(|n| !(self.seen - 1).is_multiple_of(n.max(1)))
+        {
+            return false;
+        }
+        if let Some(throttle) = self.throttle {
+            if self.last_shown.is_some_and(|at| at.elapsed() < throttle) {
+                return false;
+            }
+            self.last_shown = Some(Instant::now());
+        }
+        self.shown += 1;
+        true
+    }
+
+    fn is_active(&self) -> bool {
+        self.sample_rate.is_some() || self.throttle.is_some()
+    }
+
+    /// Log how many messages were printed, if any were skipped by design.
+    fn report(&self) {
+        if self.is_active() {
+            info!(
+                "Displayed {} of {} message(s); sinks received all of them",
+                self.shown, self.seen
+            );
+        }
+    }
+}
+
+/// How [`StdoutSink`] prints JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum JsonStyle {
+    /// The decompressed payload as received. Not even parsed, unless a
+    /// client-side filter, stats or another sink needs it parsed anyway
+    Raw,
+    /// One line per payload
+    Compact,
+    /// Indented over several lines
+    #[default]
+    Pretty,
+}
+
+/// Prints each message under a block header. Typed streams get one line per
+/// record (or just the `fields` projection) unless the style is raw;
+/// everything else, and records that don't match the typed shape, is
+/// printed in the [`JsonStyle`]. ORDERS events are counted by lifecycle
+/// state, including those not printed, and the counts logged on flush.
+pub struct StdoutSink {
+    stream_type: StreamType,
+    fields: Vec<String>,
+    style: JsonStyle,
+    /// Name the stream type in each header
+    tagged: bool,
+    throttle: DisplayThrottle,
+    /// ORDERS states to print; empty for all
+    order_states: Vec<OrderLifecycle>,
+    order_counts: BTreeMap<OrderLifecycle, u64>,
+    /// Records are `--project` output rather than the stream's own shape
+    projected: bool,
+}
+
+impl StdoutSink {
+    pub fn new(stream_type: StreamType, fields: Vec<String>) -> Self {
+        Self {
+            stream_type,
+            fields,
+            style: JsonStyle::Pretty,
+            tagged: false,
+            throttle: DisplayThrottle::default(),
+            order_states: Vec::new(),
+            order_counts: BTreeMap::new(),
+            projected: false,
+        }
+    }
+
+    /// Print records as JSON in the [`JsonStyle`], never as typed lines:
+    /// they're projections (see [`crate::projection`]).
+    pub fn projected(mut self, projected: bool) -> Self {
+        self.projected = projected;
+        self
+    }
+
+    /// Print only ORDERS events in one of `states`. Records that don't
+    /// parse as order events are still printed.
+    pub fn order_states(mut self, states: Vec<OrderLifecycle>) -> Self {
+        self.order_states = states;
+        self
+    }
+
+    /// Print only the messages `throttle` admits.
+    pub fn throttled(mut self, throttle: DisplayThrottle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Print JSON in `style`.
+    pub fn styled(mut self, style: JsonStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Print an unparsed payload as is, for [`JsonStyle::Raw`].
+    pub fn write_raw(
+        &mut self,
+        block_number: u64,
+        timestamp: u64,
+        latency_ms: Option<u64>,
+        text: &str,
+    ) {
+        if self.throttle.admit() {
+            self.header(block_number, timestamp, latency_ms);
+            println!("{}", text);
+        }
+    }
+
+    /// Flush for a sink used only through [`write_raw`](Self::write_raw).
+    pub fn finish_raw(&self) {
+        self.throttle.report();
+    }
+
+    fn header(&self, block_number: u64, timestamp: u64, latency_ms: Option<u64>) {
+        let tag = if self.tagged {
+            format!("[{}] ", self.stream_type.as_str_name())
+        } else {
+            String::new()
+        };
+        let latency = latency_ms
+            .map(|ms| format!(" | Latency {} ms", ms))
+            .unwrap_or_default();
+        println!(
+            "\n{}Block {} | Timestamp {}{}",
+            tag, block_number, timestamp, latency
+        );
+    }
+
+    fn json(&self, value: &Value) -> Result<String> {
+        Ok(match self.style {
+            JsonStyle::Pretty => serde_json::to_string_pretty(value)?,
+            JsonStyle::Raw | JsonStyle::Compact => value.to_string(),
+        })
+    }
+
+    /// Prefix each block header with `[STREAM_TYPE]`, for when several
+    /// streams print to the same stdout.
+    pub fn tagged(mut self) -> Self {
+        self.tagged = true;
+        self
+    }
+}
+
+#[async_trait]
+impl RecordSink for StdoutSink {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        if self.stream_type == StreamType::Orders {
+            for order in split_records(record.payload)
+                .into_iter()
+                .filter_map(OrderEvent::from_record)
+            {
+                *self.order_counts.entry(order.lifecycle).or_default() += 1;
+            }
+        }
+        if !self.throttle.admit() {
+            return Ok(());
+        }
+
+        let typed = !self.projected
+            && matches!(
+                self.stream_type,
+                StreamType::WriterActions
+                    | StreamType::Events
+                    | StreamType::Orders
+                    | StreamType::Twap
+            );
+        if !typed || self.style == JsonStyle::Raw {
+            self.header(record.block_number, record.timestamp, record.latency_ms);
+            println!("{}", self.json(record.payload)?);
+            return Ok(());
+        }
+
+        let mut lines = Vec::new();
+        for item in split_records(record.payload) {
+            if !self.fields.is_empty() {
+                lines.push(project(item, &self.fields).to_string());
+                continue;
+            }
+            let line = match self.stream_type {
+                StreamType::WriterActions => WriterAction::from_record(item).map(|a| a.to_string()),
+                StreamType::Orders => match OrderEvent::from_record(item) {
+                    Some(order)
+                        if !self.order_states.is_empty()
+                            && !self.order_states.contains(&order.lifecycle) =>
+                    {
+                        continue
+                    }
+                    order => order.map(|o| o.to_string()),
+                },
+                StreamType::Twap => Twap::from_record(item).map(|t| t.to_string()),
+                _ => Event::from_record(item).map(|e| e.to_string()),
+            };
+            lines.push(match line {
+                Some(line) => line,
+                None => self.json(item)?,
+            });
+        }
+        // Every event filtered out: skip the header too
+        if lines.is_empty() && !self.order_states.is_empty() {
+            return Ok(());
+        }
+        self.header(record.block_number, record.timestamp, record.latency_ms);
+        for line in lines {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.throttle.report();
+        if !self.order_counts.is_empty() {
+            let counts: Vec<String> = self
+                .order_counts
+                .iter()
+                .map(|(state, n)| format!("{} {}", n, state))
+                .collect();
+            info!("Order events: {}", counts.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// Maintains a local book from BOOK_UPDATES deltas and prints the top of
+/// every coin a message touched. A throttle only limits printing; every
+/// delta is applied.
+#[derive(Default)]
+pub struct BookSink {
+    book: DepthBook,
+    throttle: DisplayThrottle,
+}
+
+impl BookSink {
+    pub fn new(throttle: DisplayThrottle) -> Self {
+        Self {
+            book: DepthBook::default(),
+            throttle,
+        }
+    }
+}
+
+#[async_trait]
+impl RecordSink for BookSink {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        let touched = self.book.apply_payload(record.payload);
+        if self.throttle.admit() {
+            for coin in touched {
+                print_book(&self.book, &coin, record.block_number);
+            }
+        }
+        // Without a snapshot, orders placed before subscribing are expected
+        // to be unknown
+        self.book.take_unknown_oids();
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.throttle.report();
+        Ok(())
+    }
+}
+
+fn print_book(book: &DepthBook, coin: &str, block_number: u64) {
+    println!("\n{}", "─".repeat(60));
+    println!("Block: {} | Coin: {}", block_number, coin);
+    println!("{}", "─".repeat(60));
+
+    let asks = book.top_levels(coin, Side::Ask, BOOK_DEPTH);
+    if !asks.is_empty() {
+        println!("\n  ASKS:");
+        for level in asks.iter().rev() {
+            println!(
+                "    {:>12} | {:>12} | ({} orders)",
+                level.px, level.sz, level.n
+            );
+        }
+    }
+
+    let bids = book.top_levels(coin, Side::Bid, BOOK_DEPTH);
+    if !bids.is_empty() {
+        println!("\n  BIDS:");
+        for level in &bids {
+            println!(
+                "    {:>12} | {:>12} | ({} orders)",
+                level.px, level.sz, level.n
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "exact-numbers"))]
+mod tests {
+    use super::*;
+
+    /// A 64-bit oid, an integer past `u64`, and decimals f64 would round.
+    /// Keys are in the order they're serialized in
+    const ORDER: &str = r#"{"limitPx":97000.123456789012345678,"oid":18446744073709551615,"parent":123456789012345678901234567890,"sz":1.10}"#;
+
+    #[test]
+    fn numbers_survive_a_compact_round_trip() {
+        let value: Value = serde_json::from_str(ORDER).unwrap();
+        let sink = StdoutSink::new(StreamType::Orders, Vec::new()).styled(JsonStyle::Compact);
+
+        assert_eq!(sink.json(&value).unwrap(), ORDER);
+        assert_eq!(value["oid"].as_u64(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn numbers_survive_a_pretty_round_trip() {
+        let value: Value = serde_json::from_str(ORDER).unwrap();
+        let sink = StdoutSink::new(StreamType::Orders, Vec::new());
+
+        let pretty = sink.json(&value).unwrap();
+        for number in [
+            "18446744073709551615",
+            "123456789012345678901234567890",
+            "97000.123456789012345678",
+            "1.10",
+        ] {
+            assert!(pretty.contains(number), "{number} not in {pretty}");
+        }
+        let reparsed: Value = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(reparsed.to_string(), ORDER);
+    }
+}
@@ -0,0 +1,172 @@
+//! Where data messages come from.
+//!
+//! The raw stream's read loop pulls from a [`MessageSource`] rather than
+//! from tonic directly: [`GrpcSource`] reads a live subscription and
+//! [`crate::replay::ReplaySource`] a recorded file, and both feed the same
+//! gap checks, limits and outputs. A source for tests or another transport
+//! only has to yield [`RawMessage`]s; see [`crate::stream::stream_source`].
+
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tonic::{Status, Streaming};
+use tracing::{debug, info};
+
+use crate::hyperliquid::{self, StreamResponse, SubscribeUpdate};
+use crate::Result;
+
+/// One data message: `block_number`, `timestamp` (ms) and the payload in
+/// `data`, zstd-compressed or plain JSON text. The generated
+/// `StreamResponse` already carries exactly these, so every source yields
+/// it as is.
+pub type RawMessage = StreamResponse;
+
+#[async_trait]
+pub trait MessageSource: Send {
+    /// The next data message, or `None` once the source has ended. The
+    /// read loop drops a pending call when it shuts down.
+    async fn next(&mut self) -> Result<Option<RawMessage>>;
+}
+
+/// One `SubscribeUpdate` as the read loops see it. Every loop over a
+/// `StreamData` subscription goes through [`Received::from_update`], so a
+/// variant added to the proto is handled in this one `match`.
+///
+/// Server errors aren't an update variant: they end the stream with a gRPC
+/// status, which [`CliError::from_status`](crate::error::CliError::from_status)
+/// classifies.
+#[derive(Debug)]
+pub enum Received {
+    Data(RawMessage),
+    /// A pong, with the timestamp of the ping it answers
+    Pong(i64),
+    /// An update this build has no variant for. prost drops fields it
+    /// doesn't know, so a variant added by a newer server arrives empty;
+    /// regenerating from its `hyperliquid.proto` would make it readable
+    Unknown,
+}
+
+impl Received {
+    /// Classify `update`, logging unknown ones at debug level.
+    pub fn from_update(update: SubscribeUpdate) -> Self {
+        match update.update {
+            Some(hyperliquid::subscribe_update::Update::Data(data)) => Self::Data(data),
+            Some(hyperliquid::subscribe_update::Update::Pong(pong)) => Self::Pong(pong.timestamp),
+            None => {
+                debug!(
+                    "Ignoring an update of a kind this build doesn't know: {:?}",
+                    update
+                );
+                Self::Unknown
+            }
+        }
+    }
+}
+
+/// No data message arrived by the deadline set with
+/// [`GrpcSource::expect_data_by`].
+#[derive(Debug)]
+pub struct NoDataYet(pub Duration);
+
+impl fmt::Display for NoDataYet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no data within {}s of subscribing", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for NoDataYet {}
+
+/// Data messages of a `StreamData` subscription. Pongs are skipped. A
+/// failed or silent stream is an error carrying a [`Status`], so the
+/// caller can decide whether to reconnect.
+pub struct GrpcSource {
+    stream: Streaming<SubscribeUpdate>,
+    read_timeout: Duration,
+    /// Set by [`expect_data_by`](Self::expect_data_by) until data arrives
+    data_deadline: Option<Instant>,
+    data_timeout: Duration,
+    print_pongs: bool,
+    received: bool,
+}
+
+impl GrpcSource {
+    /// Read `stream`, treating `read_timeout` without any message as a
+    /// dropped connection.
+    pub fn new(stream: Streaming<SubscribeUpdate>, read_timeout: Duration) -> Self {
+        Self {
+            stream,
+            read_timeout,
+            data_deadline: None,
+            data_timeout: Duration::ZERO,
+            print_pongs: false,
+            received: false,
+        }
+    }
+
+    /// Fail with [`NoDataYet`] unless a data message arrives within
+    /// `timeout`.
+    pub fn expect_data_by(mut self, timeout: Duration) -> Self {
+        self.data_deadline = Some(Instant::now() + timeout);
+        self.data_timeout = timeout;
+        self
+    }
+
+    /// Print each pong to stdout, for `--proto-dump`.
+    pub fn print_pongs(mut self, print: bool) -> Self {
+        self.print_pongs = print;
+        self
+    }
+
+    /// Whether any message, pongs included, has arrived.
+    pub fn received(&self) -> bool {
+        self.received
+    }
+}
+
+#[async_trait]
+impl MessageSource for GrpcSource {
+    async fn next(&mut self) -> Result<Option<RawMessage>> {
+        loop {
+            let deadline = self.data_deadline;
+            let no_data = async move {
+                match deadline {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => std::future::pending().await,
+                }
+            };
+            let message = tokio::select! {
+                message = self.stream.message() => message?,
+                // A half-open connection never errors; treat silence as a drop
+                _ = tokio::time::sleep(self.read_timeout) => {
+                    return Err(Status::unavailable(format!(
+                        "no message for {}s",
+                        self.read_timeout.as_secs()
+                    ))
+                    .into());
+                }
+                _ = no_data => return Err(NoDataYet(self.data_timeout).into()),
+            };
+            let Some(update) = message else {
+                info!("Stream ended");
+                return Ok(None);
+            };
+            self.received = true;
+
+            match Received::from_update(update) {
+                Received::Data(data) => {
+                    self.data_deadline = None;
+                    return Ok(Some(data));
+                }
+                Received::Pong(timestamp) => {
+                    debug!("Pong: {}", timestamp);
+                    if self.print_pongs {
+                        println!("Pong timestamp={}", timestamp);
+                    }
+                }
+                Received::Unknown => {}
+            }
+        }
+    }
+}
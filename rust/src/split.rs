@@ -0,0 +1,140 @@
+//! One file per block, for archival.
+//!
+//! Each message's decompressed payload is written, byte for byte, to
+//! `<dir>/<block_number / 10000>/<block_number>.json`, e.g. block 830001234
+//! goes to `83000/830001234.json`. Bucketing keeps directories to 10,000
+//! entries; a flat directory of millions of files makes listing, backup and
+//! `rm` painfully slow on most filesystems. Every block still takes an
+//! inode (and at least one filesystem block, often 4 KB), so a long archive
+//! of small payloads can run out of inodes before it runs out of space:
+//! check `df -i`.
+//!
+//! A block whose file already exists is skipped, so an interrupted archive
+//! can be resumed by rerunning from an earlier block. Files are written
+//! under a temporary name and renamed into place, so a crash never leaves
+//! a truncated file that would then be skipped. Writes run in background
+//! tasks, at most [`MAX_CONCURRENT_WRITES`] at a time, so a slow disk only
+//! stalls the stream once that many are outstanding.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::info;
+
+use crate::error::CliError;
+use crate::Result;
+
+/// Blocks per subdirectory
+pub const BLOCKS_PER_DIR: u64 = 10_000;
+
+/// Block files written in parallel before a write waits for one to finish
+pub const MAX_CONCURRENT_WRITES: usize = 16;
+
+/// Writes each block's payload to its own file under a directory.
+pub struct BlockFileWriter {
+    dir: PathBuf,
+    permits: Arc<Semaphore>,
+    /// Each write reports whether it wrote the file (or found it present)
+    writes: JoinSet<Result<bool>>,
+    /// Block written last. A second message for the same block is skipped
+    last_block: Option<u64>,
+    written: u64,
+    skipped: u64,
+}
+
+impl BlockFileWriter {
+    /// Write under `dir`, creating it if needed.
+    pub fn create(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| CliError::BadArgs(format!("can't create {}: {}", dir.display(), e)))?;
+        info!("Writing one file per block under {}", dir.display());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_WRITES)),
+            writes: JoinSet::new(),
+            last_block: None,
+            written: 0,
+            skipped: 0,
+        })
+    }
+
+    /// Where block `block_number` is written.
+    pub fn path_for(&self, block_number: u64) -> PathBuf {
+        self.dir
+            .join((block_number / BLOCKS_PER_DIR).to_string())
+            .join(format!("{}.json", block_number))
+    }
+
+    /// Queue `payload` to be written as block `block_number`, waiting only
+    /// if [`MAX_CONCURRENT_WRITES`] writes are already in flight. A failed
+    /// earlier write is returned here.
+    pub async fn write(&mut self, block_number: u64, payload: &[u8]) -> Result<()> {
+        self.collect_finished()?;
+        if self.last_block == Some(block_number) {
+            self.skipped += 1;
+            return Ok(());
+        }
+        self.last_block = Some(block_number);
+
+        let permit = self.permits.clone().acquire_owned().await?;
+        let path = self.path_for(block_number);
+        let payload = payload.to_vec();
+        self.writes.spawn(async move {
+            let written = write_new(&path, &payload).await;
+            drop(permit);
+            written.map_err(|e| format!("failed to write {}: {}", path.display(), e).into())
+        });
+        Ok(())
+    }
+
+    /// Wait for every queued write and log what was written.
+    pub async fn finish(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        while let Some(done) = self.writes.join_next().await {
+            if let Err(e) = self.count(done) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        info!(
+            "Wrote {} block file(s) under {}, skipped {} already present",
+            self.written,
+            self.dir.display(),
+            self.skipped
+        );
+        result
+    }
+
+    fn collect_finished(&mut self) -> Result<()> {
+        while let Some(done) = self.writes.try_join_next() {
+            self.count(done)?;
+        }
+        Ok(())
+    }
+
+    fn count(&mut self, done: Result<Result<bool>, tokio::task::JoinError>) -> Result<()> {
+        if done?? {
+            self.written += 1;
+        } else {
+            self.skipped += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Write `payload` to `path` unless it exists, returning whether it wrote.
+async fn write_new(path: &Path, payload: &[u8]) -> std::io::Result<bool> {
+    if tokio::fs::try_exists(path).await? {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let partial = path.with_extension("json.partial");
+    tokio::fs::write(&partial, payload).await?;
+    tokio::fs::rename(&partial, path).await?;
+    Ok(true)
+}
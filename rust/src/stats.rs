@@ -0,0 +1,332 @@
+//! Per-coin throughput statistics for the raw stream.
+//!
+//! Counts are kept inline by the read loop (no extra task) and a summary of
+//! the busiest coins is printed whenever the reporting interval has passed.
+//! Rates cover the last interval; totals cover the whole run.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::records::{field_str, split_records};
+
+/// Coins shown per summary
+const TOP_COINS: usize = 10;
+/// Minimum time between compression ratio reports
+const COMPRESSION_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Clone)]
+pub struct CoinStats {
+    pub messages: u64,
+    pub bytes: u64,
+    pub last_block: u64,
+    /// Messages since the last summary
+    window_messages: u64,
+}
+
+pub struct StatsReporter {
+    coins: HashMap<String, CoinStats>,
+    interval: Duration,
+    window_start: Instant,
+}
+
+impl StatsReporter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            coins: HashMap::new(),
+            interval,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Count every record of `payload` under its `coin` (or `unknown`), then
+    /// print a summary if one is due.
+    pub fn record(&mut self, block_number: u64, payload: &Value) {
+        for record in split_records(payload) {
+            let coin = field_str(record, "coin").unwrap_or_else(|| "unknown".to_string());
+            let stats = self.coins.entry(coin).or_default();
+            stats.messages += 1;
+            stats.window_messages += 1;
+            stats.bytes += record.to_string().len() as u64;
+            stats.last_block = stats.last_block.max(block_number);
+        }
+
+        if self.window_start.elapsed() >= self.interval {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+        let mut coins: Vec<(&String, &CoinStats)> = self.coins.iter().collect();
+        coins.sort_by(|a, b| {
+            b.1.window_messages
+                .cmp(&a.1.window_messages)
+                .then(b.1.messages.cmp(&a.1.messages))
+        });
+
+        let mut table = format!(
+            "Per-coin stats (last {:.0}s)\n  {:<12} {:>10} {:>12} {:>12} {:>14}",
+            elapsed, "COIN", "MSG/S", "TOTAL", "TOTAL KB", "LAST BLOCK"
+        );
+        for (coin, stats) in coins.iter().take(TOP_COINS) {
+            table.push_str(&format!(
+                "\n  {:<12} {:>10.1} {:>12} {:>12.1} {:>14}",
+                coin,
+                stats.window_messages as f64 / elapsed,
+                stats.messages,
+                stats.bytes as f64 / 1024.0,
+                stats.last_block
+            ));
+        }
+        if coins.len() > TOP_COINS {
+            table.push_str(&format!("\n  ... and {} more", coins.len() - TOP_COINS));
+        }
+
+        info!("{}", table);
+
+        for stats in self.coins.values_mut() {
+            stats.window_messages = 0;
+        }
+        self.window_start = Instant::now();
+    }
+}
+
+/// Single-line transport throughput for `--count-only`, rewritten in place
+/// once a second.
+pub struct RateLine {
+    window_start: Instant,
+    messages: u64,
+    raw_bytes: u64,
+    bytes: u64,
+    total_messages: u64,
+    last_block: u64,
+}
+
+impl RateLine {
+    pub fn new() -> Self {
+        Self {
+            window_start: Instant::now(),
+            messages: 0,
+            raw_bytes: 0,
+            bytes: 0,
+            total_messages: 0,
+            last_block: 0,
+        }
+    }
+
+    /// Count one message of `raw_bytes` on the wire and `bytes` after
+    /// decompression.
+    pub fn record(&mut self, block_number: u64, raw_bytes: usize, bytes: usize) {
+        self.messages += 1;
+        self.total_messages += 1;
+        self.raw_bytes += raw_bytes as u64;
+        self.bytes += bytes as u64;
+        self.last_block = block_number;
+
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.print();
+        }
+    }
+
+    fn print(&mut self) {
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+        print!(
+            "\r{:>8.1} msg/s | {:>9.1} KB/s wire | {:>9.1} KB/s decoded | block {} | {} total   ",
+            self.messages as f64 / elapsed,
+            self.raw_bytes as f64 / 1024.0 / elapsed,
+            self.bytes as f64 / 1024.0 / elapsed,
+            self.last_block,
+            self.total_messages
+        );
+        let _ = std::io::stdout().flush();
+
+        self.messages = 0;
+        self.raw_bytes = 0;
+        self.bytes = 0;
+        self.window_start = Instant::now();
+    }
+
+    /// Print the last partial window and end the updating line.
+    pub fn finish(&mut self) {
+        if self.total_messages > 0 {
+            if self.messages > 0 {
+                self.print();
+            }
+            println!();
+        }
+    }
+}
+
+impl Default for RateLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cumulative wire vs decoded payload bytes, reported as a compression
+/// ratio at most once a minute and when the stream ends.
+pub struct CompressionStats {
+    messages: u64,
+    compressed_messages: u64,
+    wire_bytes: u64,
+    decoded_bytes: u64,
+    last_report: Instant,
+}
+
+impl CompressionStats {
+    pub fn new() -> Self {
+        Self {
+            messages: 0,
+            compressed_messages: 0,
+            wire_bytes: 0,
+            decoded_bytes: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Count one payload of `wire_bytes` that decoded to `decoded_bytes`.
+    pub fn record(&mut self, compressed: bool, wire_bytes: usize, decoded_bytes: usize) {
+        self.messages += 1;
+        self.compressed_messages += compressed as u64;
+        self.wire_bytes += wire_bytes as u64;
+        self.decoded_bytes += decoded_bytes as u64;
+
+        if self.last_report.elapsed() >= COMPRESSION_REPORT_INTERVAL {
+            self.report();
+        }
+    }
+
+    /// Payload bytes counted as received
+    pub fn wire_bytes(&self) -> u64 {
+        self.wire_bytes
+    }
+
+    /// Payload bytes counted after decompression
+    pub fn decoded_bytes(&self) -> u64 {
+        self.decoded_bytes
+    }
+
+    /// Decoded bytes per wire byte so far; 1.0 before anything is counted.
+    pub fn ratio(&self) -> f64 {
+        if self.wire_bytes == 0 {
+            return 1.0;
+        }
+        self.decoded_bytes as f64 / self.wire_bytes as f64
+    }
+
+    pub fn report(&mut self) {
+        self.last_report = Instant::now();
+        if self.messages == 0 {
+            return;
+        }
+        info!(
+            "Compression: {:.1} KB wire -> {:.1} KB decoded ({:.2}x, {} of {} message(s) zstd)",
+            self.wire_bytes as f64 / 1024.0,
+            self.decoded_bytes as f64 / 1024.0,
+            self.ratio(),
+            self.compressed_messages,
+            self.messages
+        );
+    }
+}
+
+impl Default for CompressionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Upper bounds (exclusive) of the [`LatencyHistogram`] buckets, in ms. The
+/// last bucket holds everything from the final bound up.
+const LATENCY_BUCKETS_MS: [u64; 9] = [10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000];
+
+/// How late data messages arrive: the local clock minus each message's
+/// `timestamp`, which is the server's ingress time in unix milliseconds.
+/// Local and server clocks are assumed to agree; a message stamped in the
+/// future counts as 0 ms, with a warning the first time.
+pub struct LatencyHistogram {
+    counts: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    messages: u64,
+    sum_ms: u64,
+    min_ms: u64,
+    max_ms: u64,
+    skew_warned: bool,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            counts: [0; LATENCY_BUCKETS_MS.len() + 1],
+            messages: 0,
+            sum_ms: 0,
+            min_ms: u64::MAX,
+            max_ms: 0,
+            skew_warned: false,
+        }
+    }
+
+    /// Count a message stamped `timestamp_ms` arriving now, returning its
+    /// latency in ms. `None` for a message without a timestamp.
+    pub fn record(&mut self, timestamp_ms: u64) -> Option<u64> {
+        if timestamp_ms == 0 {
+            return None;
+        }
+        let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+        if now_ms < timestamp_ms && !self.skew_warned {
+            self.skew_warned = true;
+            warn!(
+                "Message timestamped {} ms in the future; the local clock is behind the server's. Such latencies count as 0",
+                timestamp_ms - now_ms
+            );
+        }
+        let latency_ms = now_ms.saturating_sub(timestamp_ms);
+
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| latency_ms < bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+        self.messages += 1;
+        self.sum_ms += latency_ms;
+        self.min_ms = self.min_ms.min(latency_ms);
+        self.max_ms = self.max_ms.max(latency_ms);
+        Some(latency_ms)
+    }
+
+    /// Log the summary and each bucket up to the slowest message.
+    pub fn report(&self) {
+        if self.messages == 0 {
+            return;
+        }
+        info!(
+            "Latency over {} message(s): min {} ms, mean {:.1} ms, max {} ms",
+            self.messages,
+            self.min_ms,
+            self.sum_ms as f64 / self.messages as f64,
+            self.max_ms
+        );
+        let last = self.counts.iter().rposition(|&n| n > 0).unwrap_or(0);
+        for (i, &count) in self.counts.iter().enumerate().take(last + 1) {
+            let label = match LATENCY_BUCKETS_MS.get(i) {
+                Some(bound) => format!("< {} ms", bound),
+                None => format!(">= {} ms", LATENCY_BUCKETS_MS[i - 1]),
+            };
+            info!(
+                "  {:>10}: {:>8} ({:>5.1}%)",
+                label,
+                count,
+                count as f64 / self.messages as f64 * 100.0
+            );
+        }
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,1607 @@
+//! Raw data stream (`Streaming.StreamData`) client with reconnect.
+//!
+//! [`stream_data`] runs until the stream ends or Ctrl-C. To embed a stream
+//! in a larger service, [`stream_data_until`] stops when a
+//! [`CancellationToken`] is cancelled instead, and returns a
+//! [`StreamSummary`] once the connection is closed and every output flushed.
+//! [`stream_source`] runs the same outputs over any other
+//! [`MessageSource`], such as a recorded file or a test double.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+pub use tokio_util::sync::CancellationToken;
+use tonic::Status;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::buffer::{self, OnFull};
+use crate::candles::CandleSink;
+use crate::checkpoint::{self, Checkpoint};
+use crate::client_filter::ClientFilter;
+use crate::common::{
+    authed_request, decompress_with, is_zstd, streaming_client, ChannelOptions, PayloadEncoding,
+    ZstdDict,
+};
+use crate::compressed::OutputCompression;
+use crate::dedup::ReplayDedup;
+use crate::endpoints::EndpointPool;
+use crate::error::{is_message_too_large, CliError};
+use crate::export::TradeCsvWriter;
+use crate::gaps::{expects_every_block, GapDetector, OnGap};
+use crate::hyperliquid::{
+    self, FilterValues, Ping, StreamResponse, StreamSubscribe, StreamType, SubscribeRequest,
+};
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaSink;
+#[cfg(feature = "parquet")]
+use crate::parquet_export::TradeParquetWriter;
+#[cfg(feature = "postgres")]
+use crate::postgres_sink::PgSink;
+use crate::projection::Projection;
+use crate::recent::RecentRecords;
+use crate::reconnect::{
+    is_retryable, retry_base_delay, CircuitBreaker, RetryBudget, BASE_DELAY_SECS,
+    DEFAULT_READ_TIMEOUT, MAX_RETRIES,
+};
+use crate::report::{redact, ExitReason, RateMeter, ReconnectEvent, RunReport};
+use crate::s3::{find_block_ranges, stream_blocks, Bucket, MAINNET_BUCKET};
+use crate::schema::{SchemaSource, SchemaValidator};
+use crate::since::{current_tip, earliest_block};
+use crate::sink::{BookSink, DisplayThrottle, JsonStyle, Record, RecordSink, StdoutSink};
+use crate::source::{GrpcSource, MessageSource, NoDataYet};
+use crate::split::BlockFileWriter;
+use crate::stats::{CompressionStats, LatencyHistogram, RateLine, StatsReporter};
+use crate::twap::TwapSink;
+use crate::typed::OrderLifecycle;
+#[cfg(unix)]
+use crate::uds::UdsBroadcaster;
+use crate::ws::WsBroadcaster;
+use crate::Result;
+
+/// Parse a stream type by its proto name, case-insensitively. Unrecognised
+/// names (and `UNKNOWN`) fall back to TRADES.
+pub fn parse_stream_type(s: &str) -> StreamType {
+    match StreamType::from_str_name(&s.to_uppercase()) {
+        Some(StreamType::Unknown) | None => StreamType::Trades,
+        Some(stream_type) => stream_type,
+    }
+}
+
+/// Filter fields known to match records of each stream type.
+///
+/// - TRADES: `coin`, `side`, `user`, `hash`, `tid`
+/// - ORDERS: `coin`, `side`, `user`, `oid`, `cloid`, `status`
+/// - BOOK_UPDATES: `coin`, `side`, `user`, `oid`
+/// - TWAP: `coin`, `side`, `user`, `status`
+/// - EVENTS: `type`, `user`, `coin`, `hash`
+/// - WRITER_ACTIONS: `type`, `user`
+/// - BLOCKS: none (raw blocks are not filterable)
+///
+/// The server matches filters against fields anywhere in a record, so a
+/// field missing here may still work; `--allow-unknown-filters` skips the check.
+pub fn known_filter_fields(stream_type: StreamType) -> &'static [&'static str] {
+    match stream_type {
+        StreamType::Trades => &["coin", "side", "user", "hash", "tid"],
+        StreamType::Orders => &["coin", "side", "user", "oid", "cloid", "status"],
+        StreamType::BookUpdates => &["coin", "side", "user", "oid"],
+        StreamType::Twap => &["coin", "side", "user", "status"],
+        StreamType::Events => &["type", "user", "coin", "hash"],
+        StreamType::WriterActions => &["type", "user"],
+        StreamType::Blocks | StreamType::Unknown => &[],
+    }
+}
+
+/// Reject filter fields that `stream_type` records don't have, or only warn
+/// about them when `allow_unknown` is set.
+pub fn validate_filters(
+    stream_type: StreamType,
+    filters: &HashMap<String, Vec<String>>,
+    allow_unknown: bool,
+) -> Result<()> {
+    let known = known_filter_fields(stream_type);
+    let mut unknown: Vec<&str> = filters
+        .keys()
+        .map(String::as_str)
+        .filter(|field| !known.contains(field))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort_unstable();
+
+    let valid = if known.is_empty() {
+        "none".to_string()
+    } else {
+        known.join(", ")
+    };
+    let message = format!(
+        "unknown filter field(s) for {}: {} (valid fields: {})",
+        stream_type.as_str_name(),
+        unknown.join(", "),
+        valid
+    );
+
+    if allow_unknown {
+        warn!("{}", message);
+        Ok(())
+    } else {
+        Err(format!(
+            "{}; pass --allow-unknown-filters to send them anyway",
+            message
+        )
+        .into())
+    }
+}
+
+/// Add `values` to the `field` filter, keeping the existing values and
+/// dropping duplicates.
+pub fn add_filter_values(
+    filters: &mut HashMap<String, Vec<String>>,
+    field: &str,
+    values: impl IntoIterator<Item = String>,
+) {
+    let existing = filters.entry(field.to_string()).or_default();
+    for value in values {
+        if !existing.contains(&value) {
+            existing.push(value);
+        }
+    }
+}
+
+/// Read coin symbols for the `coin` filter from `path`, one per line.
+/// Blank lines and `#` comments are ignored and duplicates dropped; a file
+/// with no coins is an error.
+///
+/// The server doesn't document a limit on filter values; very long lists
+/// make every (re)subscription larger, so prefer the narrowest list that
+/// covers what you need.
+pub fn read_coins_file(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read coins file {}: {}", path.display(), e))?;
+    let mut coins: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let coin = line.split('#').next().unwrap_or_default().trim();
+        if !coin.is_empty() && !coins.iter().any(|c| c == coin) {
+            coins.push(coin.to_string());
+        }
+    }
+    if coins.is_empty() {
+        return Err(format!("coins file {} lists no coins", path.display()).into());
+    }
+    Ok(coins)
+}
+
+/// Settings for [`stream_data`] beyond the subscription itself.
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+    pub endpoint: String,
+    pub token: String,
+    /// Further `(endpoint, token)` pairs. Reconnects rotate through all of
+    /// them, preferring those that have failed least (see
+    /// [`EndpointPool`])
+    pub extra_endpoints: Vec<(String, String)>,
+    pub channel: ChannelOptions,
+    /// Name sent with the subscription, kept across reconnects
+    pub filter_name: String,
+    /// Block to subscribe from on the first connect; `None` (or 0) starts
+    /// at the live tip
+    pub start_block: Option<u64>,
+    /// When starting at the live tip, first read one block to learn the
+    /// tip, then subscribe from it: the start block is logged, and gap
+    /// checks count from it (see [`crate::since::current_tip`])
+    pub warmup: bool,
+    /// Live data only, whatever the server does with `start_block` 0: the
+    /// tip is read first (failing if it can't be), and messages for blocks
+    /// before it are dropped and counted. Overrides `start_block`
+    pub tail: bool,
+    /// If `start_block` is no longer retained, start from the earliest block
+    /// that is instead of failing
+    pub clamp_start: bool,
+    /// Stop at the first data message past this block, after emitting every
+    /// message for the block itself
+    pub to_block: Option<u64>,
+    /// Records that don't pass are dropped before any output
+    pub client_filter: ClientFilter,
+    /// Cut each record down to these fields before any output, dropping
+    /// records with none of them (see [`crate::projection`])
+    pub projection: Option<Projection>,
+    /// Validate each record against this JSON Schema, logging violations
+    /// (see [`crate::schema`])
+    pub schema: Option<SchemaSource>,
+    /// Reconnect when no message arrives for this long
+    pub read_timeout: Duration,
+    /// Pause for longer when reconnects come too often (see
+    /// [`crate::reconnect`])
+    pub breaker: Option<CircuitBreaker>,
+    /// Give up once reconnecting has gone on this long without data
+    pub max_outage: Option<Duration>,
+    /// Write a JSON [`RunReport`] here when the stream stops cleanly or
+    /// gives up reconnecting
+    pub report: Option<PathBuf>,
+    /// Zstd dictionary for payloads compressed against one
+    pub zstd_dict: Option<PathBuf>,
+    /// Whether payloads are checked for compression, or expected plain or
+    /// compressed
+    pub payload_encoding: PayloadEncoding,
+    /// Capacity of the channel carrying the subscription and pings to the
+    /// server
+    pub request_capacity: usize,
+    /// Queue up to this many data messages between the read loop and the
+    /// outputs, which then run in their own task
+    pub buffer: Option<usize>,
+    /// What to do when that queue is full
+    pub on_full: OnFull,
+    /// Stop after this many data messages
+    pub sample: Option<usize>,
+    /// What to do about skipped blocks on the BLOCKS stream (see
+    /// [`crate::gaps`])
+    pub on_gap: OnGap,
+    /// S3 bucket that `OnGap::Backfill` fetches missing blocks from
+    pub gap_bucket: Bucket,
+    /// Save the last handled block here periodically and on exit, and skip
+    /// the records of that block it lists (see [`crate::checkpoint`])
+    pub state_file: Option<PathBuf>,
+    /// Write TRADES rows to this CSV file
+    pub csv: Option<PathBuf>,
+    /// Compress `csv` as it is written; `None` goes by its extension
+    pub compress_output: Option<OutputCompression>,
+    /// Apply BOOK_UPDATES deltas to a local book and print it instead of
+    /// the raw deltas
+    pub reconstruct: bool,
+    /// Track each TWAP's progress and print only changes (see
+    /// [`crate::twap`]) instead of the raw records
+    pub track_twaps: bool,
+    /// Aggregate TRADES into OHLCV bars of this interval, with this grace
+    /// for late trades, and print the bars instead of the trades
+    pub candles: Option<(Duration, Duration)>,
+    /// Rebroadcast decoded messages to WebSocket clients on this port
+    pub ws_port: Option<u16>,
+    /// Keep the last `.1` decoded records in memory and serve them over
+    /// HTTP on port `.0` (see [`crate::recent`])
+    pub control: Option<(u16, usize)>,
+    /// Forward decoded messages as NDJSON to consumers of this Unix socket
+    #[cfg(unix)]
+    pub forward_uds: Option<PathBuf>,
+    /// Write each block's decompressed payload to its own file under this
+    /// directory (see [`crate::split`])
+    pub split_dir: Option<PathBuf>,
+    /// Print a per-coin throughput summary at this interval
+    pub stats_interval: Option<Duration>,
+    /// For WRITER_ACTIONS and EVENTS, print only these record fields (dotted
+    /// paths allowed) as one compact JSON line per record
+    pub fields: Vec<String>,
+    /// For ORDERS, print only events in these lifecycle states
+    pub order_states: Vec<OrderLifecycle>,
+    /// Name the stream type in each printed block header
+    pub tag_output: bool,
+    /// How payloads are printed to stdout
+    pub json_style: JsonStyle,
+    /// Print only every Nth message. Sinks and stats still see all of them
+    pub sample_rate: Option<u64>,
+    /// Print at most one message per interval. Sinks and stats still see
+    /// all of them
+    pub throttle: Option<Duration>,
+    /// Only count messages and bytes, printing a rate line instead of the
+    /// payloads. Skips JSON parsing and every sink
+    pub count_only: bool,
+    /// Print each data message's latency (arrival time minus its
+    /// `timestamp`) in its header, and a histogram of them on exit
+    pub show_latency: bool,
+    /// Print each message's protobuf fields and how its payload decodes
+    /// (compression, decoded length, whether it's JSON) instead of the
+    /// payload. Skips every sink
+    pub proto_dump: bool,
+    /// Hand each element of an array payload to the sinks as its own record,
+    /// with the message's block number and timestamp
+    pub flatten: bool,
+    /// Publish decoded messages to Kafka: `(brokers, topic)`
+    #[cfg(feature = "kafka")]
+    pub kafka: Option<(String, String)>,
+    /// Write TRADES to this Parquet file
+    #[cfg(feature = "parquet")]
+    pub parquet: Option<PathBuf>,
+    /// Insert TRADES into Postgres: connection URL, table and batch size
+    #[cfg(feature = "postgres")]
+    pub postgres: Option<(String, String, usize)>,
+}
+
+impl StreamOptions {
+    /// `endpoint` and `token` followed by `extra_endpoints`.
+    pub fn endpoints(&self) -> Vec<(String, String)> {
+        let mut endpoints = vec![(self.endpoint.clone(), self.token.clone())];
+        endpoints.extend(self.extra_endpoints.iter().cloned());
+        endpoints
+    }
+
+    /// Options for the first of `endpoints`, with the rest as
+    /// `extra_endpoints`.
+    pub fn with_endpoints(endpoints: &[(String, String)]) -> Result<Self> {
+        let ((endpoint, token), extra) = endpoints
+            .split_first()
+            .ok_or_else(|| CliError::BadArgs("no endpoint given".to_string()))?;
+        let mut options = Self::new(endpoint, token);
+        options.extra_endpoints = extra.to_vec();
+        Ok(options)
+    }
+
+    pub fn new(endpoint: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            token: token.into(),
+            extra_endpoints: Vec::new(),
+            channel: ChannelOptions::default(),
+            filter_name: String::new(),
+            start_block: None,
+            warmup: true,
+            tail: false,
+            clamp_start: false,
+            to_block: None,
+            client_filter: ClientFilter::default(),
+            projection: None,
+            schema: None,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            breaker: Some(CircuitBreaker::default()),
+            max_outage: None,
+            report: None,
+            zstd_dict: None,
+            payload_encoding: PayloadEncoding::Auto,
+            request_capacity: REQUEST_CHANNEL_CAPACITY,
+            buffer: None,
+            on_full: OnFull::Block,
+            sample: None,
+            on_gap: OnGap::Warn,
+            gap_bucket: Bucket::requester_pays(MAINNET_BUCKET),
+            state_file: None,
+            csv: None,
+            compress_output: None,
+            reconstruct: false,
+            track_twaps: false,
+            candles: None,
+            ws_port: None,
+            control: None,
+            #[cfg(unix)]
+            forward_uds: None,
+            split_dir: None,
+            stats_interval: None,
+            count_only: false,
+            show_latency: false,
+            proto_dump: false,
+            flatten: false,
+            fields: Vec::new(),
+            order_states: Vec::new(),
+            tag_output: false,
+            json_style: JsonStyle::Pretty,
+            sample_rate: None,
+            throttle: None,
+            #[cfg(feature = "kafka")]
+            kafka: None,
+            #[cfg(feature = "parquet")]
+            parquet: None,
+            #[cfg(feature = "postgres")]
+            postgres: None,
+        }
+    }
+}
+
+/// Default capacity of the request channel. It only carries the
+/// subscription and a ping every 30s, so this is plenty.
+pub const REQUEST_CHANNEL_CAPACITY: usize = 32;
+
+/// Aborts the keep-alive ping task when a connection attempt ends.
+pub(crate) struct PingTask(JoinHandle<()>);
+
+impl PingTask {
+    pub(crate) fn spawn(tx: mpsc::Sender<SubscribeRequest>) -> Self {
+        Self(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                let _ = tx
+                    .send(SubscribeRequest {
+                        request: Some(hyperliquid::subscribe_request::Request::Ping(Ping {
+                            timestamp: chrono::Utc::now().timestamp_millis(),
+                        })),
+                    })
+                    .await;
+            }
+        }))
+    }
+}
+
+impl Drop for PingTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// How long to wait for the first message after subscribing from an
+/// explicit start block
+const START_BLOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Per-message output for the raw stream, shared by the live stream and
+/// replay.
+pub(crate) struct Output {
+    client_filter: ClientFilter,
+    projection: Option<Projection>,
+    /// Decompression buffer reused across messages
+    scratch: Vec<u8>,
+    zstd_dict: Option<ZstdDict>,
+    payload_encoding: PayloadEncoding,
+    dedup: ReplayDedup,
+    stats: Option<StatsReporter>,
+    compression: CompressionStats,
+    rate_line: Option<RateLine>,
+    latency: Option<LatencyHistogram>,
+    proto_dump: bool,
+    flatten: bool,
+    /// Block files, written as received before any filtering
+    split: Option<BlockFileWriter>,
+    checkpoint: Option<Checkpoint>,
+    /// Every active output, in the order messages are written to them
+    sinks: Vec<Box<dyn RecordSink>>,
+    /// Stdout when it's the only output and prints payloads unparsed
+    /// ([`JsonStyle::Raw`]); `sinks` is then empty
+    raw_stdout: Option<StdoutSink>,
+}
+
+impl Output {
+    /// Open every sink `options` asks for, checking each is supported by
+    /// `stream_type`.
+    pub(crate) async fn new(
+        stream_type: StreamType,
+        filters: &HashMap<String, Vec<String>>,
+        options: &StreamOptions,
+    ) -> Result<Self> {
+        // Check every flag before opening anything
+        let trades_only = |flag: &str, given: bool| -> Result<()> {
+            if given && stream_type != StreamType::Trades {
+                return Err(CliError::BadArgs(format!(
+                    "{} is only supported for the TRADES stream",
+                    flag
+                ))
+                .into());
+            }
+            Ok(())
+        };
+        trades_only("--csv", options.csv.is_some())?;
+        trades_only("--candles", options.candles.is_some())?;
+        #[cfg(feature = "parquet")]
+        trades_only("--parquet", options.parquet.is_some())?;
+        #[cfg(feature = "postgres")]
+        trades_only("--pg-url", options.postgres.is_some())?;
+        if options.reconstruct && stream_type != StreamType::BookUpdates {
+            return Err(CliError::BadArgs(
+                "--reconstruct is only supported for the BOOK_UPDATES stream".to_string(),
+            )
+            .into());
+        }
+        if options.track_twaps && stream_type != StreamType::Twap {
+            return Err(CliError::BadArgs(
+                "--track-twaps is only supported for the TWAP stream".to_string(),
+            )
+            .into());
+        }
+        if !options.fields.is_empty()
+            && !matches!(stream_type, StreamType::WriterActions | StreamType::Events)
+        {
+            return Err(CliError::BadArgs(
+                "--fields is only supported for the WRITER_ACTIONS and EVENTS streams".to_string(),
+            )
+            .into());
+        }
+        if !options.order_states.is_empty() && stream_type != StreamType::Orders {
+            return Err(CliError::BadArgs(
+                "--status is only supported for the ORDERS stream".to_string(),
+            )
+            .into());
+        }
+
+        let schema = match &options.schema {
+            Some(source) => Some(SchemaValidator::load(source, stream_type)?),
+            None => None,
+        };
+
+        let mut sinks: Vec<Box<dyn RecordSink>> = Vec::new();
+        if let Some(schema) = schema {
+            sinks.push(Box::new(schema));
+        }
+        if let Some(port) = options.ws_port {
+            let handshake = serde_json::json!({
+                "type": "handshake",
+                "stream_type": stream_type.as_str_name(),
+                "filters": filters,
+                "filter_name": options.filter_name,
+            });
+            sinks.push(Box::new(WsBroadcaster::bind(port, handshake).await?));
+        }
+        if let Some((port, ring_size)) = options.control {
+            sinks.push(Box::new(RecentRecords::bind(port, ring_size).await?));
+        }
+        #[cfg(unix)]
+        if let Some(path) = &options.forward_uds {
+            sinks.push(Box::new(UdsBroadcaster::bind(path).await?));
+        }
+        #[cfg(feature = "kafka")]
+        if let Some((brokers, topic)) = &options.kafka {
+            sinks.push(Box::new(KafkaSink::new(brokers, topic)?));
+        }
+        let display = DisplayThrottle::new(options.sample_rate, options.throttle);
+        let mut stdout = None;
+        if options.reconstruct {
+            sinks.push(Box::new(BookSink::new(display)));
+        } else if options.track_twaps {
+            sinks.push(Box::new(TwapSink::new()));
+        } else if let Some((interval, grace)) = options.candles {
+            sinks.push(Box::new(CandleSink::new(interval, grace)));
+        } else {
+            let sink = StdoutSink::new(stream_type, options.fields.clone())
+                .order_states(options.order_states.clone())
+                .projected(options.projection.is_some())
+                .throttled(display)
+                .styled(options.json_style);
+            stdout = Some((
+                sinks.len(),
+                if options.tag_output {
+                    sink.tagged()
+                } else {
+                    sink
+                },
+            ));
+        }
+        if let Some(path) = &options.csv {
+            sinks.push(Box::new(TradeCsvWriter::create(
+                path,
+                options.compress_output,
+            )?));
+        }
+        #[cfg(feature = "parquet")]
+        if let Some(path) = &options.parquet {
+            sinks.push(Box::new(TradeParquetWriter::create(path)?));
+        }
+        #[cfg(feature = "postgres")]
+        if let Some((url, table, batch_size)) = &options.postgres {
+            sinks.push(Box::new(PgSink::trades(url, table, *batch_size).await?));
+        }
+
+        // Raw stdout alone needs nothing parsed
+        let unparsed = options.json_style == JsonStyle::Raw
+            && sinks.is_empty()
+            && options.client_filter.is_empty()
+            && options.projection.is_none()
+            && options.stats_interval.is_none()
+            && options.state_file.is_none()
+            && !options.flatten;
+        let mut raw_stdout = None;
+        match stdout {
+            Some((_, sink)) if unparsed => raw_stdout = Some(sink),
+            Some((at, sink)) => sinks.insert(at, Box::new(sink)),
+            None => {}
+        }
+
+        let zstd_dict = match &options.zstd_dict {
+            Some(path) => {
+                let dict = ZstdDict::load(path).map_err(|e| CliError::BadArgs(e.to_string()))?;
+                match dict.id() {
+                    Some(id) => info!("Loaded zstd dictionary {} ({})", id, path.display()),
+                    None => info!("Loaded raw zstd dictionary {}", path.display()),
+                }
+                Some(dict)
+            }
+            None => None,
+        };
+
+        let mut dedup = ReplayDedup::new();
+        let mut checkpoint = None;
+        if let Some(path) = &options.state_file {
+            if let Some(state) = checkpoint::load(path)? {
+                dedup.resume(state.block_number, state.record_hashes);
+            }
+            checkpoint = Some(Checkpoint::new(path.clone(), stream_type));
+        }
+
+        Ok(Self {
+            client_filter: options.client_filter.clone(),
+            projection: options.projection.clone(),
+            scratch: Vec::new(),
+            zstd_dict,
+            payload_encoding: options.payload_encoding,
+            dedup,
+            stats: options.stats_interval.map(StatsReporter::new),
+            compression: CompressionStats::new(),
+            rate_line: options.count_only.then(RateLine::new),
+            latency: options.show_latency.then(LatencyHistogram::new),
+            proto_dump: options.proto_dump,
+            flatten: options.flatten,
+            split: options
+                .split_dir
+                .as_deref()
+                .map(BlockFileWriter::create)
+                .transpose()?,
+            checkpoint,
+            sinks,
+            raw_stdout,
+        })
+    }
+
+    pub(crate) async fn handle_data(&mut self, data: &StreamResponse) -> Result<()> {
+        self.write_data(data).await?;
+        if let Some(checkpoint) = &mut self.checkpoint {
+            // Messages dropped before any sink are handled too
+            match self.dedup.current() {
+                Some((block, hashes)) if block == data.block_number => {
+                    checkpoint.record(block, hashes)
+                }
+                _ => checkpoint.record(data.block_number, &HashSet::new()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn write_data(&mut self, data: &StreamResponse) -> Result<()> {
+        // Before decompressing, so it's the time the message was received
+        let latency_ms = self
+            .latency
+            .as_mut()
+            .and_then(|latency| latency.record(data.timestamp));
+        if self.proto_dump {
+            self.dump_data(data);
+            return Ok(());
+        }
+        let wire = data.data.as_bytes();
+        let plain = self.payload_encoding == PayloadEncoding::Plain;
+        if self.payload_encoding == PayloadEncoding::Zstd && !is_zstd(wire) {
+            return Err(format!(
+                "block {}: payload is not zstd-compressed (--require-compressed); is the endpoint configured for compression?",
+                data.block_number
+            )
+            .into());
+        }
+        let payload: &[u8] = if plain {
+            wire
+        } else {
+            decompress_with(wire, &mut self.scratch, self.zstd_dict.as_ref())?;
+            &self.scratch
+        };
+        self.compression
+            .record(!plain && is_zstd(wire), wire.len(), payload.len());
+        if let Some(split) = &mut self.split {
+            split.write(data.block_number, payload).await?;
+        }
+        if let Some(rate_line) = &mut self.rate_line {
+            rate_line.record(data.block_number, wire.len(), payload.len());
+            return Ok(());
+        }
+        if let Some(stdout) = &mut self.raw_stdout {
+            if self.dedup.retain_message(data.block_number, payload) {
+                let text = match plain {
+                    true => Cow::Borrowed(data.data.as_str()),
+                    false => String::from_utf8_lossy(payload),
+                };
+                stdout.write_raw(data.block_number, data.timestamp, latency_ms, &text);
+            } else {
+                info!(
+                    "Skipped a message replayed from block {}",
+                    data.block_number
+                );
+            }
+            return Ok(());
+        }
+
+        // One UTF-8 pass up front is cheaper than from_slice's per-string
+        // checks; a plain payload arrived as a string and needs none
+        let text = match plain {
+            true => Some(data.data.as_str()),
+            false => std::str::from_utf8(payload).ok(),
+        };
+        let parsed = text.and_then(|text| serde_json::from_str::<serde_json::Value>(text).ok());
+        let Some(mut parsed) = parsed else {
+            println!(
+                "Block {}: {}",
+                data.block_number,
+                String::from_utf8_lossy(payload)
+            );
+            return Ok(());
+        };
+
+        let suppressed = self.dedup.suppressed();
+        let any_new = self.dedup.retain_new(data.block_number, &mut parsed);
+        if self.dedup.suppressed() > suppressed {
+            info!(
+                "Skipped {} record(s) replayed from block {}",
+                self.dedup.suppressed() - suppressed,
+                data.block_number
+            );
+        }
+        if !any_new {
+            return Ok(());
+        }
+        if !self.client_filter.is_empty() && self.client_filter.apply(&mut parsed) == 0 {
+            return Ok(());
+        }
+        if let Some(projection) = &self.projection {
+            if projection.apply(&mut parsed) == 0 {
+                return Ok(());
+            }
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.record(data.block_number, &parsed);
+        }
+
+        let payloads = match &parsed {
+            serde_json::Value::Array(items) if self.flatten => items.as_slice(),
+            _ => std::slice::from_ref(&parsed),
+        };
+        for payload in payloads {
+            let record = Record {
+                block_number: data.block_number,
+                timestamp: data.timestamp,
+                latency_ms,
+                payload,
+            };
+            for sink in &mut self.sinks {
+                sink.write(&record).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Print a data message's fields for `--proto-dump`. A payload that
+    /// doesn't decompress or parse is reported on the line rather than
+    /// stopping the stream, since that's what the dump is for.
+    fn dump_data(&mut self, data: &StreamResponse) {
+        let wire = data.data.as_bytes();
+        let compression = if is_zstd(wire) { "zstd" } else { "none" };
+        let decoded = match decompress_with(wire, &mut self.scratch, self.zstd_dict.as_ref()) {
+            Ok(()) => match serde_json::from_slice::<serde_json::Value>(&self.scratch) {
+                Ok(_) => format!("decoded_len={} json=ok", self.scratch.len()),
+                Err(e) => format!(
+                    "decoded_len={} json_error={:?}",
+                    self.scratch.len(),
+                    e.to_string()
+                ),
+            },
+            Err(e) => format!("decompress_error={:?}", e.to_string()),
+        };
+        println!(
+            "Data block_number={} timestamp={} data_len={} compression={} {}",
+            data.block_number,
+            data.timestamp,
+            wire.len(),
+            compression,
+            decoded
+        );
+    }
+
+    /// Flush every sink, even if an earlier one fails; the first error is
+    /// returned.
+    pub(crate) async fn flush(&mut self) -> Result<()> {
+        if let Some(rate_line) = &mut self.rate_line {
+            rate_line.finish();
+        }
+        self.compression.report();
+        if let Some(latency) = &self.latency {
+            latency.report();
+        }
+        if let Some(stdout) = &self.raw_stdout {
+            stdout.finish_raw();
+        }
+        let mut result = match &mut self.split {
+            Some(split) => split.finish().await,
+            None => Ok(()),
+        };
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.flush().await {
+                error!("Failed to flush output: {}", e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        // Only once the sinks have written everything out
+        if let Some(checkpoint) = &mut self.checkpoint {
+            if result.is_ok() {
+                result = checkpoint.save();
+            }
+        }
+        result
+    }
+}
+
+/// Hands data messages to an [`Output`]: directly, or through a [`buffer`]
+/// to a task running the output, so slow sinks don't stall the reader.
+pub(crate) enum Pipeline {
+    Direct(Box<Output>),
+    Buffered {
+        sender: buffer::Sender<(u64, StreamResponse)>,
+        writer: JoinHandle<Result<CompressionStats>>,
+        /// Connections made so far. Each message carries it, so the writer
+        /// sees reconnects in order with the data
+        connection: u64,
+    },
+}
+
+impl Pipeline {
+    pub(crate) fn new(output: Output, options: &StreamOptions) -> Self {
+        let Some(capacity) = options.buffer else {
+            return Self::Direct(Box::new(output));
+        };
+        let (sender, mut receiver) = buffer::channel(capacity, options.on_full);
+        let writer = tokio::spawn(async move {
+            let mut output = output;
+            let mut connection = 0;
+            let mut result = Ok(());
+            while let Some((message_connection, data)) = receiver.recv().await {
+                if message_connection != connection {
+                    output.dedup.reconnected();
+                    connection = message_connection;
+                }
+                if let Err(e) = output.handle_data(&data).await {
+                    result = Err(e);
+                    break;
+                }
+            }
+            // Stop the reader before flushing, which may take a while
+            drop(receiver);
+            let flushed = output.flush().await;
+            result.and(flushed).map(|()| output.compression)
+        });
+        Self::Buffered {
+            sender,
+            writer,
+            connection: 0,
+        }
+    }
+
+    pub(crate) async fn handle_data(&mut self, data: StreamResponse) -> Result<()> {
+        match self {
+            Self::Direct(output) => output.handle_data(&data).await,
+            Self::Buffered {
+                sender, connection, ..
+            } => sender
+                .send((*connection, data))
+                .await
+                // The writer's own error comes from `finish`
+                .map_err(|_| "output writer stopped".into()),
+        }
+    }
+
+    /// Call when the stream is about to resubscribe.
+    pub(crate) fn reconnected(&mut self) {
+        match self {
+            Self::Direct(output) => output.dedup.reconnected(),
+            Self::Buffered { connection, .. } => *connection += 1,
+        }
+    }
+
+    /// Write out everything queued and flush the outputs, returning the
+    /// byte counts of everything decoded.
+    pub(crate) async fn finish(self) -> Result<CompressionStats> {
+        match self {
+            Self::Direct(mut output) => {
+                output.flush().await?;
+                Ok(output.compression)
+            }
+            Self::Buffered { sender, writer, .. } => {
+                sender.report();
+                drop(sender);
+                writer.await?
+            }
+        }
+    }
+}
+
+/// How a single connection ended.
+pub(crate) enum Attempt {
+    /// Stream finished, was interrupted, or hit its sample limit
+    Done,
+    /// Transient failure; reconnect and resume
+    Retry {
+        reason: String,
+        /// The status that ended the stream; `None` when connecting failed
+        code: Option<tonic::Code>,
+        /// Whether anything was received before the failure, which resets
+        /// the retry budget
+        received: bool,
+        /// Base for the backoff before reconnecting
+        base_delay_secs: u64,
+    },
+    /// The server doesn't retain the requested start block
+    StartUnavailable { block: u64, reason: String },
+}
+
+/// How a stream went, returned when it stops cleanly.
+#[derive(Debug, Clone)]
+pub struct StreamSummary {
+    pub stream_type: StreamType,
+    /// Data messages received
+    pub messages: usize,
+    /// Block of the last data message
+    pub last_block: Option<u64>,
+    /// Connections that failed and were retried
+    pub reconnects: usize,
+    /// Gaps in the BLOCKS stream (see [`crate::gaps`])
+    pub gaps: u64,
+    /// Whether it was stopped through the [`CancellationToken`] rather than
+    /// ending on its own (end of stream, `sample` or `to_block`)
+    pub cancelled: bool,
+}
+
+/// Stream state carried across reconnects.
+pub(crate) struct Progress {
+    pub(crate) data_count: usize,
+    first_block: Option<u64>,
+    pub(crate) last_block: Option<u64>,
+    pub(crate) reconnects: usize,
+    /// Each reconnect, kept only for `--report`
+    pub(crate) reconnect_events: Vec<ReconnectEvent>,
+    /// Only for streams that deliver every block
+    gaps: Option<GapDetector>,
+    rate: RateMeter,
+    /// Why `consume` last returned cleanly
+    pub(crate) exit: ExitReason,
+    /// With `tail`, the tip until a message reaches it; earlier blocks are
+    /// backlog
+    pub(crate) tail_from: Option<u64>,
+    backlog: u64,
+}
+
+impl Progress {
+    pub(crate) fn new(gaps: Option<GapDetector>) -> Self {
+        Self {
+            data_count: 0,
+            first_block: None,
+            last_block: None,
+            reconnects: 0,
+            reconnect_events: Vec::new(),
+            gaps,
+            rate: RateMeter::new(),
+            exit: ExitReason::Ended,
+            tail_from: None,
+            backlog: 0,
+        }
+    }
+
+    pub(crate) fn report(&self) {
+        if let Some(gaps) = &self.gaps {
+            gaps.report();
+        }
+    }
+
+    /// Write the `--report` file, if one was asked for.
+    pub(crate) fn write_run_report(
+        &self,
+        stream_type: StreamType,
+        options: &StreamOptions,
+        compression: &CompressionStats,
+        exit: ExitReason,
+    ) -> Result<()> {
+        let Some(path) = &options.report else {
+            return Ok(());
+        };
+        RunReport {
+            stream_type,
+            exit_reason: exit,
+            messages: self.data_count as u64,
+            bytes_in: compression.wire_bytes(),
+            bytes_out: compression.decoded_bytes(),
+            peak_rate: self.rate.peak(),
+            reconnects: self.reconnect_events.clone(),
+            gaps: self.gaps.as_ref().map_or(0, GapDetector::gaps),
+            first_block: self.first_block,
+            last_block: self.last_block,
+            duration: self.rate.elapsed(),
+        }
+        .write(path)?;
+        info!("Wrote run report to {}", path.display());
+        Ok(())
+    }
+
+    pub(crate) fn summary(
+        &self,
+        stream_type: StreamType,
+        cancel: &CancellationToken,
+    ) -> StreamSummary {
+        StreamSummary {
+            stream_type,
+            messages: self.data_count,
+            last_block: self.last_block,
+            reconnects: self.reconnects,
+            gaps: self.gaps.as_ref().map_or(0, GapDetector::gaps),
+            cancelled: cancel.is_cancelled(),
+        }
+    }
+}
+
+/// Stream `stream_type` records, reconnecting on a transient status (see
+/// [`is_retryable`]) or a failed connect. Each reconnect resubscribes from
+/// the last block seen (with the same filters and `filter_name`) so no
+/// blocks are skipped; records in that boundary block that were already
+/// handled are dropped rather than emitted twice. Ctrl-C stops it cleanly.
+pub async fn stream_data(
+    stream_type: StreamType,
+    filters: HashMap<String, Vec<String>>,
+    options: &StreamOptions,
+) -> Result<()> {
+    until_ctrl_c(|cancel| stream_data_until(stream_type, filters, options, cancel))
+        .await
+        .map(|_| ())
+}
+
+/// Run `run` with a token that Ctrl-C cancels.
+pub(crate) async fn until_ctrl_c<F: Future>(run: impl FnOnce(CancellationToken) -> F) -> F::Output {
+    let cancel = CancellationToken::new();
+    let on_ctrl_c = tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+        }
+    });
+    let result = run(cancel).await;
+    on_ctrl_c.abort();
+    result
+}
+
+/// Run the messages of `source` through the same outputs, limits and gap
+/// checks as [`stream_data_until`], without connecting to anything: for
+/// replays, tests and other transports. Filters are only used to describe
+/// the stream (e.g. in the `--ws-port` handshake), not applied. Stops when
+/// the source ends or `cancel` is cancelled.
+pub async fn stream_source(
+    stream_type: StreamType,
+    filters: &HashMap<String, Vec<String>>,
+    mut source: impl MessageSource,
+    options: &StreamOptions,
+    cancel: CancellationToken,
+) -> Result<StreamSummary> {
+    let mut output = Pipeline::new(Output::new(stream_type, filters, options).await?, options);
+    let mut progress = Progress::new(expects_every_block(stream_type).then(GapDetector::new));
+    let result = consume(&mut source, options, &mut progress, &mut output, &cancel).await;
+    progress.report();
+    let compression = result.and(output.finish().await)?;
+    let exit = match cancel.is_cancelled() {
+        true => ExitReason::Interrupted,
+        false => progress.exit,
+    };
+    progress.write_run_report(stream_type, options, &compression, exit)?;
+    Ok(progress.summary(stream_type, &cancel))
+}
+
+/// [`stream_data`], stopping when `cancel` is cancelled instead of on
+/// Ctrl-C. Cancellation takes effect between messages, and while
+/// connecting or waiting to reconnect: the connection and its pings are
+/// closed and every output is flushed before this returns.
+pub async fn stream_data_until(
+    stream_type: StreamType,
+    filters: HashMap<String, Vec<String>>,
+    options: &StreamOptions,
+    cancel: CancellationToken,
+) -> Result<StreamSummary> {
+    // Endpoint and token errors are fatal; only connecting is retried
+    let pool = EndpointPool::new(&options.endpoints(), &options.channel)?;
+    let output = Pipeline::new(Output::new(stream_type, &filters, options).await?, options);
+    let mut options = options.clone();
+
+    if !filters.is_empty() {
+        info!("Filters applied: {:?}", filters);
+    }
+    let filters: HashMap<String, FilterValues> = filters
+        .into_iter()
+        .map(|(field, values)| (field, FilterValues { values }))
+        .collect();
+
+    let tip = start_at_tip(&mut options, &cancel).await?;
+    let from = match tip {
+        Some(block) => format!(" live from block {}", block),
+        None => String::new(),
+    };
+    if options.filter_name.is_empty() {
+        info!("Streaming {}{}...", stream_type.as_str_name(), from);
+    } else {
+        info!(
+            "Streaming {}{} with filter name {:?}...",
+            stream_type.as_str_name(),
+            from,
+            options.filter_name
+        );
+    }
+
+    let mut progress = Progress::new(
+        expects_every_block(stream_type)
+            .then(|| tip.map_or_else(GapDetector::new, GapDetector::starting_at)),
+    );
+    if options.tail {
+        progress.tail_from = tip;
+    }
+    // Boxed, or the futures of callers that await this inline nest too
+    // deep for the compiler to lay out
+    Box::pin(run_connections(
+        pool,
+        stream_type,
+        &filters,
+        options,
+        progress,
+        output,
+        &cancel,
+    ))
+    .await
+}
+
+/// With `warmup` or `tail`, read the live tip and subscribe from it, which
+/// pins down where the stream starts before the first message arrives.
+/// Returns the tip, if it was read.
+pub(crate) async fn start_at_tip(
+    options: &mut StreamOptions,
+    cancel: &CancellationToken,
+) -> Result<Option<u64>> {
+    if options.tail {
+        options.start_block = None;
+    }
+    if !(options.warmup || options.tail) || options.start_block.unwrap_or(0) != 0 {
+        return Ok(None);
+    }
+    let warmed = tokio::select! {
+        warmed = current_tip(options) => warmed,
+        _ = cancel.cancelled() => return Ok(None),
+    };
+    match warmed {
+        Ok(warmed) => {
+            options.start_block = Some(warmed.block);
+            Ok(Some(warmed.block))
+        }
+        Err(e) if options.tail => Err(e),
+        Err(e) => {
+            warn!("Could not read the tip, starting live: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+/// The reconnect loop of [`stream_data_until`]: stream over one connection
+/// at a time, resubscribing from `progress` after each transient failure,
+/// then flush `output`. A stream type handed over by
+/// [`crate::multiplex`] continues here with the state it had.
+pub(crate) async fn run_connections(
+    mut pool: EndpointPool,
+    stream_type: StreamType,
+    filters: &HashMap<String, FilterValues>,
+    mut options: StreamOptions,
+    mut progress: Progress,
+    mut output: Pipeline,
+    cancel: &CancellationToken,
+) -> Result<StreamSummary> {
+    // --clamp-start may move the start block, once
+    let mut clamped = false;
+    let mut budget = RetryBudget::new(options.breaker).with_max_outage(options.max_outage);
+
+    loop {
+        if cancel.is_cancelled() {
+            break;
+        }
+        if budget.attempt() > 1 {
+            info!(
+                "Reconnecting (attempt {}/{})...",
+                budget.attempt(),
+                MAX_RETRIES
+            );
+        }
+
+        // The filter name is resent with every resubscription; naming it on
+        // each connection's logs shows which subscription reconnected
+        let span = info_span!(
+            "connection",
+            stream = stream_type.as_str_name(),
+            retry = budget.attempt() - 1,
+            endpoint = pool.url(),
+            filter_name = tracing::field::Empty
+        );
+        if !options.filter_name.is_empty() {
+            span.record("filter_name", options.filter_name.as_str());
+        }
+        let attempt = stream_once(
+            &pool,
+            stream_type,
+            filters,
+            &options,
+            &mut progress,
+            &mut output,
+            cancel,
+        )
+        .instrument(span)
+        .await;
+
+        match attempt {
+            Ok(Attempt::Done) => break,
+            Ok(Attempt::StartUnavailable { block, reason }) => {
+                info!(
+                    "Start block {} is not available ({}), looking for the earliest retained block...",
+                    block, reason
+                );
+                let earliest = earliest_block(&options, block).await.unwrap_or_else(|e| {
+                    warn!("Could not find the earliest retained block: {}", e);
+                    None
+                });
+                match earliest {
+                    Some(earliest) if options.clamp_start && !clamped => {
+                        warn!(
+                            "Start block {} is not retained, starting from block {} instead",
+                            block, earliest
+                        );
+                        options.start_block = Some(earliest);
+                        clamped = true;
+                    }
+                    _ => {
+                        output.finish().await?;
+                        let earliest = match earliest {
+                            Some(earliest) => format!(
+                                "the earliest available block is {} (rerun with --start-block {} or --clamp-start); ",
+                                earliest, earliest
+                            ),
+                            None => String::new(),
+                        };
+                        return Err(format!(
+                            "start block {} is not available on the server ({}); {}\
+                             older blocks can be fetched from the S3 archive with s3_blocks_backfill",
+                            block, reason, earliest
+                        )
+                        .into());
+                    }
+                }
+            }
+            Ok(Attempt::Retry {
+                reason,
+                code,
+                received,
+                base_delay_secs,
+            }) => {
+                if received {
+                    budget.reset();
+                    pool.succeeded();
+                }
+                output.reconnected();
+                progress.reconnects += 1;
+                pool.failed();
+                let delay = budget.failed(base_delay_secs);
+                let event = ReconnectEvent {
+                    at_ms: chrono::Utc::now().timestamp_millis(),
+                    stream_type,
+                    retry: budget.attempt() - 1,
+                    code,
+                    message: redact(
+                        &reason,
+                        options.endpoints().iter().map(|(_, token)| token.as_str()),
+                    ),
+                    delay: delay.as_ref().ok().copied(),
+                    last_block: progress.last_block,
+                };
+                warn!(
+                    stream = stream_type.as_str_name(),
+                    retry = event.retry,
+                    code = event.code.map(|code| format!("{:?}", code)),
+                    delay_ms = event.delay.map(|delay| delay.as_millis() as u64),
+                    last_block = event.last_block,
+                    "{}",
+                    event.message
+                );
+                if options.report.is_some() {
+                    progress.reconnect_events.push(event);
+                }
+                let delay = match delay {
+                    Ok(delay) => delay,
+                    Err(give_up) => {
+                        pool.report();
+                        progress.report();
+                        let compression = output.finish().await?;
+                        progress.write_run_report(
+                            stream_type,
+                            &options,
+                            &compression,
+                            give_up.into(),
+                        )?;
+                        return Err(give_up.error(&reason).into());
+                    }
+                };
+                info!("Waiting {:.1}s before reconnecting...", delay.as_secs_f64());
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = cancel.cancelled() => info!("Interrupted, shutting down"),
+                }
+            }
+            Err(e) => {
+                output.finish().await?;
+                return Err(e);
+            }
+        }
+    }
+
+    pool.report();
+    progress.report();
+    let compression = output.finish().await?;
+    let exit = match cancel.is_cancelled() {
+        true => ExitReason::Interrupted,
+        false => progress.exit,
+    };
+    progress.write_run_report(stream_type, &options, &compression, exit)?;
+    Ok(progress.summary(stream_type, cancel))
+}
+
+/// Run one connection until it ends, fails, or should be retried.
+async fn stream_once(
+    pool: &EndpointPool,
+    stream_type: StreamType,
+    filters: &HashMap<String, FilterValues>,
+    options: &StreamOptions,
+    progress: &mut Progress,
+    output: &mut Pipeline,
+    cancel: &CancellationToken,
+) -> Result<Attempt> {
+    let connected = tokio::select! {
+        connected = pool.connect() => connected,
+        _ = cancel.cancelled() => {
+            info!("Interrupted, shutting down");
+            return Ok(Attempt::Done);
+        }
+    };
+    let channel = match connected {
+        Ok(channel) => channel,
+        Err(e) => {
+            return Ok(Attempt::Retry {
+                reason: format!("Connection failed: {}", e),
+                code: None,
+                received: false,
+                base_delay_secs: BASE_DELAY_SECS,
+            })
+        }
+    };
+    let mut client = streaming_client(channel, &options.channel);
+
+    // Create request stream
+    let (tx, rx) = mpsc::channel(options.request_capacity.max(1));
+    let stream = ReceiverStream::new(rx);
+
+    // Resume from the last block seen, if any
+    let subscribe = StreamSubscribe {
+        stream_type: stream_type as i32,
+        start_block: progress.last_block.or(options.start_block).unwrap_or(0),
+        filters: filters.clone(),
+        filter_name: options.filter_name.clone(),
+    };
+
+    // Send subscription
+    tx.send(SubscribeRequest {
+        request: Some(hyperliquid::subscribe_request::Request::Subscribe(
+            subscribe,
+        )),
+    })
+    .await?;
+
+    // Keep-alive pings for the lifetime of this connection
+    let _ping = PingTask::spawn(tx.clone());
+
+    // Create request with auth
+    let request = authed_request(stream, pool.token(), pool.auth())?;
+
+    // A requested start block the server no longer retains should fail
+    // loudly rather than leave the stream silently waiting
+    let requested_start = options
+        .start_block
+        .filter(|&block| block > 0 && progress.last_block.is_none());
+
+    // Start streaming
+    let subscribed = tokio::select! {
+        subscribed = client.stream_data(request) => subscribed,
+        _ = cancel.cancelled() => {
+            info!("Interrupted, shutting down");
+            return Ok(Attempt::Done);
+        }
+    };
+    let response_stream = match subscribed {
+        Ok(response) => response.into_inner(),
+        Err(status) => return disconnected(status, false, requested_start),
+    };
+
+    // Only unfiltered streams are guaranteed steady data; a filtered one
+    // can legitimately stay quiet
+    let mut source =
+        GrpcSource::new(response_stream, options.read_timeout).print_pongs(options.proto_dump);
+    if requested_start.is_some() && filters.is_empty() {
+        source = source.expect_data_by(START_BLOCK_TIMEOUT);
+    }
+
+    match consume(&mut source, options, progress, output, cancel).await {
+        Ok(()) => Ok(Attempt::Done),
+        Err(e) => match e.downcast::<Status>() {
+            Ok(status) => disconnected(*status, source.received(), requested_start),
+            Err(e) => match e.downcast_ref::<NoDataYet>() {
+                Some(no_data) => Ok(Attempt::StartUnavailable {
+                    block: requested_start.unwrap_or_default(),
+                    reason: no_data.to_string(),
+                }),
+                None => Err(e),
+            },
+        },
+    }
+}
+
+/// Read `source` into `output` until it ends, `cancel` is cancelled, or
+/// `sample` or `to_block` is reached, counting messages in `progress` and
+/// checking for gaps. The live stream and [`stream_source`] both read
+/// through this.
+async fn consume(
+    source: &mut dyn MessageSource,
+    options: &StreamOptions,
+    progress: &mut Progress,
+    output: &mut Pipeline,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    loop {
+        let data = tokio::select! {
+            data = source.next() => data?,
+            _ = cancel.cancelled() => {
+                info!("Interrupted, shutting down");
+                return Ok(());
+            }
+        };
+        let Some(data) = data else {
+            return Ok(());
+        };
+        if handle_message(data, options, progress, output).await? {
+            return Ok(());
+        }
+    }
+}
+
+/// Count one message of [`consume`] in `progress` and write it to
+/// `output`, unless it's backlog before the `tail` tip or past `to_block`.
+/// Returns whether the stream should stop here.
+pub(crate) async fn handle_message(
+    data: StreamResponse,
+    options: &StreamOptions,
+    progress: &mut Progress,
+    output: &mut Pipeline,
+) -> Result<bool> {
+    if let Some(tip) = progress.tail_from {
+        if data.block_number < tip {
+            progress.backlog += 1;
+            return Ok(false);
+        }
+        info!(
+            "Reached live block {} after skipping {} backlog message(s)",
+            data.block_number, progress.backlog
+        );
+        progress.tail_from = None;
+    }
+    if options.to_block.is_some_and(|to| data.block_number > to) {
+        info!(
+            "Passed block {}, exiting",
+            options.to_block.unwrap_or_default()
+        );
+        progress.exit = ExitReason::ToBlock;
+        return Ok(true);
+    }
+    if let Some(gaps) = &mut progress.gaps {
+        if let Some(missing) = gaps.observe(data.block_number) {
+            if options.on_gap == OnGap::Backfill {
+                gaps.backfilled(backfill_gap(&options.gap_bucket, missing, output).await?);
+            }
+        }
+    }
+    progress.data_count += 1;
+    progress.first_block.get_or_insert(data.block_number);
+    progress.last_block = Some(data.block_number);
+    progress.rate.record();
+    output.handle_data(data).await?;
+
+    if options.sample.is_some_and(|n| progress.data_count >= n) {
+        info!("Sampled {} message(s), exiting", progress.data_count);
+        progress.exit = ExitReason::Sampled;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Fetch the `missing` blocks from `bucket` and write them out, returning
+/// how many were found. Failing to find or open the files is logged rather
+/// than stopping the stream; the archive also lags the chain, so recent
+/// blocks may not be there yet. A download that fails partway through is
+/// returned as an error, since the blocks after it would silently be
+/// missing.
+async fn backfill_gap(
+    bucket: &Bucket,
+    missing: RangeInclusive<u64>,
+    output: &mut Pipeline,
+) -> Result<u64> {
+    let (first, last) = (*missing.start(), *missing.end());
+    info!("Backfilling block(s) {}-{} from {}...", first, last, bucket);
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let client = aws_sdk_s3::Client::new(&config);
+
+    let files = match find_block_ranges(&client, bucket, first, last).await {
+        Ok(files) => files,
+        Err(e) => {
+            warn!("Could not backfill block(s) {}-{}: {}", first, last, e);
+            return Ok(0);
+        }
+    };
+    let mut count = 0;
+    for file in &files {
+        let mut blocks = match stream_blocks(&client, bucket, file, None, None).await {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                warn!("Could not read {}: {}", file.s3_key, e);
+                continue;
+            }
+        };
+        while let Some(block) = blocks.next().await? {
+            if block.block_number > last {
+                break;
+            }
+            if block.block_number < first {
+                continue;
+            }
+            output
+                .handle_data(StreamResponse {
+                    block_number: block.block_number,
+                    timestamp: block.timestamp_ms(),
+                    data: block.data.to_string(),
+                })
+                .await?;
+            count += 1;
+        }
+    }
+
+    let wanted = last - first + 1;
+    if count < wanted {
+        warn!(
+            "{} of {} missing block(s) are not in {} (the archive lags the chain)",
+            wanted - count,
+            wanted,
+            bucket
+        );
+    } else {
+        info!("Backfilled {} block(s)", count);
+    }
+    Ok(count)
+}
+
+/// Transient statuses (see [`is_retryable`]) are retried; any other status
+/// is fatal, except a rejected `start_block`, which is reported separately.
+pub(crate) fn disconnected(
+    status: Status,
+    received: bool,
+    requested_start: Option<u64>,
+) -> Result<Attempt> {
+    use tonic::Code;
+
+    match (status.code(), requested_start) {
+        _ if is_retryable(&status) => Ok(Attempt::Retry {
+            reason: match status.code() {
+                Code::DataLoss => format!("Server reinitialized: {}", status.message()),
+                code => format!("Stream interrupted ({:?}): {}", code, status.message()),
+            },
+            code: Some(status.code()),
+            received,
+            base_delay_secs: retry_base_delay(&status),
+        }),
+        (
+            Code::OutOfRange | Code::NotFound | Code::InvalidArgument | Code::FailedPrecondition,
+            Some(block),
+        ) if !received && !is_message_too_large(&status) => Ok(Attempt::StartUnavailable {
+            block,
+            reason: status.message().to_string(),
+        }),
+        _ => {
+            let error = CliError::from_status(&status);
+            error!("gRPC error: {}", error);
+            Err(error.into())
+        }
+    }
+}
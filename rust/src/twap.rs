@@ -0,0 +1,170 @@
+//! Progress of TWAP orders from the TWAP stream.
+//!
+//! The stream resends a TWAP's whole state each time it changes. The
+//! tracker keeps the last state of every active TWAP and reports only what
+//! moved: a TWAP seen for the first time, more size executed, or its end
+//! (finished, terminated or failed), after which it's forgotten. A TWAP
+//! that was already running when the stream started is reported from the
+//! first state seen.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use tracing::info;
+
+use crate::records::split_records;
+use crate::sink::{Record, RecordSink};
+use crate::typed::Twap;
+use crate::Result;
+
+pub type TwapId = u64;
+
+/// What's remembered of an active TWAP.
+#[derive(Debug, Clone)]
+pub struct TwapState {
+    pub coin: String,
+    pub executed: Decimal,
+}
+
+/// Active TWAPs by id.
+#[derive(Debug, Default)]
+pub struct TwapTracker {
+    active: HashMap<TwapId, TwapState>,
+}
+
+impl TwapTracker {
+    /// Record `twap`'s latest state, returning a line describing the change,
+    /// or `None` if nothing worth reporting changed.
+    pub fn observe(&mut self, twap: &Twap) -> Option<String> {
+        let previous = if twap.is_done() {
+            self.active.remove(&twap.id)
+        } else {
+            self.active.insert(
+                twap.id,
+                TwapState {
+                    coin: twap.coin.clone(),
+                    executed: twap.executed,
+                },
+            )
+        };
+
+        if twap.is_done() {
+            return Some(format!(
+                "TWAP {} {}: {} {}, {}{}",
+                twap.id,
+                twap.status,
+                side_name(&twap.side),
+                twap.coin,
+                progress(twap),
+                average_px(twap)
+            ));
+        }
+        match previous {
+            None => Some(format!(
+                "TWAP {} active: {} {} {}{}, {}{}, user {}",
+                twap.id,
+                side_name(&twap.side),
+                twap.sz.normalize(),
+                twap.coin,
+                twap.minutes
+                    .map(|m| format!(" over {} min", m))
+                    .unwrap_or_default(),
+                progress(twap),
+                average_px(twap),
+                twap.user
+            )),
+            Some(state) if state.executed != twap.executed => Some(format!(
+                "TWAP {} {}: +{}, {}, {} remaining{}",
+                twap.id,
+                twap.coin,
+                (twap.executed - state.executed).normalize(),
+                progress(twap),
+                twap.size_remaining().normalize(),
+                average_px(twap)
+            )),
+            Some(_) => None,
+        }
+    }
+
+    /// TWAPs seen and not yet ended.
+    pub fn active(&self) -> &HashMap<TwapId, TwapState> {
+        &self.active
+    }
+}
+
+fn side_name(side: &str) -> &str {
+    match side {
+        "B" => "buy",
+        "A" => "sell",
+        other => other,
+    }
+}
+
+/// `executed/sz executed (pct%)`
+fn progress(twap: &Twap) -> String {
+    let pct = if twap.sz.is_zero() {
+        Decimal::ZERO
+    } else {
+        (twap.executed * Decimal::ONE_HUNDRED / twap.sz).round_dp(1)
+    };
+    format!(
+        "{}/{} executed ({}%)",
+        twap.executed.normalize(),
+        twap.sz.normalize(),
+        pct.normalize()
+    )
+}
+
+fn average_px(twap: &Twap) -> String {
+    twap.average_px()
+        .map(|px| format!(", avg px {}", px))
+        .unwrap_or_default()
+}
+
+/// Prints TWAP progress instead of the raw records. Records that don't
+/// parse as a TWAP are printed as JSON.
+#[derive(Default)]
+pub struct TwapSink {
+    tracker: TwapTracker,
+}
+
+impl TwapSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RecordSink for TwapSink {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        for item in split_records(record.payload) {
+            match Twap::from_record(item) {
+                Some(twap) => {
+                    if let Some(line) = self.tracker.observe(&twap) {
+                        println!("Block {} | {}", record.block_number, line);
+                    }
+                }
+                None => println!("Block {} | {}", record.block_number, item),
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let active = self.tracker.active();
+        if !active.is_empty() {
+            let mut ids: Vec<_> = active.keys().collect();
+            ids.sort();
+            info!(
+                "{} TWAP(s) still active: {}",
+                ids.len(),
+                ids.iter()
+                    .map(|id| format!("{} ({})", id, active[id].coin))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(())
+    }
+}
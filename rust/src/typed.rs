@@ -0,0 +1,394 @@
+//! Typed one-line formatting for WRITER_ACTIONS, EVENTS, ORDERS and TWAP
+//! records.
+//!
+//! Only the fields worth scanning are pulled out; records without a
+//! recognisable `type` return `None` so callers can fall back to printing
+//! the raw JSON.
+
+use std::fmt;
+
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+
+use crate::price::parse_value;
+use crate::records::{field_str, value_str};
+
+/// A WRITER_ACTIONS record. The action type is read from `type`, or from
+/// `action.type` when the action is nested.
+#[derive(Debug, Clone)]
+pub struct WriterAction {
+    pub kind: String,
+    pub user: Option<String>,
+    pub nonce: Option<String>,
+    pub hash: Option<String>,
+}
+
+impl WriterAction {
+    pub fn from_record(record: &Value) -> Option<Self> {
+        let kind = field_str(record, "type")
+            .or_else(|| record.get("action").and_then(|a| field_str(a, "type")))?;
+        Some(Self {
+            kind,
+            user: field_str(record, "user"),
+            nonce: field_str(record, "nonce"),
+            hash: field_str(record, "hash").or_else(|| field_str(record, "evm_tx_hash")),
+        })
+    }
+}
+
+impl fmt::Display for WriterAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WriterAction {}", self.kind)?;
+        write_opt(f, "user", &self.user)?;
+        write_opt(f, "nonce", &self.nonce)?;
+        write_opt(f, "hash", &self.hash)
+    }
+}
+
+/// An EVENTS record.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub kind: String,
+    pub user: Option<String>,
+    pub coin: Option<String>,
+    pub time: Option<String>,
+    pub hash: Option<String>,
+}
+
+impl Event {
+    pub fn from_record(record: &Value) -> Option<Self> {
+        Some(Self {
+            kind: field_str(record, "type")?,
+            user: field_str(record, "user"),
+            coin: field_str(record, "coin"),
+            time: field_str(record, "time"),
+            hash: field_str(record, "hash"),
+        })
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Event {}", self.kind)?;
+        write_opt(f, "coin", &self.coin)?;
+        write_opt(f, "user", &self.user)?;
+        write_opt(f, "time", &self.time)?;
+        write_opt(f, "hash", &self.hash)
+    }
+}
+
+/// Where an order is in its lifecycle, from the ORDERS `status` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+pub enum OrderLifecycle {
+    /// Resting with nothing filled
+    Open,
+    /// Resting with part of its size filled
+    PartiallyFilled,
+    Filled,
+    /// Cancelled by the user or by the exchange (`canceled`,
+    /// `marginCanceled`, `scheduledCancel`, ...)
+    Cancelled,
+    /// Anything else, such as `triggered` or the `*Rejected` statuses
+    Other,
+}
+
+impl OrderLifecycle {
+    /// Classify `status`. An open order is partially filled when its
+    /// remaining `sz` is below its `orig_sz`.
+    pub fn classify(status: &str, sz: &Value, orig_sz: Option<&Value>) -> Self {
+        let lower = status.to_ascii_lowercase();
+        match lower.as_str() {
+            "open" => {
+                let filled = orig_sz
+                    .and_then(parse_value)
+                    .zip(parse_value(sz))
+                    .is_some_and(|(orig, remaining)| remaining < orig);
+                if filled {
+                    Self::PartiallyFilled
+                } else {
+                    Self::Open
+                }
+            }
+            "filled" => Self::Filled,
+            "scheduledcancel" => Self::Cancelled,
+            s if s.ends_with("canceled") || s.ends_with("cancelled") => Self::Cancelled,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for OrderLifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Open => "open",
+            Self::PartiallyFilled => "partially-filled",
+            Self::Filled => "filled",
+            Self::Cancelled => "cancelled",
+            Self::Other => "other",
+        })
+    }
+}
+
+/// An ORDERS record: `status`, `time` and `user` at the top level and the
+/// order itself under `order`.
+#[derive(Debug, Clone)]
+pub struct OrderEvent {
+    pub oid: u64,
+    pub coin: String,
+    pub side: String,
+    pub limit_px: String,
+    /// Remaining size
+    pub sz: String,
+    pub orig_sz: Option<String>,
+    /// `status` as sent
+    pub status: String,
+    pub lifecycle: OrderLifecycle,
+    /// The record's `time`, or the order's `timestamp` without one
+    pub time: Option<u64>,
+}
+
+impl OrderEvent {
+    pub fn from_record(record: &Value) -> Option<Self> {
+        let order = record.get("order")?;
+        let status = record.get("status")?.as_str()?;
+        let sz = order.get("sz")?;
+        let orig_sz = order.get("origSz");
+        Some(Self {
+            oid: order.get("oid")?.as_u64()?,
+            coin: order.get("coin")?.as_str()?.to_string(),
+            side: order.get("side")?.as_str()?.to_string(),
+            limit_px: field_str(order, "limitPx")?,
+            sz: value_str(sz)?,
+            orig_sz: orig_sz.and_then(value_str),
+            status: status.to_string(),
+            lifecycle: OrderLifecycle::classify(status, sz, orig_sz),
+            time: record
+                .get("time")
+                .or_else(|| order.get("timestamp"))
+                .and_then(Value::as_u64),
+        })
+    }
+}
+
+impl fmt::Display for OrderEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Order {}", self.lifecycle)?;
+        // Several statuses map to these; name the one sent
+        let grouped = matches!(
+            self.lifecycle,
+            OrderLifecycle::Cancelled | OrderLifecycle::Other
+        );
+        if grouped && self.status != "canceled" {
+            write!(f, " ({})", self.status)?;
+        }
+        write!(
+            f,
+            " coin={} side={} px={} sz={}",
+            self.coin, self.side, self.limit_px, self.sz
+        )?;
+        if self.lifecycle == OrderLifecycle::PartiallyFilled {
+            write_opt(f, "orig_sz", &self.orig_sz)?;
+        }
+        write!(f, " oid={}", self.oid)?;
+        write_opt(f, "time", &self.time.map(|t| t.to_string()))
+    }
+}
+
+/// A TWAP record: the order's `state` (size, amount executed so far, ...)
+/// and its `status` (`activated`, `finished`, `terminated` or an error),
+/// keyed by `twap_id`.
+#[derive(Debug, Clone)]
+pub struct Twap {
+    pub id: u64,
+    pub user: String,
+    pub coin: String,
+    pub side: String,
+    /// Total size
+    pub sz: Decimal,
+    /// Size executed so far
+    pub executed: Decimal,
+    /// Notional executed so far
+    pub executed_ntl: Option<Decimal>,
+    pub minutes: Option<u64>,
+    pub status: String,
+}
+
+impl Twap {
+    /// Also accepts `twapId` and a `{"status": ...}` object for `status`,
+    /// as the info API returns them.
+    pub fn from_record(record: &Value) -> Option<Self> {
+        let id = record
+            .get("twap_id")
+            .or_else(|| record.get("twapId"))?
+            .as_u64()?;
+        let state = record.get("state").unwrap_or(record);
+        let status = match record.get("status")? {
+            Value::String(status) => status.clone(),
+            Value::Object(status) if status.contains_key("error") => "error".to_string(),
+            Value::Object(status) => status.get("status")?.as_str()?.to_string(),
+            _ => return None,
+        };
+        Some(Self {
+            id,
+            user: field_str(state, "user")?,
+            coin: field_str(state, "coin")?,
+            side: field_str(state, "side")?,
+            sz: parse_value(state.get("sz")?)?,
+            executed: parse_value(state.get("executedSz")?)?,
+            executed_ntl: state.get("executedNtl").and_then(parse_value),
+            minutes: state.get("minutes").and_then(Value::as_u64),
+            status,
+        })
+    }
+
+    /// Size left to execute.
+    pub fn size_remaining(&self) -> Decimal {
+        (self.sz - self.executed).max(Decimal::ZERO)
+    }
+
+    /// Whether the TWAP has stopped for good.
+    pub fn is_done(&self) -> bool {
+        self.status != "activated"
+    }
+
+    /// Average execution price so far, if anything has executed.
+    pub fn average_px(&self) -> Option<Decimal> {
+        let ntl = self.executed_ntl?;
+        (!self.executed.is_zero()).then(|| (ntl / self.executed).round_dp(6).normalize())
+    }
+}
+
+impl fmt::Display for Twap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Twap {} {} coin={} side={} executed={}/{} remaining={}",
+            self.id,
+            self.status,
+            self.coin,
+            self.side,
+            self.executed.normalize(),
+            self.sz.normalize(),
+            self.size_remaining().normalize()
+        )?;
+        write_opt(f, "avg_px", &self.average_px().map(|px| px.to_string()))?;
+        write!(f, " user={}", self.user)
+    }
+}
+
+fn write_opt(f: &mut fmt::Formatter<'_>, name: &str, value: &Option<String>) -> fmt::Result {
+    match value {
+        Some(value) => write!(f, " {}={}", name, value),
+        None => Ok(()),
+    }
+}
+
+/// Keep only `fields` of `record`, each either a top-level key or a dotted
+/// path (`action.type`). Missing fields are omitted.
+pub fn project(record: &Value, fields: &[String]) -> Value {
+    let mut projected = Map::new();
+    for field in fields {
+        let value = field
+            .split('.')
+            .try_fold(record, |value, key| value.get(key));
+        if let Some(value) = value {
+            projected.insert(field.clone(), value.clone());
+        }
+    }
+    Value::Object(projected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn order(status: &str, sz: &str, orig_sz: &str) -> Value {
+        json!({
+            "time": 1_700_000_000_123u64,
+            "user": "0x1234",
+            "status": status,
+            "order": {
+                "coin": "BTC", "side": "B", "limitPx": "97000.5", "sz": sz,
+                "oid": 42, "timestamp": 1_700_000_000_000u64, "origSz": orig_sz,
+            },
+        })
+    }
+
+    fn lifecycle(status: &str, sz: &str, orig_sz: &str) -> OrderLifecycle {
+        OrderEvent::from_record(&order(status, sz, orig_sz))
+            .unwrap()
+            .lifecycle
+    }
+
+    #[test]
+    fn order_statuses_are_classified() {
+        assert_eq!(lifecycle("open", "1.0", "1.0"), OrderLifecycle::Open);
+        assert_eq!(
+            lifecycle("open", "0.4", "1.0"),
+            OrderLifecycle::PartiallyFilled
+        );
+        assert_eq!(lifecycle("filled", "0.0", "1.0"), OrderLifecycle::Filled);
+        for status in [
+            "canceled",
+            "marginCanceled",
+            "scheduledCancel",
+            "selfTradeCanceled",
+        ] {
+            assert_eq!(
+                lifecycle(status, "1.0", "1.0"),
+                OrderLifecycle::Cancelled,
+                "{status}"
+            );
+        }
+        for status in ["triggered", "perpMarginRejected"] {
+            assert_eq!(lifecycle(status, "1.0", "1.0"), OrderLifecycle::Other);
+        }
+    }
+
+    #[test]
+    fn an_order_record_parses_into_an_event() {
+        let event = OrderEvent::from_record(&order("open", "0.4", "1.0")).unwrap();
+
+        assert_eq!(event.oid, 42);
+        assert_eq!(event.coin, "BTC");
+        assert_eq!(event.side, "B");
+        assert_eq!(event.limit_px, "97000.5");
+        assert_eq!(event.sz, "0.4");
+        assert_eq!(event.orig_sz.as_deref(), Some("1.0"));
+        assert_eq!(event.time, Some(1_700_000_000_123));
+        assert_eq!(
+            event.to_string(),
+            "Order partially-filled coin=BTC side=B px=97000.5 sz=0.4 orig_sz=1.0 oid=42 time=1700000000123"
+        );
+    }
+
+    #[test]
+    fn grouped_statuses_name_the_one_sent() {
+        let event = OrderEvent::from_record(&order("marginCanceled", "1.0", "1.0")).unwrap();
+
+        assert!(event
+            .to_string()
+            .starts_with("Order cancelled (marginCanceled) coin=BTC"));
+    }
+
+    #[test]
+    fn the_order_timestamp_stands_in_for_a_missing_time() {
+        let mut record = order("filled", "0.0", "1.0");
+        record.as_object_mut().unwrap().remove("time");
+
+        let event = OrderEvent::from_record(&record).unwrap();
+        assert_eq!(event.time, Some(1_700_000_000_000));
+    }
+
+    #[test]
+    fn records_that_dont_match_fall_back() {
+        let mut no_oid = order("open", "1.0", "1.0");
+        no_oid["order"].as_object_mut().unwrap().remove("oid");
+
+        assert!(OrderEvent::from_record(&no_oid).is_none());
+        assert!(OrderEvent::from_record(&json!({ "status": "open" })).is_none());
+        assert!(OrderEvent::from_record(&json!([1, 2])).is_none());
+    }
+}
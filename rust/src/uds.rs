@@ -0,0 +1,137 @@
+//! Unix domain socket forwarding of decoded stream records.
+//!
+//! For sidecars on the same host: one privileged process holds the
+//! QuickNode token and streams, and any number of local consumers read
+//! NDJSON from the socket, without the TLS and HTTP framing of the
+//! WebSocket rebroadcast ([`crate::ws`]). Each line is the same
+//! `{"block_number", "timestamp", "data"}` envelope the WebSocket sends, so
+//! a capture can be fed back through `--replay`. Consumers that fall too far
+//! behind are disconnected instead of slowing down the gRPC read loop.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::error::CliError;
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+/// Lines buffered per consumer before it counts as a slow consumer
+const CLIENT_BUFFER: usize = 4096;
+
+/// Handle for publishing to all connected socket consumers.
+pub struct UdsBroadcaster {
+    path: PathBuf,
+    tx: broadcast::Sender<Arc<str>>,
+    accept: JoinHandle<()>,
+}
+
+impl UdsBroadcaster {
+    /// Listen on a socket at `path`. A stale socket file left by an earlier
+    /// run is replaced; one another process is still listening on is not.
+    pub async fn bind(path: &Path) -> Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(CliError::BadArgs(format!(
+                    "{} is in use by another process",
+                    path.display()
+                ))
+                .into());
+            }
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|e| CliError::BadArgs(format!("can't listen on {}: {}", path.display(), e)))?;
+        info!("Forwarding records to Unix socket {}", path.display());
+
+        let (tx, _) = broadcast::channel(CLIENT_BUFFER);
+        let accept_tx = tx.clone();
+        let accept = tokio::spawn(async move {
+            let mut next_id = 0u64;
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        next_id += 1;
+                        tokio::spawn(serve_client(stream, next_id, accept_tx.subscribe()));
+                    }
+                    Err(e) => warn!("Unix socket accept failed: {}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            tx,
+            accept,
+        })
+    }
+
+    /// Publish one NDJSON `line` to every connected consumer. Never blocks.
+    pub fn send(&self, mut line: String) {
+        line.push('\n');
+        // No receivers just means no consumers are connected
+        let _ = self.tx.send(line.into());
+    }
+}
+
+/// Sends each message as a `{"block_number", "timestamp", "data"}` line.
+#[async_trait]
+impl RecordSink for UdsBroadcaster {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        self.send(
+            serde_json::json!({
+                "block_number": record.block_number,
+                "timestamp": record.timestamp,
+                "data": record.payload,
+            })
+            .to_string(),
+        );
+        Ok(())
+    }
+
+    /// Stop accepting consumers and remove the socket file. Connected
+    /// consumers get what's queued for them, then end of file.
+    async fn flush(&mut self) -> Result<()> {
+        self.accept.abort();
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+        Ok(())
+    }
+}
+
+async fn serve_client(stream: UnixStream, id: u64, mut rx: broadcast::Receiver<Arc<str>>) {
+    info!("Unix socket consumer {} connected", id);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        match rx.recv().await {
+            Ok(line) => {
+                if writer.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+                // Batch writes while lines are queued; flush once caught up
+                if rx.is_empty() && writer.flush().await.is_err() {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    "Dropping slow Unix socket consumer {} ({} messages behind)",
+                    id, skipped
+                );
+                break;
+            }
+            Err(RecvError::Closed) => {
+                let _ = writer.flush().await;
+                break;
+            }
+        }
+    }
+    info!("Unix socket consumer {} disconnected", id);
+}
@@ -0,0 +1,222 @@
+//! Cross-check blocks delivered over gRPC against the S3 archive.
+//!
+//! A BLOCKS message and the archive's line for the same block should carry
+//! the same JSON. [`fetch_grpc_blocks`] subscribes from the first block of a
+//! range and collects messages until it has passed the last;
+//! [`compare_blocks`] then compares each block with its S3 copy as parsed
+//! JSON, so key order and whitespace don't count, and lists the paths where
+//! they differ.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{info, warn};
+
+use crate::common::{
+    authed_request, build_endpoint, decompress_with, streaming_client, validate_token,
+};
+use crate::error::CliError;
+use crate::health::describe_connect_error;
+use crate::hyperliquid::{self, StreamSubscribe, StreamType, SubscribeRequest};
+use crate::source::Received;
+use crate::stream::StreamOptions;
+use crate::Result;
+
+/// Longest value shown in a difference before it's cut short
+const MAX_SHOWN_LEN: usize = 80;
+
+/// Subscribe to BLOCKS from `start_block` and collect each block's payload
+/// up to `end_block`. One connection, no reconnects: a verification run
+/// that loses its stream is simply run again.
+pub async fn fetch_grpc_blocks(
+    options: &StreamOptions,
+    start_block: u64,
+    end_block: u64,
+) -> Result<BTreeMap<u64, Value>> {
+    validate_token(&options.token, options.channel.auth)?;
+    let channel = build_endpoint(&options.endpoint, &options.channel)?
+        .connect()
+        .await
+        .map_err(|e| CliError::ConnectFailed(describe_connect_error(&e)))?;
+    let mut client = streaming_client(channel, &options.channel);
+
+    // Held until the range is read; dropping it would end the subscription
+    let (tx, rx) = mpsc::channel(1);
+    tx.send(SubscribeRequest {
+        request: Some(hyperliquid::subscribe_request::Request::Subscribe(
+            StreamSubscribe {
+                stream_type: StreamType::Blocks as i32,
+                start_block,
+                filters: Default::default(),
+                filter_name: options.filter_name.clone(),
+            },
+        )),
+    })
+    .await?;
+    let request = authed_request(
+        ReceiverStream::new(rx),
+        &options.token,
+        options.channel.auth,
+    )?;
+    let mut stream = client.stream_data(request).await?.into_inner();
+    info!(
+        "Reading blocks {} to {} over gRPC from {}",
+        start_block, end_block, options.endpoint
+    );
+
+    let mut blocks = BTreeMap::new();
+    let mut scratch = Vec::new();
+    loop {
+        let message = tokio::time::timeout(options.read_timeout, stream.message())
+            .await
+            .map_err(|_| {
+                CliError::StreamEnded(format!(
+                    "no message for {}s",
+                    options.read_timeout.as_secs()
+                ))
+            })??;
+        let Some(update) = message else {
+            return Err(CliError::StreamEnded(format!(
+                "stream closed after {} of blocks {} to {}",
+                blocks.len(),
+                start_block,
+                end_block
+            ))
+            .into());
+        };
+        let Received::Data(data) = Received::from_update(update) else {
+            continue;
+        };
+        if data.block_number > end_block {
+            break;
+        }
+        if data.block_number < start_block {
+            continue;
+        }
+        decompress_with(data.data.as_bytes(), &mut scratch, None)?;
+        let payload = serde_json::from_slice(&scratch).unwrap_or_else(|e| {
+            warn!(
+                "Block {} over gRPC is not valid JSON: {}",
+                data.block_number, e
+            );
+            Value::String(String::from_utf8_lossy(&scratch).into_owned())
+        });
+        blocks.insert(data.block_number, payload);
+        if data.block_number == end_block {
+            break;
+        }
+    }
+    drop(tx);
+    Ok(blocks)
+}
+
+/// One block whose two copies differ.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub block_number: u64,
+    /// Where they differ, as `path: gRPC <value> vs S3 <value>`, up to the
+    /// limit given to [`compare_blocks`]
+    pub differences: Vec<String>,
+}
+
+/// Outcome of comparing a range of blocks from both sources.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Blocks present in both
+    pub compared: u64,
+    pub mismatches: Vec<Mismatch>,
+    /// Blocks only one side had
+    pub grpc_only: Vec<u64>,
+    pub s3_only: Vec<u64>,
+}
+
+/// Compare the blocks in `grpc` and `s3` by block number, listing up to
+/// `max_differences` differing paths per mismatched block.
+pub fn compare_blocks(
+    grpc: &BTreeMap<u64, Value>,
+    s3: &BTreeMap<u64, Value>,
+    max_differences: usize,
+) -> VerifyReport {
+    let mut report = VerifyReport::default();
+    for (&block_number, grpc_block) in grpc {
+        let Some(s3_block) = s3.get(&block_number) else {
+            report.grpc_only.push(block_number);
+            continue;
+        };
+        report.compared += 1;
+        if grpc_block != s3_block {
+            let mut differences = Vec::new();
+            diff_values(
+                "",
+                Some(grpc_block),
+                Some(s3_block),
+                max_differences,
+                &mut differences,
+            );
+            report.mismatches.push(Mismatch {
+                block_number,
+                differences,
+            });
+        }
+    }
+    report.s3_only = s3
+        .keys()
+        .filter(|block_number| !grpc.contains_key(block_number))
+        .copied()
+        .collect();
+    report
+}
+
+/// Append the paths (JSON pointers) at which `grpc` and `s3` differ to
+/// `out`, until it holds `max`. Objects are compared key by key in sorted
+/// order and arrays element by element.
+fn diff_values(
+    path: &str,
+    grpc: Option<&Value>,
+    s3: Option<&Value>,
+    max: usize,
+    out: &mut Vec<String>,
+) {
+    if out.len() >= max || grpc == s3 {
+        return;
+    }
+    match (grpc, s3) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                diff_values(&path, a.get(key), b.get(key), max, out);
+            }
+        }
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            for i in 0..a.len().max(b.len()) {
+                diff_values(&format!("{}/{}", path, i), a.get(i), b.get(i), max, out);
+            }
+        }
+        _ => {
+            let path = if path.is_empty() { "/" } else { path };
+            out.push(format!(
+                "{}: gRPC {} vs S3 {}",
+                path,
+                shown(grpc),
+                shown(s3)
+            ));
+        }
+    }
+}
+
+/// A value for a difference line, cut short if it's long.
+fn shown(value: Option<&Value>) -> String {
+    let Some(value) = value else {
+        return "(missing)".to_string();
+    };
+    let text = value.to_string();
+    match text.char_indices().nth(MAX_SHOWN_LEN) {
+        Some((cut, _)) => format!("{}...", &text[..cut]),
+        None => text,
+    }
+}
@@ -0,0 +1,130 @@
+//! WebSocket rebroadcast of decoded stream records.
+//!
+//! Lets browser dashboards subscribe to the decoded stream without ever
+//! seeing the QuickNode token. Every client first receives a handshake
+//! describing the upstream subscription, then one JSON text message per
+//! data message. Clients that fall too far behind are disconnected instead
+//! of slowing down the gRPC read loop.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+
+use crate::sink::{Record, RecordSink};
+use crate::Result;
+
+/// Messages buffered per client before it counts as a slow consumer
+const CLIENT_BUFFER: usize = 1024;
+
+/// Handle for publishing to all connected WebSocket clients.
+pub struct WsBroadcaster {
+    tx: broadcast::Sender<Arc<str>>,
+}
+
+impl WsBroadcaster {
+    /// Listen on `0.0.0.0:port` and greet each client with `handshake`.
+    pub async fn bind(port: u16, handshake: Value) -> Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+        info!("WebSocket rebroadcast listening on ws://0.0.0.0:{}", port);
+
+        let (tx, _) = broadcast::channel(CLIENT_BUFFER);
+        let handshake: Arc<str> = handshake.to_string().into();
+
+        let accept_tx = tx.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, addr)) => {
+                        let rx = accept_tx.subscribe();
+                        tokio::spawn(serve_client(stream, addr, handshake.clone(), rx));
+                    }
+                    Err(e) => warn!("WebSocket accept failed: {}", e),
+                }
+            }
+        });
+
+        Ok(Self { tx })
+    }
+
+    /// Publish `message` to every connected client. Never blocks.
+    pub fn send(&self, message: String) {
+        // No receivers just means no clients are connected
+        let _ = self.tx.send(message.into());
+    }
+}
+
+/// Sends each message as a `{"block_number", "timestamp", "data"}` envelope.
+#[async_trait]
+impl RecordSink for WsBroadcaster {
+    async fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        self.send(
+            serde_json::json!({
+                "block_number": record.block_number,
+                "timestamp": record.timestamp,
+                "data": record.payload,
+            })
+            .to_string(),
+        );
+        Ok(())
+    }
+}
+
+async fn serve_client(
+    stream: TcpStream,
+    addr: SocketAddr,
+    handshake: Arc<str>,
+    mut rx: broadcast::Receiver<Arc<str>>,
+) {
+    let ws = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            warn!("WebSocket handshake with {} failed: {}", addr, e);
+            return;
+        }
+    };
+    info!("WebSocket client connected: {}", addr);
+
+    let (mut sink, mut incoming) = ws.split();
+    if sink
+        .send(Message::Text(handshake.to_string()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            message = rx.recv() => match message {
+                Ok(message) => {
+                    if sink.send(Message::Text(message.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Warning: dropping slow WebSocket client {} ({} messages behind)",
+                        addr, skipped
+                    );
+                    let _ = sink.send(Message::Close(None)).await;
+                    break;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // Clients only send control frames; stop when they go away
+            frame = incoming.next() => match frame {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    info!("WebSocket client disconnected: {}", addr);
+}
@@ -0,0 +1,287 @@
+//! The streaming clients against a scripted in-process `Streaming` server,
+//! connected over an in-memory pipe instead of TCP.
+//!
+//! Each connection the server accepts plays the next script: data messages,
+//! pongs, then either a failing status or the end of the stream. Every
+//! subscription's start block is recorded, which is how the tests check
+//! that a reconnect resumes from the last block seen.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+
+use hyperliquid_grpc::common::{authed_request, build_channel, streaming_client, Connector};
+use hyperliquid_grpc::hyperliquid::streaming_server::{Streaming, StreamingServer};
+use hyperliquid_grpc::hyperliquid::{
+    subscribe_request, subscribe_update, PingRequest, PingResponse, Pong, StreamResponse,
+    StreamSubscribe, StreamType, SubscribeRequest, SubscribeUpdate,
+};
+use hyperliquid_grpc::reconnect::{with_reconnect, Flow, Resubscribe, DEFAULT_READ_TIMEOUT};
+use hyperliquid_grpc::source::Received;
+use hyperliquid_grpc::stream::{stream_data_until, StreamOptions};
+use hyperliquid_grpc::Result;
+
+use Step::{Data, Fail, Pong as SendPong};
+
+const ENDPOINT: &str = "http://mock.test";
+
+enum Step {
+    Data(u64),
+    Pong,
+    Fail(Code),
+}
+
+#[derive(Clone, Default)]
+struct Mock {
+    /// One script per connection, in order; a connection past the last
+    /// script ends at once
+    scripts: Arc<Mutex<VecDeque<Vec<Step>>>>,
+    /// Start block of every subscription received
+    subscriptions: Arc<Mutex<Vec<u64>>>,
+}
+
+impl Mock {
+    fn new(scripts: Vec<Vec<Step>>) -> Self {
+        Self {
+            scripts: Arc::new(Mutex::new(scripts.into())),
+            ..Self::default()
+        }
+    }
+
+    fn subscriptions(&self) -> Vec<u64> {
+        self.subscriptions.lock().clone()
+    }
+
+    /// Serve this mock in the background, returning a connector that opens
+    /// a new in-memory connection to it each time.
+    fn serve(&self) -> Connector {
+        let (connections, incoming) = mpsc::unbounded_channel();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StreamingServer::new(self.clone()))
+                .serve_with_incoming(
+                    UnboundedReceiverStream::new(incoming).map(Ok::<_, std::io::Error>),
+                ),
+        );
+        Connector::new(move || {
+            let connections = connections.clone();
+            async move {
+                let (client, server) = tokio::io::duplex(64 * 1024);
+                connections
+                    .send(server)
+                    .map_err(|_| std::io::Error::other("mock server stopped"))?;
+                Ok(client)
+            }
+        })
+    }
+}
+
+fn data(block_number: u64) -> SubscribeUpdate {
+    SubscribeUpdate {
+        update: Some(subscribe_update::Update::Data(StreamResponse {
+            block_number,
+            timestamp: 1_700_000_000_000 + block_number,
+            data: json!([{ "coin": "BTC", "px": "97000", "sz": "0.1", "tid": block_number }])
+                .to_string(),
+        })),
+    }
+}
+
+fn pong() -> SubscribeUpdate {
+    SubscribeUpdate {
+        update: Some(subscribe_update::Update::Pong(Pong {
+            timestamp: 1_700_000_000_000,
+        })),
+    }
+}
+
+#[tonic::async_trait]
+impl Streaming for Mock {
+    type StreamDataStream = ReceiverStream<Result<SubscribeUpdate, Status>>;
+
+    async fn stream_data(
+        &self,
+        request: Request<tonic::Streaming<SubscribeRequest>>,
+    ) -> Result<Response<Self::StreamDataStream>, Status> {
+        let mut requests = request.into_inner();
+        let subscribe = loop {
+            match requests.message().await? {
+                Some(SubscribeRequest {
+                    request: Some(subscribe_request::Request::Subscribe(subscribe)),
+                }) => break subscribe,
+                Some(_) => continue,
+                None => return Err(Status::invalid_argument("no subscription sent")),
+            }
+        };
+        self.subscriptions.lock().push(subscribe.start_block);
+        let script = self.scripts.lock().pop_front().unwrap_or_default();
+
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            for step in script {
+                let sent = match step {
+                    Data(block_number) => tx.send(Ok(data(block_number))).await,
+                    SendPong => tx.send(Ok(pong())).await,
+                    Fail(code) => {
+                        // tonic drops messages still queued when the stream
+                        // yields an error, so let those be sent first
+                        while tx.capacity() < tx.max_capacity() {
+                            tokio::task::yield_now().await;
+                        }
+                        tx.send(Err(Status::new(code, "scripted failure"))).await
+                    }
+                };
+                if sent.is_err() {
+                    break;
+                }
+            }
+            // Keep reading so the client's pings don't fail the connection
+            drop(tx);
+            while let Ok(Some(_)) = requests.message().await {}
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PingResponse>, Status> {
+        Ok(Response::new(PingResponse {
+            count: request.into_inner().count,
+        }))
+    }
+}
+
+fn options(mock: &Mock, start_block: u64) -> StreamOptions {
+    let mut options = StreamOptions::new(ENDPOINT, "token");
+    options.channel.connector = Some(mock.serve());
+    options.start_block = Some(start_block);
+    options.breaker = None;
+    options
+}
+
+async fn stream_trades(options: &StreamOptions) -> hyperliquid_grpc::stream::StreamSummary {
+    stream_data_until(
+        StreamType::Trades,
+        HashMap::new(),
+        options,
+        CancellationToken::new(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn data_loss_resubscribes_from_the_last_block() {
+    let mock = Mock::new(vec![
+        vec![Data(100), Data(101), Fail(Code::DataLoss)],
+        vec![Data(101), Data(102), Data(103)],
+    ]);
+
+    let summary = stream_trades(&options(&mock, 100)).await;
+
+    assert_eq!(mock.subscriptions(), vec![100, 101]);
+    assert_eq!(summary.reconnects, 1);
+    assert_eq!(summary.last_block, Some(103));
+    assert!(!summary.cancelled);
+}
+
+#[tokio::test(start_paused = true)]
+async fn pongs_are_skipped_without_counting_as_data() {
+    let mock = Mock::new(vec![vec![SendPong, Data(100), SendPong, Data(101), SendPong]]);
+
+    let summary = stream_trades(&options(&mock, 100)).await;
+
+    assert_eq!(mock.subscriptions(), vec![100]);
+    assert_eq!(summary.messages, 2);
+    assert_eq!(summary.reconnects, 0);
+    assert_eq!(summary.last_block, Some(101));
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_message_limit_stops_the_stream() {
+    let mock = Mock::new(vec![(100..110).map(Data).collect()]);
+    let mut options = options(&mock, 100);
+    options.sample = Some(3);
+
+    let summary = stream_trades(&options).await;
+
+    assert_eq!(summary.messages, 3);
+    assert_eq!(summary.last_block, Some(102));
+    assert_eq!(summary.reconnects, 0);
+    assert!(!summary.cancelled);
+}
+
+/// A raw subscription reopened by [`with_reconnect`], resuming from the
+/// last block it saw.
+struct Blocks {
+    options: StreamOptions,
+    seen: Vec<u64>,
+    limit: usize,
+    /// Request side of the open connection, kept so it isn't closed
+    _requests: Option<mpsc::Sender<SubscribeRequest>>,
+}
+
+#[async_trait]
+impl Resubscribe for Blocks {
+    type Message = SubscribeUpdate;
+    type Stream = tonic::Streaming<SubscribeUpdate>;
+
+    async fn open(&mut self) -> Result<Self::Stream> {
+        let channel = build_channel(&self.options.endpoint, &self.options.channel).await?;
+        let mut client = streaming_client(channel, &self.options.channel);
+        let (tx, rx) = mpsc::channel(4);
+        tx.send(SubscribeRequest {
+            request: Some(subscribe_request::Request::Subscribe(StreamSubscribe {
+                stream_type: StreamType::Trades as i32,
+                start_block: self.seen.last().copied().unwrap_or(100),
+                ..Default::default()
+            })),
+        })
+        .await?;
+        self._requests = Some(tx);
+        let request = authed_request(
+            ReceiverStream::new(rx),
+            &self.options.token,
+            self.options.channel.auth,
+        )?;
+        Ok(client.stream_data(request).await?.into_inner())
+    }
+
+    async fn on_message(&mut self, message: SubscribeUpdate, _index: usize) -> Result<Flow> {
+        if let Received::Data(data) = Received::from_update(message) {
+            self.seen.push(data.block_number);
+        }
+        Ok(match self.seen.len() >= self.limit {
+            true => Flow::Stop,
+            false => Flow::Continue,
+        })
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn with_reconnect_resumes_after_data_loss_and_stops_at_the_limit() {
+    let mock = Mock::new(vec![
+        vec![Data(100), SendPong, Data(101), Fail(Code::DataLoss)],
+        vec![Data(101), SendPong, Data(102), Data(103), Data(104)],
+    ]);
+    let mut blocks = Blocks {
+        options: options(&mock, 100),
+        seen: Vec::new(),
+        limit: 5,
+        _requests: None,
+    };
+
+    with_reconnect(&mut blocks, DEFAULT_READ_TIMEOUT, None, None)
+        .await
+        .unwrap();
+
+    assert_eq!(mock.subscriptions(), vec![100, 101]);
+    assert_eq!(blocks.seen, vec![100, 101, 101, 102, 103]);
+}